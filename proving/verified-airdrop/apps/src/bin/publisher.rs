@@ -19,11 +19,16 @@
 use alloy_primitives::Address;
 use alloy_primitives::FixedBytes;
 use alloy_sol_types::{sol, SolCall};
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use apps::TxSender;
 use clap::Parser;
 use risc0_ethereum_contracts::groth16::encode;
-use risc0_steel::{config::ETH_SEPOLIA_CHAIN_SPEC, ethereum::EthEvmEnv, Contract, EvmBlockHeader};
+use risc0_steel::{
+    config::ETH_SEPOLIA_CHAIN_SPEC,
+    ethereum::EthEvmEnv,
+    host::{provider::EthersProvider, BlockSelector, EthersClient},
+    Contract, EvmBlockHeader,
+};
 use risc0_zkvm::{default_prover, ExecutorEnv, ProverOpts, VerifierContext};
 use tracing_subscriber::EnvFilter;
 use verified_airdrop::VERIFIED_AIRDROP_ELF;
@@ -72,9 +77,9 @@ fn main() -> Result<()> {
 
     println!("args: {:?}", args);
 
-    // Create an EVM environment from an RPC endpoint and a block number. If no block number is
-    // provided, the latest block is used.
-    let mut env = EthEvmEnv::from_rpc(&args.rpc_url, None)?;
+    // Create an EVM environment from an RPC endpoint and a block number or tag. The safe block is
+    // unlikely to be reorged before the proof is submitted.
+    let mut env = EthEvmEnv::from_rpc(&args.rpc_url, BlockSelector::Safe)?;
     //  The `with_chain_spec` method is used to specify the chain configuration.
     env = env.with_chain_spec(&ETH_SEPOLIA_CHAIN_SPEC);
 
@@ -115,6 +120,7 @@ fn main() -> Result<()> {
     ];
 
     println!("proving...");
+    let commitment = env.block_commitment();
     let view_call_input = env.into_input()?;
     let env = ExecutorEnv::builder()
         .write(&view_call_input)?
@@ -157,6 +163,16 @@ fn main() -> Result<()> {
     }
     .abi_encode();
 
+    // Proving takes time, so make sure that the committed block can still be validated on-chain.
+    let provider = EthersProvider::new(EthersClient::new_client(&args.rpc_url, 3, 500)?);
+    let status = provider.check_commitment(&commitment)?;
+    ensure!(
+        status.is_valid(),
+        "the proof for block {} is stale: {:?}",
+        commitment.blockNumber,
+        status
+    );
+
     // Send the calldata to Ethereum.
     println!("sending tx...");
     let runtime = tokio::runtime::Runtime::new()?;
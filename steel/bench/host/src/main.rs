@@ -0,0 +1,112 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares the cycle count of the MPT hashing of the current version of Steel with the baseline
+//! of Steel 0.11.1, using the recorded Sepolia RPC responses of the Steel tests.
+
+use alloy_primitives::{address, Address};
+use alloy_sol_types::{sol, SolCall};
+use anyhow::{Context, Result};
+use risc0_steel::{
+    config::ETH_SEPOLIA_CHAIN_SPEC,
+    ethereum::{EthEvmEnv, EthEvmInput},
+    host::provider::EthFileProvider,
+    Contract,
+};
+use risc0_zkvm::{default_executor, ExecutorEnv};
+use steel_bench_methods::MPT_HASH_ELF;
+use tracing_subscriber::EnvFilter;
+
+/// The RPC cache file used by the Steel tests.
+const RPC_CACHE_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../testdata/rpc_cache.json");
+
+const VIEW_CALL_TEST_CONTRACT: Address = address!("C5096d96dbC7594B3d0Ba50e708ba654A7ae1F3E");
+const VIEW_CALL_TEST_BLOCK: u64 = 5702743;
+
+sol! {
+    interface ViewCallTest {
+        function testPrecompile() external view returns (bytes32);
+        function testNonexistentAccount() external view returns (uint256 size);
+        function testBlockhash() external view returns (bytes32);
+        function testMuliContractCalls() external view returns (uint256);
+    }
+}
+
+/// Runs the preflight of the given call and returns the resulting input.
+fn preflight<C: SolCall>(call: &C) -> Result<EthEvmInput> {
+    let provider = EthFileProvider::from_file(&RPC_CACHE_FILE.into())?;
+    let mut env = EthEvmEnv::from_provider(provider, VIEW_CALL_TEST_BLOCK)?
+        .with_chain_spec(&ETH_SEPOLIA_CHAIN_SPEC);
+    let mut contract = Contract::preflight(VIEW_CALL_TEST_CONTRACT, &mut env);
+    contract.call_builder(call).call()?;
+
+    env.into_input()
+}
+
+/// Executes the benchmark guest and returns the cycle counts committed to the journal.
+fn bench(input: &EthEvmInput) -> Result<(u64, u64)> {
+    let env = ExecutorEnv::builder()
+        .write(input)?
+        .write(input)?
+        .build()
+        .context("failed to build executor env")?;
+    let session_info = default_executor()
+        .execute(env, MPT_HASH_ELF)
+        .context("failed to run executor")?;
+
+    Ok(session_info.journal.decode()?)
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let inputs = [
+        (
+            ViewCallTest::testPrecompileCall::SIGNATURE,
+            preflight(&ViewCallTest::testPrecompileCall {})?,
+        ),
+        (
+            ViewCallTest::testNonexistentAccountCall::SIGNATURE,
+            preflight(&ViewCallTest::testNonexistentAccountCall {})?,
+        ),
+        (
+            ViewCallTest::testBlockhashCall::SIGNATURE,
+            preflight(&ViewCallTest::testBlockhashCall {})?,
+        ),
+        (
+            ViewCallTest::testMuliContractCallsCall::SIGNATURE,
+            preflight(&ViewCallTest::testMuliContractCallsCall {})?,
+        ),
+    ];
+
+    println!(
+        "{:<28} {:>6} {:>12} {:>12} {:>9}",
+        "call", "tries", "0.11.1", "current", "speedup"
+    );
+    for (name, input) in &inputs {
+        let (legacy, current) = bench(input)?;
+        println!(
+            "{:<28} {:>6} {:>12} {:>12} {:>8.2}x",
+            name,
+            1 + input.storage_tries.len(),
+            legacy,
+            current,
+            legacy as f64 / current as f64
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use risc0_steel::ethereum::EthEvmInput;
+use risc0_zkvm::guest::env;
+
+risc0_zkvm::guest::entry!(main);
+
+fn main() {
+    // Read the same input twice, once for each version of the library.
+    let legacy_input: risc0_steel_legacy::ethereum::EthEvmInput = env::read();
+    let input: EthEvmInput = env::read();
+
+    // Hash the state trie and all storage tries as it is done by `EvmInput::into_env`.
+    let start = env::cycle_count();
+    legacy_input.state_trie.hash_slow();
+    for trie in &legacy_input.storage_tries {
+        trie.hash_slow();
+    }
+    let legacy_cycles = env::cycle_count() - start;
+
+    let start = env::cycle_count();
+    input.state_trie.hash_slow();
+    for trie in &input.storage_tries {
+        trie.hash_slow();
+    }
+    let cycles = env::cycle_count() - start;
+
+    env::commit(&(legacy_cycles, cycles));
+}
@@ -0,0 +1,170 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed bindings for calling contracts with Steel.
+
+/// Generates typed Steel bindings for a Solidity interface.
+///
+/// The interface is passed to [sol!] as is, and all the generated types are available in a
+/// module with the name of the interface. In addition, the module contains a `steel` function
+/// returning a `Steel` wrapper, which has one method per Solidity function. These take the
+/// arguments of the function and return a [CallBuilder] for it, so that the call has the same
+/// shape in the preflight and in the guest:
+/// - **Host:** `IERC20::steel(address, &mut env).balanceOf(account).call()?`
+/// - **Guest:** `IERC20::steel(address, &env).balanceOf(account).call()`
+///
+/// The wrapper works with any [CallEnv], and the return value of the call is the typed
+/// `<function>Return` struct generated by [sol!].
+///
+/// **Note:** Each parameter of a function must be named, and overloaded functions are not
+/// supported.
+///
+/// ### Examples
+/// ```rust no_run
+/// # use risc0_steel::{ethereum::EthEvmEnv, host::BlockSelector, steel_bindings};
+/// # use alloy_primitives::address;
+/// # fn main() -> anyhow::Result<()> {
+/// steel_bindings! {
+///     interface IERC20 {
+///         function balanceOf(address account) external view returns (uint);
+///     }
+/// }
+///
+/// let contract_address = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+/// let account = address!("F977814e90dA44bFA03b6295A0616a897441aceC");
+///
+/// // Host:
+/// let rpc_url = "https://ethereum-rpc.publicnode.com";
+/// let mut env = EthEvmEnv::from_rpc(rpc_url, BlockSelector::Latest)?;
+/// IERC20::steel(contract_address, &mut env).balanceOf(account).call()?;
+///
+/// let evm_input = env.into_input()?;
+///
+/// // Guest:
+/// let evm_env = evm_input.into_env();
+/// let balance = IERC20::steel(contract_address, &evm_env).balanceOf(account).call()._0;
+///
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [sol!]: alloy_sol_types::sol
+/// [CallBuilder]: crate::CallBuilder
+/// [CallEnv]: crate::CallEnv
+#[macro_export]
+macro_rules! steel_bindings {
+    (
+        $(#![$inner:meta])*
+        $(#[$attr:meta])*
+        $vis:vis interface $name:ident { $($body:tt)* }
+    ) => {
+        #[allow(non_snake_case)]
+        #[doc = concat!("Types and Steel bindings of the `", stringify!($name), "` interface.")]
+        $vis mod $name {
+            mod __sol {
+                $crate::__private::alloy_sol_types::sol! {
+                    #![sol(alloy_sol_types = $crate::__private::alloy_sol_types)]
+                    $(#![$inner])*
+                    $(#[$attr])*
+                    interface $name { $($body)* }
+                }
+            }
+            pub use self::__sol::$name::*;
+
+            #[doc = concat!("Typed Steel bindings of a `", stringify!($name), "` contract.")]
+            #[derive(Debug, Clone, Copy)]
+            pub struct Steel<E> {
+                address: $crate::__private::Address,
+                env: E,
+            }
+
+            /// Returns the bindings for calling the contract at the given address in the
+            /// environment.
+            pub fn steel<E: $crate::CallEnv>(
+                address: $crate::__private::Address,
+                env: E,
+            ) -> Steel<E> {
+                Steel { address, env }
+            }
+
+            $crate::steel_bindings!(@items $($body)*);
+        }
+    };
+
+    // Generates the methods item by item. The methods are expanded by separate invocations, so
+    // the recursion depth grows with the number of items, not with the number of tokens.
+    (@items) => {};
+    (@items
+        $(#[$fattr:meta])*
+        function $fname:ident ($($params:tt)*) $($modifier:ident)* ($($returns:tt)*);
+        $($rest:tt)*
+    ) => {
+        $crate::steel_bindings!(@params { [$(#[$fattr])*] $fname } [] $($params)*);
+        $crate::steel_bindings!(@items $($rest)*);
+    };
+    (@items $(#[$fattr:meta])* function $fname:ident ($($params:tt)*) $($rest:tt)*) => {
+        $crate::steel_bindings!(@params { [$(#[$fattr])*] $fname } [] $($params)*);
+        $crate::steel_bindings!(@skip $($rest)*);
+    };
+    (@items $(#[$attr:meta])* struct $sname:ident { $($fields:tt)* } $($rest:tt)*) => {
+        $crate::steel_bindings!(@items $($rest)*);
+    };
+    (@items $(#[$attr:meta])* enum $ename:ident { $($variants:tt)* } $($rest:tt)*) => {
+        $crate::steel_bindings!(@items $($rest)*);
+    };
+    (@items $($rest:tt)*) => {
+        $crate::steel_bindings!(@skip $($rest)*);
+    };
+
+    // Skips the remaining tokens of an item up to its terminating semicolon.
+    (@skip ; $($rest:tt)*) => {
+        $crate::steel_bindings!(@items $($rest)*);
+    };
+    (@skip $t:tt $($rest:tt)*) => {
+        $crate::steel_bindings!(@skip $($rest)*);
+    };
+
+    // The name of a parameter is the identifier preceding a comma or the end of the list.
+    (@params { [$($fattr:tt)*] $fname:ident } [$($pname:ident)*]) => {
+        $crate::steel_bindings!(@method [$($fattr)*] $fname [$($pname)*]);
+    };
+    (@params $ctx:tt [$($pname:ident)*] $name:ident , $($params:tt)*) => {
+        $crate::steel_bindings!(@params $ctx [$($pname)* $name] $($params)*);
+    };
+    (@params $ctx:tt [$($pname:ident)*] $name:ident) => {
+        $crate::steel_bindings!(@params $ctx [$($pname)* $name]);
+    };
+    (@params $ctx:tt [$($pname:ident)*] $t:tt $($params:tt)*) => {
+        $crate::steel_bindings!(@params $ctx [$($pname)*] $($params)*);
+    };
+
+    // Generates the method of a single function.
+    (@method [$($fattr:tt)*] $fname:ident [$($pname:ident)*]) => {
+        $crate::__private::paste! {
+            impl<__E: $crate::CallEnv> Steel<__E> {
+                $($fattr)*
+                pub fn $fname<$([<$pname:camel>]),*>(
+                    self,
+                    $($pname: [<$pname:camel>]),*
+                ) -> $crate::CallBuilder<[<$fname Call>], __E>
+                where
+                    [<$fname Call>]: ::core::convert::From<($([<$pname:camel>],)*)>,
+                {
+                    let call = [<$fname Call>]::from(($($pname,)*));
+                    $crate::CallEnv::call_builder(self.env, self.address, &call)
+                }
+            }
+        }
+    };
+}
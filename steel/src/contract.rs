@@ -13,23 +13,38 @@
 // limitations under the License.
 
 #[cfg(feature = "host")]
-use crate::host::{provider::Provider, HostEvmEnv};
-use crate::{EvmBlockHeader, GuestEvmEnv, MerkleTrie, StateDb};
-use alloy_primitives::{keccak256, Address, Sealed, B256, U256};
+use crate::{
+    host::{
+        db::{AsyncProofDb, TraceDb},
+        provider::{AsyncProvider, Provider, TraceCall, TraceProvider},
+        HostEvmEnv, ReplayCall,
+    },
+    EvmEnv,
+};
+use crate::{EvmBlockHeader, GuestEvmEnv, StateDb, Trie};
+use alloy_primitives::{keccak256, Address, Bytes, Sealed, B256, U256};
+use alloy_rlp::Decodable;
 use alloy_sol_types::{SolCall, SolType};
+#[cfg(feature = "host")]
+use anyhow::Context;
+#[cfg(feature = "host")]
+use revm::primitives::BlockEnv;
 use revm::{
     primitives::{
         AccountInfo, Bytecode, CfgEnvWithHandlerCfg, ExecutionResult, HashMap, ResultAndState,
-        SuccessReason, TransactTo,
+        SuccessReason, TransactTo, TxEnv,
     },
     Database, Evm,
 };
-use std::{convert::Infallible, fmt::Debug, marker::PhantomData, mem, rc::Rc};
+#[cfg(feature = "host")]
+use std::panic;
+use std::{convert::Infallible, fmt, fmt::Debug, marker::PhantomData, mem, sync::Arc};
 
 /// Represents a contract that is initialized with a specific environment and contract address.
 ///
 /// **Note:** This contract is not type-safe. Ensure that the deployed contract at the specified
-/// address matches the ABI used for making calls.
+/// address matches the ABI used for making calls. For typed calls, generate bindings with
+/// [steel_bindings].
 ///
 /// ### Usage
 /// - **Preflight calls on the Host:** To prepare calls on the host environment and build the
@@ -40,7 +55,7 @@ use std::{convert::Infallible, fmt::Debug, marker::PhantomData, mem, rc::Rc};
 ///
 /// ### Examples
 /// ```rust no_run
-/// # use risc0_steel::{ethereum::EthEvmEnv, Contract};
+/// # use risc0_steel::{ethereum::EthEvmEnv, host::BlockSelector, Contract};
 /// # use alloy_primitives::{address};
 /// # use alloy_sol_types::sol;
 ///
@@ -57,7 +72,8 @@ use std::{convert::Infallible, fmt::Debug, marker::PhantomData, mem, rc::Rc};
 /// };
 ///
 /// // Host:
-/// let mut env = EthEvmEnv::from_rpc("https://ethereum-rpc.publicnode.com", None)?;
+/// let rpc_url = "https://ethereum-rpc.publicnode.com";
+/// let mut env = EthEvmEnv::from_rpc(rpc_url, BlockSelector::Latest)?;
 /// let mut contract = Contract::preflight(contract_address, &mut env);
 /// contract.call_builder(&get_balance).call()?;
 ///
@@ -75,47 +91,82 @@ use std::{convert::Infallible, fmt::Debug, marker::PhantomData, mem, rc::Rc};
 /// [EvmInput::into_env]: crate::EvmInput::into_env
 /// [EvmEnv::new]: crate::EvmEnv::new
 /// [EthEvmEnv::from_rpc]: crate::ethereum::EthEvmEnv::from_rpc
+/// [steel_bindings]: crate::steel_bindings
 pub struct Contract<E> {
     address: Address,
     env: E,
 }
 
-impl<'a, H> Contract<&'a GuestEvmEnv<H>> {
+impl<'a, H, T> Contract<&'a GuestEvmEnv<H, T>> {
     /// Constructor for executing calls to an Ethereum contract in the guest.
-    pub fn new(address: Address, env: &'a GuestEvmEnv<H>) -> Self {
+    pub fn new(address: Address, env: &'a GuestEvmEnv<H, T>) -> Self {
         Self { address, env }
     }
 
     /// Initializes a call builder to execute a call on the contract.
-    pub fn call_builder<C: SolCall>(&self, call: &C) -> CallBuilder<C, &GuestEvmEnv<H>> {
+    pub fn call_builder<C: SolCall>(&self, call: &C) -> CallBuilder<C, &GuestEvmEnv<H, T>> {
         CallBuilder::new(self.env, self.address, call)
     }
 }
 
 #[cfg(feature = "host")]
-impl<'a, P, H> Contract<&'a mut HostEvmEnv<P, H>>
-where
-    P: Provider,
-{
+impl<'a, D, H> Contract<&'a mut EvmEnv<D, H>> {
     /// Constructor for preflighting calls to an Ethereum contract on the host.
     ///
     /// Initializes the environment for calling functions on the Ethereum contract, fetching
-    /// necessary data via the [Provider], and generating a storage proof for any accessed
-    /// elements using [EvmEnv::into_input].
+    /// necessary data via the [Provider] or [AsyncProvider], and generating a storage proof for
+    /// any accessed elements using [EvmEnv::into_input].
     ///
     /// [Provider]: crate::host::provider::Provider
+    /// [AsyncProvider]: crate::host::provider::AsyncProvider
     /// [EvmEnv::into_input]: crate::EvmEnv::into_input
     /// [EvmEnv]: crate::EvmEnv
-    pub fn preflight(address: Address, env: &'a mut HostEvmEnv<P, H>) -> Self {
+    pub fn preflight(address: Address, env: &'a mut EvmEnv<D, H>) -> Self {
         Self { address, env }
     }
 
     /// Initializes a call builder to execute a call on the contract.
-    pub fn call_builder<C: SolCall>(&mut self, call: &C) -> CallBuilder<C, &mut HostEvmEnv<P, H>> {
+    pub fn call_builder<C: SolCall>(&mut self, call: &C) -> CallBuilder<C, &mut EvmEnv<D, H>> {
         CallBuilder::new(self.env, self.address, call)
     }
 }
 
+/// An environment in which calls to a contract can be built.
+///
+/// It is implemented for references to the guest environment and for mutable references to the
+/// host environment, so that code building calls, like the bindings generated with
+/// [steel_bindings], is the same for the preflight and for the guest.
+///
+/// [steel_bindings]: crate::steel_bindings
+pub trait CallEnv: Sized {
+    /// Initializes a call builder to execute a call on the contract at the given address.
+    fn call_builder<C: SolCall>(self, address: Address, call: &C) -> CallBuilder<C, Self>;
+}
+
+impl<'a, H, T> CallEnv for &'a GuestEvmEnv<H, T> {
+    fn call_builder<C: SolCall>(self, address: Address, call: &C) -> CallBuilder<C, Self> {
+        CallBuilder::new(self, address, call)
+    }
+}
+
+#[cfg(feature = "host")]
+impl<'a, D, H> CallEnv for &'a mut EvmEnv<D, H> {
+    fn call_builder<C: SolCall>(self, address: Address, call: &C) -> CallBuilder<C, Self> {
+        CallBuilder::new(self, address, call)
+    }
+}
+
+#[cfg(feature = "host")]
+impl<D, H> EvmEnv<D, H> {
+    /// Records a preflighted call, so that it can be replayed with [EvmInput::replay].
+    ///
+    /// [EvmInput::replay]: crate::EvmInput::replay
+    fn record_call<C: SolCall>(&mut self, tx: &CallTxData<C>, output: &Result<Bytes, String>) {
+        let call = tx.replay_call(&self.cfg_env, output);
+        self.preflight_calls.push(call);
+    }
+}
+
 /// A builder for calling an Ethereum contract.
 ///
 /// Once configured, call with [CallBuilder::call].
@@ -190,14 +241,109 @@ where
         );
 
         let evm = new_evm(&mut self.env.db, self.env.cfg_env.clone(), &self.env.header);
-        self.tx.transact(evm).map_err(|err| anyhow::anyhow!(err))
+        let output = self.tx.execute(evm);
+        self.env.record_call(&self.tx, &output);
+        output
+            .and_then(|output| CallTxData::<C>::decode(&output))
+            .map_err(|err| anyhow::anyhow!(err))
     }
 }
 
-impl<'a, C, H> CallBuilder<C, &'a GuestEvmEnv<H>>
+#[cfg(feature = "host")]
+impl<'a, C, P, H> CallBuilder<C, &'a mut EvmEnv<AsyncProofDb<P>, H>>
+where
+    C: SolCall + Send + 'static,
+    C::Return: Send,
+    P: AsyncProvider + 'static,
+    H: EvmBlockHeader,
+{
+    /// Executes the call with a [EvmEnv] constructed with [Contract::preflight].
+    ///
+    /// As the EVM is synchronous, it is executed on a blocking thread of the current tokio
+    /// runtime, so that the requests of the [AsyncProvider] do not block the async executor.
+    ///
+    /// The call is cancellation-safe: If the future is dropped before it completes, the call still
+    /// runs to completion in the background and all subsequent operations on the environment wait
+    /// for it. Its accessed state is part of the input, but it cannot be replayed.
+    ///
+    /// [EvmEnv]: crate::EvmEnv
+    /// [AsyncProvider]: crate::host::provider::AsyncProvider
+    pub async fn call(self) -> anyhow::Result<C::Return> {
+        log::info!(
+            "Executing preflight for '{}' on contract {}",
+            C::SIGNATURE,
+            self.tx.to
+        );
+
+        let cfg = self.env.cfg_env.clone();
+        let mut blk_env = BlockEnv::default();
+        self.env.header.fill_block_env(&mut blk_env);
+
+        // the blocking task keeps the database locked until the call completes, even when this
+        // future is dropped before
+        let db = self.env.db.shared();
+        let tx = self.tx.tx_env();
+        let handle = tokio::task::spawn_blocking(move || {
+            let mut db = db.blocking_lock();
+            let evm = Evm::builder()
+                .with_db(&mut *db)
+                .with_cfg_env_with_handler_cfg(cfg)
+                .modify_block_env(|env| *env = blk_env)
+                .build();
+            execute(evm, tx, C::SIGNATURE)
+        });
+        let output = match handle.await {
+            Ok(output) => output,
+            Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+            Err(err) => return Err(err).context("preflight was cancelled"),
+        };
+        self.env.record_call(&self.tx, &output);
+
+        output
+            .and_then(|output| CallTxData::<C>::decode(&output))
+            .map_err(|err| anyhow::anyhow!(err))
+    }
+}
+
+#[cfg(feature = "host")]
+impl<'a, C, P, H> CallBuilder<C, &'a mut EvmEnv<TraceDb<P>, H>>
 where
     C: SolCall,
+    P: TraceProvider,
     H: EvmBlockHeader,
+{
+    /// Executes the call with a [EvmEnv] constructed with [Contract::preflight].
+    ///
+    /// The call is first traced by the [TraceProvider] and then executed against the returned
+    /// prestate.
+    ///
+    /// [EvmEnv]: crate::EvmEnv
+    /// [TraceProvider]: crate::host::provider::TraceProvider
+    pub fn call(self) -> anyhow::Result<C::Return> {
+        log::info!(
+            "Executing preflight for '{}' on contract {}",
+            C::SIGNATURE,
+            self.tx.to
+        );
+
+        self.env
+            .db
+            .trace(&self.tx.trace_call())
+            .context("failed to trace call")?;
+        let evm = new_evm(&mut self.env.db, self.env.cfg_env.clone(), &self.env.header);
+        let output = self.tx.execute(evm);
+        self.env.record_call(&self.tx, &output);
+        output
+            .and_then(|output| CallTxData::<C>::decode(&output))
+            .map_err(|err| anyhow::anyhow!(err))
+    }
+}
+
+impl<'a, C, H, T> CallBuilder<C, &'a GuestEvmEnv<H, T>>
+where
+    C: SolCall,
+    H: EvmBlockHeader,
+    T: Trie,
 {
     /// Executes the call with a [EvmEnv] constructed with [Contract::new].
     ///
@@ -208,7 +354,10 @@ where
             self.env.cfg_env.clone(),
             &self.env.header,
         );
-        self.tx.transact(evm).unwrap()
+        self.tx
+            .execute(evm)
+            .and_then(|output| CallTxData::<C>::decode(&output))
+            .unwrap()
     }
 }
 
@@ -231,38 +380,64 @@ impl<C: SolCall> CallTxData<C> {
         "Function call must have a return value"
     );
 
-    /// Executes the call in the provided [Evm].
-    fn transact<DB>(self, mut evm: Evm<'_, (), DB>) -> Result<C::Return, String>
+    /// Returns the call to be traced by a [TraceProvider].
+    #[cfg(feature = "host")]
+    fn trace_call(&self) -> TraceCall {
+        TraceCall {
+            from: self.caller,
+            to: self.to,
+            gas: self.gas_limit,
+            gas_price: self.gas_price,
+            value: self.value,
+            data: self.data.clone().into(),
+        }
+    }
+
+    /// Returns the transaction environment of the call.
+    fn tx_env(&self) -> TxEnv {
+        TxEnv {
+            caller: self.caller,
+            gas_limit: self.gas_limit,
+            gas_price: self.gas_price,
+            transact_to: TransactTo::call(self.to),
+            value: self.value,
+            data: self.data.clone().into(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the call to be replayed by [EvmInput::replay].
+    ///
+    /// [EvmInput::replay]: crate::EvmInput::replay
+    #[cfg(feature = "host")]
+    fn replay_call(
+        &self,
+        cfg: &CfgEnvWithHandlerCfg,
+        output: &Result<Bytes, String>,
+    ) -> ReplayCall {
+        ReplayCall {
+            signature: C::SIGNATURE.to_string(),
+            cfg: cfg.clone(),
+            tx: self.tx_env(),
+            output: output.clone(),
+        }
+    }
+
+    /// Executes the call in the provided [Evm] and returns its output.
+    fn execute<DB>(&self, evm: Evm<'_, (), DB>) -> Result<Bytes, String>
     where
         DB: Database,
         <DB as Database>::Error: Debug,
     {
+        execute(evm, self.tx_env(), C::SIGNATURE)
+    }
+
+    /// Decodes the output of the call.
+    fn decode(output: &[u8]) -> Result<C::Return, String> {
         #[allow(clippy::let_unit_value)]
         let _ = Self::RETURNS;
 
-        let tx_env = evm.tx_mut();
-        tx_env.caller = self.caller;
-        tx_env.gas_limit = self.gas_limit;
-        tx_env.gas_price = self.gas_price;
-        tx_env.transact_to = TransactTo::call(self.to);
-        tx_env.value = self.value;
-        tx_env.data = self.data.into();
-
-        let ResultAndState { result, .. } = evm
-            .transact_preverified()
-            .map_err(|err| format!("Call '{}' failed: {:?}", C::SIGNATURE, err))?;
-        let ExecutionResult::Success { reason, output, .. } = result else {
-            return Err(format!("Call '{}' failed", C::SIGNATURE));
-        };
-        // there must be a return value to decode
-        if reason != SuccessReason::Return {
-            return Err(format!(
-                "Call '{}' did not return: {:?}",
-                C::SIGNATURE,
-                reason
-            ));
-        }
-        let returns = C::abi_decode_returns(&output.into_data(), true).map_err(|err| {
+        let returns = C::abi_decode_returns(output, true).map_err(|err| {
             format!(
                 "Call '{}' returned invalid type; expected '{}': {:?}",
                 C::SIGNATURE,
@@ -275,7 +450,37 @@ impl<C: SolCall> CallTxData<C> {
     }
 }
 
-fn new_evm<'a, DB, H>(db: DB, cfg: CfgEnvWithHandlerCfg, header: &Sealed<H>) -> Evm<'a, (), DB>
+/// Executes the transaction in the provided [Evm] and returns the data it returned.
+pub(crate) fn execute<DB>(
+    mut evm: Evm<'_, (), DB>,
+    tx: TxEnv,
+    signature: &str,
+) -> Result<Bytes, String>
+where
+    DB: Database,
+    <DB as Database>::Error: Debug,
+{
+    *evm.tx_mut() = tx;
+
+    let ResultAndState { result, .. } = evm
+        .transact_preverified()
+        .map_err(|err| format!("Call '{}' failed: {:?}", signature, err))?;
+    let ExecutionResult::Success { reason, output, .. } = result else {
+        return Err(format!("Call '{}' failed", signature));
+    };
+    // there must be a return value to decode
+    if reason != SuccessReason::Return {
+        return Err(format!("Call '{}' did not return: {:?}", signature, reason));
+    }
+
+    Ok(output.into_data())
+}
+
+pub(crate) fn new_evm<'a, DB, H>(
+    db: DB,
+    cfg: CfgEnvWithHandlerCfg,
+    header: &Sealed<H>,
+) -> Evm<'a, (), DB>
 where
     DB: Database,
     H: EvmBlockHeader,
@@ -287,29 +492,93 @@ where
         .build()
 }
 
-struct WrapStateDb<'a> {
-    inner: &'a StateDb,
-    account_storage: HashMap<Address, Option<Rc<MerkleTrie>>>,
+/// State that is accessed by a call but not contained in the [EvmInput](crate::EvmInput).
+///
+/// In the guest, accessing such state results in a panic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MissingState {
+    /// The account is neither proven to exist nor to not exist in the state trie.
+    Account(Address),
+    /// The storage slot of the account is not contained in its storage trie.
+    Storage(Address, U256),
+    /// The bytecode with the given hash is not contained in the contracts.
+    Code(B256),
+    /// The hash of the block with the given number is not contained in the ancestors.
+    BlockHash(u64),
+}
+
+impl fmt::Display for MissingState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MissingState::Account(address) => write!(f, "account {address}"),
+            MissingState::Storage(address, index) => write!(f, "storage {address} at {index}"),
+            MissingState::Code(hash) => write!(f, "code {hash}"),
+            MissingState::BlockHash(number) => write!(f, "hash of block {number}"),
+        }
+    }
+}
+
+/// Handles state that is accessed by a call but not contained in the [StateDb].
+pub(crate) trait MissingStateHook {
+    /// The error that is returned to the EVM.
+    type Error;
+
+    /// Called for each access of the missing state.
+    fn missing(&mut self, state: MissingState) -> Self::Error;
+}
+
+/// The hook used in the guest, which panics as there is no way to obtain the missing state.
+pub(crate) struct PanicOnMissing;
+
+impl MissingStateHook for PanicOnMissing {
+    type Error = Infallible;
+
+    #[cold]
+    fn missing(&mut self, state: MissingState) -> Self::Error {
+        panic!("{} not found", state)
+    }
+}
+
+/// A [Database] backed by a [StateDb], which calls the hook for any missing state.
+pub(crate) struct WrapStateDb<'a, T, M = PanicOnMissing> {
+    inner: &'a StateDb<T>,
+    account_storage: HashMap<Address, Option<Arc<T>>>,
+    hook: M,
 }
 
-impl<'a> WrapStateDb<'a> {
+impl<'a, T> WrapStateDb<'a, T> {
     /// Creates a new [Database] from the given [StateDb].
-    pub(crate) fn new(inner: &'a StateDb) -> Self {
+    pub(crate) fn new(inner: &'a StateDb<T>) -> Self {
+        Self::with_hook(inner, PanicOnMissing)
+    }
+}
+
+impl<'a, T, M> WrapStateDb<'a, T, M> {
+    /// Creates a new [Database] from the given [StateDb] calling `hook` for any missing state.
+    pub(crate) fn with_hook(inner: &'a StateDb<T>, hook: M) -> Self {
         Self {
             inner,
             account_storage: HashMap::new(),
+            hook,
         }
     }
+
+    /// Consumes the database and returns its hook.
+    #[cfg(feature = "host")]
+    pub(crate) fn into_hook(self) -> M {
+        self.hook
+    }
 }
 
-impl Database for WrapStateDb<'_> {
-    /// The database does not return any errors.
-    type Error = Infallible;
+impl<T: Trie, M: MissingStateHook> Database for WrapStateDb<'_, T, M> {
+    type Error = M::Error;
 
     /// Get basic account information.
     #[inline]
     fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
-        let account = self.inner.account(address);
+        let Some(account) = self.inner.account(address) else {
+            return Err(self.hook.missing(MissingState::Account(address)));
+        };
         match account {
             Some(account) => {
                 // link storage trie to the account, if it exists
@@ -336,31 +605,35 @@ impl Database for WrapStateDb<'_> {
     /// Get account code by its hash.
     #[inline]
     fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
-        let code = self.inner.code_by_hash(code_hash);
-        Ok(Bytecode::new_raw(code.clone()))
+        match self.inner.code_by_hash(code_hash) {
+            Some(code) => Ok(Bytecode::new_raw(code.clone())),
+            None => Err(self.hook.missing(MissingState::Code(code_hash))),
+        }
     }
 
     /// Get storage value of address at index.
     #[inline]
     fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
-        let storage = self
-            .account_storage
-            .get(&address)
-            .unwrap_or_else(|| panic!("storage not found: {:?}", address));
-        match storage {
-            Some(storage) => {
-                let val = storage
-                    .get_rlp(keccak256(index.to_be_bytes::<32>()))
-                    .expect("invalid storage value");
-                Ok(val.unwrap_or_default())
-            }
-            None => Ok(U256::ZERO),
+        let value = match self.account_storage.get(&address) {
+            Some(Some(storage)) => storage.try_get(keccak256(index.to_be_bytes::<32>())),
+            Some(None) => Some(None),
+            None => None,
+        };
+        match value {
+            Some(Some(mut rlp)) => Ok(U256::decode(&mut rlp).expect("invalid storage value")),
+            Some(None) => Ok(U256::ZERO),
+            None => Err(self.hook.missing(MissingState::Storage(address, index))),
         }
     }
 
     /// Get block hash by block number.
     #[inline]
     fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
-        Ok(self.inner.block_hash(number))
+        // block number is never bigger then u64::MAX
+        let number: u64 = number.to();
+        match self.inner.block_hash(number) {
+            Some(hash) => Ok(hash),
+            None => Err(self.hook.missing(MissingState::BlockHash(number))),
+        }
     }
 }
@@ -0,0 +1,292 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{config::ChainSpec, EvmBlockHeader, EvmInput, GuestEvmEnv};
+use alloy_primitives::ChainId;
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Visitor},
+    ser::SerializeTuple,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{fmt, marker::PhantomData};
+use thiserror::Error as ThisError;
+
+/// Magic number at the start of every serialized [VersionedEvmInput], i.e. "STEL" in ASCII.
+pub const INPUT_MAGIC: u32 = u32::from_be_bytes(*b"STEL");
+
+/// The current version of the serialized [EvmInput] format.
+///
+/// It must be incremented whenever the serialization of [EvmInput] changes in an incompatible way
+/// and a migration from the previous version must be added to [VersionedEvmInput::deserialize_payload].
+/// Unversioned inputs of previous releases use the format of version 1.
+pub const INPUT_VERSION: u16 = 1;
+
+/// The error type that is returned when the envelope of a [VersionedEvmInput] is invalid.
+#[derive(Debug, Clone, PartialEq, Eq, ThisError)]
+pub enum EnvelopeError {
+    /// The input does not start with [INPUT_MAGIC].
+    #[error("invalid magic number {0:#010x}: the input was not created by a compatible risc0-steel host")]
+    InvalidMagic(u32),
+    /// The version of the input is not supported by this library.
+    #[error("unsupported input version {found}: this library supports versions 1 to {supported}, the host and guest must use compatible versions of risc0-steel")]
+    UnsupportedVersion { found: u16, supported: u16 },
+    /// The input was created for a different block header type.
+    #[error("header type mismatch: the input contains headers of type {found}, but {expected} was expected")]
+    HeaderTypeMismatch { expected: u16, found: u16 },
+    /// The input was created for a different chain.
+    #[error("chain ID mismatch: the input was created for chain {found}, but chain {expected} was expected")]
+    ChainIdMismatch { expected: ChainId, found: ChainId },
+}
+
+/// An [EvmInput] wrapped in an envelope to detect incompatibilities between host and guest.
+///
+/// The input is serialized as a tuple of [INPUT_MAGIC], [INPUT_VERSION], the
+/// [EvmBlockHeader::HEADER_TYPE_ID], the chain ID and the actual [EvmInput]. When deserializing,
+/// the envelope is checked before the payload is read, so that mismatching inputs fail with an
+/// [EnvelopeError] instead of a cryptic deserialization error. Inputs of older versions are
+/// migrated to the current format.
+///
+/// ### Usage
+/// ```rust ignore
+/// // Host:
+/// let input = env.into_versioned_input()?;
+/// let env = ExecutorEnv::builder().write(&input)?.build()?;
+///
+/// // Guest:
+/// let input: VersionedEvmInput<EthBlockHeader> = env::read();
+/// let evm_env = input.into_env(&ETH_SEPOLIA_CHAIN_SPEC);
+/// ```
+#[derive(Debug)]
+pub struct VersionedEvmInput<H> {
+    chain_id: ChainId,
+    input: EvmInput<H>,
+}
+
+impl<H> VersionedEvmInput<H> {
+    /// Wraps the given input for the chain with the given ID.
+    pub fn new(input: EvmInput<H>, chain_id: ChainId) -> Self {
+        Self { chain_id, input }
+    }
+
+    /// Deserializes a bare [EvmInput] in the format of the given version and wraps it for the
+    /// chain with the given ID.
+    ///
+    /// This is the single point where the payloads of older versions are migrated to the current
+    /// format, and it can be used to read unversioned inputs of previous releases with version 1.
+    pub fn deserialize_payload<'de, D: Deserializer<'de>>(
+        version: u16,
+        chain_id: ChainId,
+        deserializer: D,
+    ) -> Result<Self, D::Error>
+    where
+        H: DeserializeOwned,
+    {
+        let input = PayloadSeed::<H>::new(version)
+            .map_err(de::Error::custom)?
+            .deserialize(deserializer)?;
+        Ok(Self { chain_id, input })
+    }
+
+    /// Returns the ID of the chain the input was created for.
+    pub fn chain_id(&self) -> ChainId {
+        self.chain_id
+    }
+
+    /// Checks that the input was created for the chain with the given ID.
+    pub fn check_chain_id(&self, chain_id: ChainId) -> Result<(), EnvelopeError> {
+        if self.chain_id != chain_id {
+            return Err(EnvelopeError::ChainIdMismatch {
+                expected: chain_id,
+                found: self.chain_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the wrapped input.
+    pub fn into_inner(self) -> EvmInput<H> {
+        self.input
+    }
+}
+
+impl<H: EvmBlockHeader> VersionedEvmInput<H> {
+    /// Converts the input into a [EvmEnv] for execution with the given chain spec.
+    ///
+    /// This method verifies that the input was created for the chain of the chain spec and that
+    /// the state matches the state root in the header and panics if not.
+    ///
+    /// [EvmEnv]: crate::EvmEnv
+    pub fn into_env(self, chain_spec: &ChainSpec) -> GuestEvmEnv<H> {
+        match self.try_into_env(chain_spec) {
+            Ok(env) => env,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Converts the input into a [EvmEnv] for execution with the given chain spec.
+    ///
+    /// Unlike [VersionedEvmInput::into_env], it returns an error if the input was created for a
+    /// different chain. It still panics if the state does not match the state root in the header.
+    ///
+    /// [EvmEnv]: crate::EvmEnv
+    pub fn try_into_env(self, chain_spec: &ChainSpec) -> Result<GuestEvmEnv<H>, EnvelopeError> {
+        self.check_chain_id(chain_spec.chain_id())?;
+        Ok(self.input.into_env().with_chain_spec(chain_spec))
+    }
+}
+
+impl<H: EvmBlockHeader + Serialize> Serialize for VersionedEvmInput<H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(5)?;
+        tuple.serialize_element(&INPUT_MAGIC)?;
+        tuple.serialize_element(&INPUT_VERSION)?;
+        tuple.serialize_element(&H::HEADER_TYPE_ID)?;
+        tuple.serialize_element(&self.chain_id)?;
+        tuple.serialize_element(&self.input)?;
+        tuple.end()
+    }
+}
+
+impl<'de, H: EvmBlockHeader + DeserializeOwned> Deserialize<'de> for VersionedEvmInput<H> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple(5, EnvelopeVisitor(PhantomData))
+    }
+}
+
+struct EnvelopeVisitor<H>(PhantomData<H>);
+
+impl<'de, H: EvmBlockHeader + DeserializeOwned> Visitor<'de> for EnvelopeVisitor<H> {
+    type Value = VersionedEvmInput<H>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a versioned EVM input")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let magic: u32 = next_element(&mut seq, 0, &self)?;
+        if magic != INPUT_MAGIC {
+            return Err(de::Error::custom(EnvelopeError::InvalidMagic(magic)));
+        }
+        let version: u16 = next_element(&mut seq, 1, &self)?;
+        let payload = PayloadSeed::<H>::new(version).map_err(de::Error::custom)?;
+        let header_type: u16 = next_element(&mut seq, 2, &self)?;
+        if header_type != H::HEADER_TYPE_ID {
+            return Err(de::Error::custom(EnvelopeError::HeaderTypeMismatch {
+                expected: H::HEADER_TYPE_ID,
+                found: header_type,
+            }));
+        }
+        let chain_id: ChainId = next_element(&mut seq, 3, &self)?;
+
+        let input = seq
+            .next_element_seed(payload)?
+            .ok_or_else(|| de::Error::invalid_length(4, &self))?;
+
+        Ok(VersionedEvmInput { chain_id, input })
+    }
+}
+
+/// Deserializes the [EvmInput] payload in the format of its version.
+struct PayloadSeed<H> {
+    version: u16,
+    phantom: PhantomData<H>,
+}
+
+impl<H> PayloadSeed<H> {
+    /// Returns the seed for the given version, if it is supported.
+    fn new(version: u16) -> Result<Self, EnvelopeError> {
+        if version == 0 || version > INPUT_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion {
+                found: version,
+                supported: INPUT_VERSION,
+            });
+        }
+        Ok(Self {
+            version,
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<'de, H: DeserializeOwned> DeserializeSeed<'de> for PayloadSeed<H> {
+    type Value = EvmInput<H>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        // deserialize the payload in the format of its version and migrate it to the current one
+        match self.version {
+            1 => EvmInput::deserialize(deserializer),
+            _ => unreachable!("unsupported versions are rejected by PayloadSeed::new"),
+        }
+    }
+}
+
+fn next_element<'de, T: Deserialize<'de>, A: SeqAccess<'de>>(
+    seq: &mut A,
+    index: usize,
+    expected: &dyn de::Expected,
+) -> Result<T, A::Error> {
+    seq.next_element()?
+        .ok_or_else(|| de::Error::invalid_length(index, expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethereum::EthBlockHeader;
+    use serde_json::json;
+
+    fn deserialize(value: serde_json::Value) -> String {
+        serde_json::from_value::<VersionedEvmInput<EthBlockHeader>>(value)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn invalid_envelope() {
+        let err = deserialize(json!([0x20, INPUT_VERSION, 1, 1, null]));
+        assert_eq!(err, EnvelopeError::InvalidMagic(0x20).to_string());
+
+        let err = deserialize(json!([INPUT_MAGIC, INPUT_VERSION + 1, 1, 1, null]));
+        let expected = EnvelopeError::UnsupportedVersion {
+            found: INPUT_VERSION + 1,
+            supported: INPUT_VERSION,
+        };
+        assert_eq!(err, expected.to_string());
+
+        let err = deserialize(json!([INPUT_MAGIC, INPUT_VERSION, 2, 1, null]));
+        let expected = EnvelopeError::HeaderTypeMismatch {
+            expected: 1,
+            found: 2,
+        };
+        assert_eq!(err, expected.to_string());
+
+        let err = deserialize(json!([INPUT_MAGIC, INPUT_VERSION, 1]));
+        assert!(err.contains("invalid length 3"), "{}", err);
+    }
+
+    #[test]
+    fn unsupported_payload_version() {
+        for version in [0, INPUT_VERSION + 1] {
+            let err =
+                VersionedEvmInput::<EthBlockHeader>::deserialize_payload(version, 1, json!(null))
+                    .unwrap_err()
+                    .to_string();
+            let expected = EnvelopeError::UnsupportedVersion {
+                found: version,
+                supported: INPUT_VERSION,
+            };
+            assert_eq!(err, expected.to_string());
+        }
+    }
+}
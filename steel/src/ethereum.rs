@@ -15,11 +15,11 @@
 //! Type aliases for Ethereum.
 use crate::EvmEnv;
 
-use super::{EvmBlockHeader, EvmInput};
+use super::{EvmBlockHeader, EvmInput, FlatEvmInput};
 use alloy_primitives::{
     keccak256, Address, BlockHash, BlockNumber, Bloom, Bytes, Sealable, B256, B64, U256,
 };
-use alloy_rlp_derive::RlpEncodable;
+use alloy_rlp_derive::{RlpDecodable, RlpEncodable};
 use revm::primitives::BlockEnv;
 use serde::{Deserialize, Serialize};
 
@@ -29,8 +29,11 @@ pub type EthEvmEnv<D> = EvmEnv<D, EthBlockHeader>;
 /// [EvmInput] for Ethereum.
 pub type EthEvmInput = EvmInput<EthBlockHeader>;
 
+/// [FlatEvmInput] for Ethereum.
+pub type EthFlatEvmInput<'a> = FlatEvmInput<'a, EthBlockHeader>;
+
 /// Ethereum post-merge block header.
-#[derive(Debug, Clone, Serialize, Deserialize, RlpEncodable)]
+#[derive(Debug, Clone, Serialize, Deserialize, RlpEncodable, RlpDecodable)]
 #[rlp(trailing)]
 pub struct EthBlockHeader {
     /// Hash of the parent block's header.
@@ -83,6 +86,8 @@ impl Sealable for EthBlockHeader {
 }
 
 impl EvmBlockHeader for EthBlockHeader {
+    const HEADER_TYPE_ID: u16 = 1;
+
     #[inline]
     fn parent_hash(&self) -> &B256 {
         &self.parent_hash
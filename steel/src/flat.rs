@@ -0,0 +1,220 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    mpt::ParseNodeError, new_guest_env, EvmBlockHeader, EvmInput, FlatMerkleTrie, GuestEvmEnv,
+};
+use alloy_primitives::{keccak256, Bytes, B256};
+use alloy_rlp::{Decodable, Encodable};
+use thiserror::Error as ThisError;
+
+/// The error type that is returned when decoding a [FlatEvmInput].
+#[derive(Debug, ThisError)]
+pub enum FlatDecodeError {
+    /// The input ended before all the data was read.
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    /// The input contains more data than expected.
+    #[error("unexpected trailing data")]
+    TrailingData,
+    /// Error that occurs when decoding a block header.
+    #[error("invalid header")]
+    Header(#[from] alloy_rlp::Error),
+    /// Error that occurs when decoding a trie.
+    #[error("invalid trie")]
+    Trie(#[from] ParseNodeError),
+}
+
+impl<H: EvmBlockHeader + Encodable> EvmInput<H> {
+    /// Returns the flat encoding of the input, which can be read with [FlatEvmInput::decode].
+    ///
+    /// Headers are RLP encoded and all the tries use the encoding of [MerkleTrie::to_flat]. Each
+    /// component is prefixed with its length as a little-endian `u32`.
+    ///
+    /// The encoding is canonical: Storage tries are ordered by their root and contracts by their
+    /// code hash, duplicates are removed. Thus, inputs containing the same data always have the
+    /// same encoding, independent of the order of their collections.
+    ///
+    /// [MerkleTrie::to_flat]: crate::MerkleTrie::to_flat
+    pub fn to_flat(&self) -> Vec<u8> {
+        let mut storage_tries: Vec<_> = self
+            .storage_tries
+            .iter()
+            .map(|trie| (trie.hash_slow(), trie.to_flat()))
+            .collect();
+        storage_tries.sort_unstable();
+        storage_tries.dedup();
+        let mut contracts: Vec<_> = self
+            .contracts
+            .iter()
+            .map(|code| (keccak256(code), code))
+            .collect();
+        contracts.sort_unstable();
+        contracts.dedup();
+
+        let mut out = Vec::new();
+        write_slice(&alloy_rlp::encode(&self.header), &mut out);
+        write_len(self.ancestors.len(), &mut out);
+        for ancestor in &self.ancestors {
+            write_slice(&alloy_rlp::encode(ancestor), &mut out);
+        }
+        write_slice(&self.state_trie.to_flat(), &mut out);
+        write_len(storage_tries.len(), &mut out);
+        for (_, storage_trie) in &storage_tries {
+            write_slice(storage_trie, &mut out);
+        }
+        write_len(contracts.len(), &mut out);
+        for (_, contract) in &contracts {
+            write_slice(contract, &mut out);
+        }
+
+        out
+    }
+
+    /// Returns the keccak hash of the canonical encoding of the input.
+    ///
+    /// Two inputs have the same digest if and only if they contain the same header, ancestors,
+    /// resolved trie nodes and contracts. It can therefore be used to identify an input, e.g. to
+    /// cache its proof.
+    #[inline]
+    pub fn digest(&self) -> B256 {
+        keccak256(self.to_flat())
+    }
+}
+
+/// The input to derive and validate an [EvmEnv] borrowed from its flat encoding.
+///
+/// In contrast to [EvmInput], the tries are not deserialized, but accessed directly in the buffer
+/// returned by [EvmInput::to_flat]. This avoids allocating every node of the tries, which makes up
+/// a significant part of the cycles for large inputs. The contracts are only copied out of the
+/// buffer by [FlatEvmInput::into_env], as the EVM requires owned bytecode.
+///
+/// ### Usage
+/// ```rust ignore
+/// // Host:
+/// let input = env.into_input()?;
+/// let flat = input.to_flat();
+/// let env = ExecutorEnv::builder()
+///     .write(&(flat.len() as u32))?
+///     .write_slice(&flat)
+///     .build()?;
+///
+/// // Guest:
+/// let len: u32 = env::read();
+/// let mut flat = vec![0u8; len as usize];
+/// env::read_slice(&mut flat);
+/// let input = EthFlatEvmInput::decode(&flat).unwrap();
+/// let evm_env = input.into_env();
+/// ```
+///
+/// [EvmEnv]: crate::EvmEnv
+#[derive(Debug)]
+pub struct FlatEvmInput<'a, H> {
+    pub header: H,
+    pub state_trie: FlatMerkleTrie<'a>,
+    pub storage_tries: Vec<FlatMerkleTrie<'a>>,
+    pub contracts: Vec<&'a [u8]>,
+    pub ancestors: Vec<H>,
+}
+
+impl<'a, H: EvmBlockHeader + Decodable> FlatEvmInput<'a, H> {
+    /// Decodes the input from its flat encoding as returned by [EvmInput::to_flat].
+    pub fn decode(data: &'a [u8]) -> Result<Self, FlatDecodeError> {
+        let mut reader = Reader(data);
+
+        let header = reader.header()?;
+        let ancestors = (0..reader.u32()?)
+            .map(|_| reader.header())
+            .collect::<Result<_, _>>()?;
+        let state_trie = FlatMerkleTrie::new(reader.slice()?)?;
+        let storage_tries = (0..reader.u32()?)
+            .map(|_| Ok(FlatMerkleTrie::new(reader.slice()?)?))
+            .collect::<Result<_, FlatDecodeError>>()?;
+        let contracts = (0..reader.u32()?)
+            .map(|_| reader.slice())
+            .collect::<Result<_, _>>()?;
+        if !reader.0.is_empty() {
+            return Err(FlatDecodeError::TrailingData);
+        }
+
+        Ok(Self {
+            header,
+            state_trie,
+            storage_tries,
+            contracts,
+            ancestors,
+        })
+    }
+}
+
+impl<'a, H: EvmBlockHeader> FlatEvmInput<'a, H> {
+    /// Converts the input into a [EvmEnv] for execution.
+    ///
+    /// This method verifies that the state matches the state root in the header and panics if not.
+    /// The tries keep borrowing the buffer, while each contract is copied into owned bytecode.
+    ///
+    /// [EvmEnv]: crate::EvmEnv
+    pub fn into_env(self) -> GuestEvmEnv<H, FlatMerkleTrie<'a>> {
+        new_guest_env(
+            self.header,
+            self.state_trie,
+            self.storage_tries,
+            self.contracts.into_iter().map(Bytes::copy_from_slice),
+            &self.ancestors,
+        )
+    }
+}
+
+fn write_len(len: usize, out: &mut Vec<u8>) {
+    let len = u32::try_from(len).expect("input too large");
+    out.extend_from_slice(&len.to_le_bytes());
+}
+
+fn write_slice(slice: &[u8], out: &mut Vec<u8>) {
+    write_len(slice.len(), out);
+    out.extend_from_slice(slice);
+}
+
+/// A simple cursor over the flat encoding.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], FlatDecodeError> {
+        if self.0.len() < len {
+            return Err(FlatDecodeError::UnexpectedEnd);
+        }
+        let (bytes, remaining) = self.0.split_at(len);
+        self.0 = remaining;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, FlatDecodeError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn slice(&mut self) -> Result<&'a [u8], FlatDecodeError> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    /// Decodes a header, which must consume its entire slice.
+    fn header<H: Decodable>(&mut self) -> Result<H, FlatDecodeError> {
+        let mut buf = self.slice()?;
+        let header = H::decode(&mut buf)?;
+        if !buf.is_empty() {
+            return Err(FlatDecodeError::TrailingData);
+        }
+        Ok(header)
+    }
+}
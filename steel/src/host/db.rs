@@ -12,14 +12,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::provider::Provider;
-use alloy_primitives::{Address, Bytes, Sealable, B256, U256};
+use super::{
+    provider::{
+        AsyncProvider, BlockingProvider, EIP1186Proof, PrestateAccount, Provider, TraceCall,
+        TraceProvider,
+    },
+    PreflightSnapshot,
+};
+use crate::{mpt::EMPTY_ROOT_HASH, EvmBlockHeader, MerkleTrie, StateAccount};
+use alloy::rpc::types::eth::{AccessList, AccessListItem};
+use alloy_primitives::{keccak256, Address, Bytes, Sealable, Sealed, StorageKey, B256, U256};
+use alloy_rlp::Decodable;
 use revm::{
-    primitives::{AccountInfo, Bytecode, HashMap, HashSet, KECCAK_EMPTY},
+    primitives::{hash_map::Entry, AccountInfo, Bytecode, HashMap, HashSet, KECCAK_EMPTY},
     Database,
 };
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
 use thiserror::Error;
+use tokio::{
+    runtime::Handle,
+    sync::{Mutex, MutexGuard},
+};
 
 /// Error type for the [ProviderDb].
 #[derive(Error, Debug)]
@@ -30,51 +43,219 @@ pub enum ProviderDbError<E: std::error::Error> {
     InvalidBlockNumber(U256),
     #[error("hash missing for block: {0}")]
     BlockHashMissing(U256),
+    #[error("invalid proof for account {0}")]
+    InvalidAccountProof(Address),
+    #[error("invalid proof for storage slot {1} of account {0}")]
+    InvalidStorageProof(Address, StorageKey),
+    #[error("code of account {0} does not match its code hash")]
+    InvalidCode(Address),
+    #[error("header of block {0} does not match the parent hash of its child")]
+    InvalidHeader(u64),
 }
 
 /// A revm [Database] backed by a [Provider].
+///
+/// By default, all responses of the provider are trusted. With
+/// [ProviderDb::enable_verification], every response is verified against the header as it
+/// arrives, so that a faulty provider is detected already during the preflight.
 pub struct ProviderDb<P> {
     provider: P,
     block_number: u64,
+    verifier: Option<Verifier>,
 
     /// Cache for code hashes to contract addresses.
     code_hashes: HashMap<B256, Address>,
 }
 
+/// The data needed to verify responses against a block header.
+struct Verifier {
+    state_root: B256,
+    /// Verified hashes of the block and its ancestors.
+    block_hashes: HashMap<u64, B256>,
+    /// Number and parent hash of the oldest verified block.
+    oldest: (u64, B256),
+}
+
 impl<P: Provider> ProviderDb<P> {
     /// Creates a new [ProviderDb] with the given provider and block number.
     pub fn new(provider: P, block_number: u64) -> Self {
         Self {
             provider,
             block_number,
+            verifier: None,
             code_hashes: HashMap::new(),
         }
     }
-}
 
-impl<P: Provider> Database for ProviderDb<P> {
-    type Error = ProviderDbError<P::Error>;
+    /// Enables the verification of all responses against the given header of the block.
+    ///
+    /// Account info and storage values are then only accepted with a valid EIP-1186 proof for the
+    /// header's state root, contracts must match their code hash, and block hashes must be linked
+    /// to the header by the parent hashes. Any invalid response results in an error naming the
+    /// affected account, storage slot or block.
+    pub fn enable_verification<H: EvmBlockHeader>(&mut self, header: &Sealed<H>) {
+        let number = header.number();
+        self.verifier = Some(Verifier {
+            state_root: *header.state_root(),
+            block_hashes: [(number, header.seal())].into_iter().collect(),
+            oldest: (number, *header.parent_hash()),
+        });
+    }
 
-    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
-        // use `eth_getProof` to get all the account info with a single call
+    /// Verifies the account info contained in the proof against the state root.
+    fn verify_account(&self, proof: &EIP1186Proof) -> Result<(), ProviderDbError<P::Error>> {
+        let Some(verifier) = &self.verifier else {
+            return Ok(());
+        };
+        let invalid = || ProviderDbError::InvalidAccountProof(proof.address);
+
+        let trie = MerkleTrie::from_rlp_nodes(&proof.account_proof).map_err(|_| invalid())?;
+        if trie.hash_slow() != verifier.state_root {
+            return Err(invalid());
+        }
+        let account = trie
+            .try_get(keccak256(proof.address))
+            .ok_or_else(invalid)?
+            .map(|mut rlp| StateAccount::decode(&mut rlp))
+            .transpose()
+            .map_err(|_| invalid())?;
+
+        let valid = match account {
+            Some(account) => {
+                account.nonce == proof.nonce
+                    && account.balance == proof.balance
+                    && account.code_hash == proof.code_hash
+                    && account.storage_root == proof.storage_hash
+            }
+            // non-existent accounts may have a zero code hash and storage hash
+            None => {
+                proof.nonce == 0
+                    && proof.balance.is_zero()
+                    && (proof.code_hash.is_zero() || proof.code_hash == KECCAK_EMPTY)
+                    && (proof.storage_hash.is_zero() || proof.storage_hash == EMPTY_ROOT_HASH)
+            }
+        };
+        if !valid {
+            return Err(invalid());
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the storage proof of the key against the storage root of the account and returns
+    /// the proven value. The account proof must have been verified before.
+    fn verify_storage(
+        proof: &EIP1186Proof,
+        key: StorageKey,
+    ) -> Result<U256, ProviderDbError<P::Error>> {
+        let invalid = || ProviderDbError::InvalidStorageProof(proof.address, key);
+
+        let storage_proof = proof
+            .storage_proof
+            .iter()
+            .find(|storage_proof| storage_proof.key == key)
+            .ok_or_else(invalid)?;
+        let trie = MerkleTrie::from_rlp_nodes(&storage_proof.proof).map_err(|_| invalid())?;
+        let storage_root = match proof.storage_hash {
+            B256::ZERO => EMPTY_ROOT_HASH,
+            storage_hash => storage_hash,
+        };
+        if trie.hash_slow() != storage_root {
+            return Err(invalid());
+        }
+        let value = trie
+            .try_get(keccak256(key))
+            .ok_or_else(invalid)?
+            .map(|mut rlp| U256::decode(&mut rlp))
+            .transpose()
+            .map_err(|_| invalid())?
+            .unwrap_or_default();
+        if value != storage_proof.value {
+            return Err(invalid());
+        }
+
+        Ok(value)
+    }
+
+    /// Requests the proof of the storage slot and returns the verified value together with the
+    /// proof. `eth_getStorageAt` cannot be verified, so this is used instead when verifying.
+    fn verified_storage(
+        &mut self,
+        address: Address,
+        index: U256,
+    ) -> Result<(U256, EIP1186Proof), ProviderDbError<P::Error>> {
+        let key = StorageKey::from(index);
         let proof = self
             .provider
-            .get_proof(address, vec![], self.block_number)?;
+            .get_proof(address, vec![key], self.block_number)?;
+        self.verify_account(&proof)?;
+        let value = Self::verify_storage(&proof, key)?;
+
+        Ok((value, proof))
+    }
+
+    /// Returns the hash of the given ancestor, verifying all the headers down to it.
+    fn verified_block_hash(
+        &mut self,
+        block_number: u64,
+    ) -> Result<B256, ProviderDbError<P::Error>> {
+        let verifier = self.verifier.as_mut().unwrap();
+        if block_number > verifier.oldest.0 {
+            return verifier.block_hashes.get(&block_number).copied().ok_or(
+                ProviderDbError::InvalidBlockNumber(U256::from(block_number)),
+            );
+        }
+
+        // request all the missing headers at once and verify them by following the parent hashes
+        let (oldest, mut parent_hash) = verifier.oldest;
+        let headers = self.provider.get_block_headers(block_number..oldest)?;
+        for (number, header) in (block_number..oldest).rev().zip(headers.into_iter().rev()) {
+            let header = header.ok_or(ProviderDbError::InvalidBlockNumber(U256::from(number)))?;
+            if header.hash_slow() != parent_hash {
+                return Err(ProviderDbError::InvalidHeader(number));
+            }
+            verifier.block_hashes.insert(number, parent_hash);
+            parent_hash = *header.parent_hash();
+            verifier.oldest = (number, parent_hash);
+        }
+
+        verifier.block_hashes.get(&block_number).copied().ok_or(
+            ProviderDbError::InvalidBlockNumber(U256::from(block_number)),
+        )
+    }
+}
+
+impl<P> ProviderDb<P> {
+    /// Returns the account info contained in the given proof.
+    fn account_info(&mut self, proof: &EIP1186Proof) -> Option<AccountInfo> {
         // for non-existent accounts, the code hash is zero
         // see https://github.com/ethereum/go-ethereum/issues/28441
         if proof.code_hash == B256::ZERO {
-            return Ok(None);
+            return None;
         }
         // cache the code hash to address mapping, so we can later retrieve the code
-        self.code_hashes
-            .insert(proof.code_hash.0.into(), proof.address);
+        self.code_hashes.insert(proof.code_hash, proof.address);
 
-        Ok(Some(AccountInfo {
+        Some(AccountInfo {
             nonce: proof.nonce,
             balance: proof.balance,
             code_hash: proof.code_hash,
             code: None,
-        }))
+        })
+    }
+}
+
+impl<P: Provider> Database for ProviderDb<P> {
+    type Error = ProviderDbError<P::Error>;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        // use `eth_getProof` to get all the account info with a single call
+        let proof = self
+            .provider
+            .get_proof(address, vec![], self.block_number)?;
+        self.verify_account(&proof)?;
+
+        Ok(self.account_info(&proof))
     }
 
     fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
@@ -92,11 +273,18 @@ impl<P: Provider> Database for ProviderDb<P> {
             .provider
             .get_code(contract_address, self.block_number)
             .map_err(ProviderDbError::Provider)?;
+        if self.verifier.is_some() && keccak256(&code) != code_hash {
+            return Err(ProviderDbError::InvalidCode(contract_address));
+        }
 
         Ok(Bytecode::new_raw(code.0.into()))
     }
 
     fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        if self.verifier.is_some() {
+            return Ok(self.verified_storage(address, index)?.0);
+        }
+
         let storage = self
             .provider
             .get_storage_at(address, index.into(), self.block_number)
@@ -109,6 +297,9 @@ impl<P: Provider> Database for ProviderDb<P> {
         let block_number: u64 = number
             .try_into()
             .map_err(|_| ProviderDbError::InvalidBlockNumber(number))?;
+        if self.verifier.is_some() {
+            return self.verified_block_hash(block_number);
+        }
         let header = self
             .provider
             .get_block_header(block_number)?
@@ -118,12 +309,33 @@ impl<P: Provider> Database for ProviderDb<P> {
     }
 }
 
+/// Statistics about the queries answered by a [ProofDb].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProofDbStats {
+    /// Number of requests issued to the provider.
+    pub rpc_calls: u64,
+    /// Number of batches, in which the proofs of several accounts were requested at once.
+    pub batches: u64,
+    /// Number of queries answered from the cache without a request.
+    pub cache_hits: u64,
+}
+
 /// A revm [Database] backed by a [Provider] that caches all queries needed for a state proof.
+///
+/// Account info is read from the EIP-1186 proofs of the accounts, which are cached and later
+/// reused when creating the input. Thus, only accounts with accessed storage need to be proven
+/// again. Storage values, contracts and block hashes are cached as well, so that repeated
+/// queries, e.g. of different calls, do not result in additional requests.
 pub struct ProofDb<P> {
     accounts: HashMap<Address, HashSet<U256>>,
     contracts: HashMap<B256, Bytes>,
     block_hash_numbers: HashSet<U256>,
 
+    proofs: HashMap<Address, EIP1186Proof>,
+    storage: HashMap<(Address, U256), U256>,
+    block_hashes: HashMap<U256, B256>,
+    stats: ProofDbStats,
+
     db: ProviderDb<P>,
 }
 
@@ -133,6 +345,10 @@ impl<P: Provider> ProofDb<P> {
             accounts: HashMap::new(),
             contracts: HashMap::new(),
             block_hash_numbers: HashSet::new(),
+            proofs: HashMap::new(),
+            storage: HashMap::new(),
+            block_hashes: HashMap::new(),
+            stats: ProofDbStats::default(),
             db: ProviderDb::new(provider, block_number),
         }
     }
@@ -152,36 +368,436 @@ impl<P: Provider> ProofDb<P> {
     pub fn block_hash_numbers(&self) -> &HashSet<U256> {
         &self.block_hash_numbers
     }
+    pub fn stats(&self) -> ProofDbStats {
+        self.stats
+    }
+
+    /// Enables the verification of all responses against the given header of the block.
+    ///
+    /// See [ProviderDb::enable_verification].
+    pub fn enable_verification<H: EvmBlockHeader>(&mut self, header: &Sealed<H>) {
+        self.db.enable_verification(header);
+    }
+
+    /// Returns the accounts and storage keys for which the cached proofs are insufficient.
+    ///
+    /// These are all accounts with accessed storage slots that are not contained in their proof
+    /// yet, or accounts without any proof.
+    pub fn missing_proofs(&self) -> Vec<(Address, Vec<StorageKey>)> {
+        self.accounts
+            .iter()
+            .filter_map(|(address, storage_keys)| {
+                let proven: HashSet<StorageKey> = match self.proofs.get(address) {
+                    Some(proof) => proof.storage_proof.iter().map(|p| p.key).collect(),
+                    None => HashSet::new(),
+                };
+                let missing: Vec<StorageKey> = storage_keys
+                    .iter()
+                    .map(|key| StorageKey::from(*key))
+                    .filter(|key| !proven.contains(key))
+                    .collect();
+                (!missing.is_empty() || !self.proofs.contains_key(address))
+                    .then_some((*address, missing))
+            })
+            .collect()
+    }
+
+    /// Adds the given proofs, requested for the result of [ProofDb::missing_proofs], to the cache.
+    ///
+    /// The storage proofs are merged into the cached proofs of the corresponding accounts. Each
+    /// proof is counted as a request to the provider, while all of them count as a single batch.
+    pub fn add_proofs(&mut self, proofs: impl IntoIterator<Item = EIP1186Proof>) {
+        let mut proofs = proofs.into_iter().peekable();
+        if proofs.peek().is_some() {
+            self.stats.batches += 1;
+        }
+        for proof in proofs {
+            self.stats.rpc_calls += 1;
+            merge_proof(&mut self.proofs, proof);
+        }
+    }
+
+    /// Returns the cached proofs of all the accessed accounts.
+    ///
+    /// The proofs only contain the accessed storage slots, cached proofs of other slots, e.g.
+    /// from [ProofDb::prefetch], are removed.
+    pub fn proofs(&self) -> impl Iterator<Item = EIP1186Proof> + '_ {
+        self.accounts.iter().filter_map(|(address, slots)| {
+            let mut proof = self.proofs.get(address)?.clone();
+            proof
+                .storage_proof
+                .retain(|p| slots.contains(&U256::from_be_bytes(p.key.0)));
+            Some(proof)
+        })
+    }
+
+    /// Returns the code of all the executed contracts by the address of their account.
+    pub(crate) fn contracts_by_address(&self) -> BTreeMap<Address, Bytes> {
+        self.accounts
+            .keys()
+            .filter_map(|address| {
+                let code_hash = self.proofs.get(address)?.code_hash;
+                let code = self.contracts.get(&code_hash)?;
+                Some((*address, code.clone()))
+            })
+            .collect()
+    }
+
+    /// Restores the state accessed in a previous session recorded in the snapshot.
+    ///
+    /// The proofs of all the accounts and storage slots are requested again at once, see
+    /// [ProofDb::prefetch]. The code of an executed contract is only requested if it differs from
+    /// the code in the snapshot, i.e. when the snapshot is for a different block.
+    pub(crate) fn restore<H>(
+        &mut self,
+        snapshot: &PreflightSnapshot<H>,
+    ) -> Result<(), ProviderDbError<P::Error>> {
+        let items = snapshot
+            .accounts
+            .iter()
+            .map(|(address, slots)| AccessListItem {
+                address: *address,
+                storage_keys: slots.iter().map(|slot| StorageKey::from(*slot)).collect(),
+            })
+            .collect();
+        self.prefetch(&AccessList(items))?;
+
+        // all the accounts are cached now, read them without counting them as queries
+        for (address, slots) in &snapshot.accounts {
+            let info = self
+                .proofs
+                .get(address)
+                .and_then(|proof| self.db.account_info(proof));
+            if let (Some(info), Some(code)) = (info, snapshot.contracts.get(address)) {
+                if info.code_hash == KECCAK_EMPTY || self.contracts.contains_key(&info.code_hash) {
+                    // no code is needed or it is already known
+                } else if keccak256(code) == info.code_hash {
+                    self.contracts.insert(info.code_hash, code.clone());
+                } else {
+                    self.code_by_hash(info.code_hash)?;
+                }
+            }
+            self.accounts.entry(*address).or_default().extend(slots);
+        }
+        self.block_hash_numbers.extend(
+            snapshot
+                .block_hash_numbers
+                .iter()
+                .map(|number| U256::from(*number)),
+        );
+
+        Ok(())
+    }
+
+    /// Returns all the accessed accounts and storage slots as an EIP-2930 access list.
+    ///
+    /// The accounts and their storage keys are sorted, so that the list is deterministic.
+    pub fn access_list(&self) -> AccessList {
+        let mut items: Vec<_> = self
+            .accounts
+            .iter()
+            .map(|(address, keys)| {
+                let mut storage_keys: Vec<StorageKey> =
+                    keys.iter().map(|key| StorageKey::from(*key)).collect();
+                storage_keys.sort_unstable();
+                AccessListItem {
+                    address: *address,
+                    storage_keys,
+                }
+            })
+            .collect();
+        items.sort_unstable_by_key(|item| item.address);
+
+        AccessList(items)
+    }
+
+    /// Fetches the proofs of all the accounts and storage slots in the access list at once.
+    ///
+    /// The proofs and storage values are cached, so that calls accessing this state do not issue
+    /// any further requests. Listed state only becomes part of the input when it is actually
+    /// accessed by a call. If verification is enabled, all the proofs are verified.
+    pub fn prefetch(&mut self, access_list: &AccessList) -> Result<(), ProviderDbError<P::Error>> {
+        // only request the accounts and storage slots that are not cached yet
+        let mut accounts: HashMap<Address, Vec<StorageKey>> = HashMap::new();
+        for item in &access_list.0 {
+            let keys = accounts.entry(item.address).or_default();
+            for key in &item.storage_keys {
+                if !self.storage.contains_key(&(item.address, (*key).into())) && !keys.contains(key)
+                {
+                    keys.push(*key);
+                }
+            }
+        }
+        accounts.retain(|address, keys| !keys.is_empty() || !self.proofs.contains_key(address));
+        if accounts.is_empty() {
+            return Ok(());
+        }
+
+        let proofs = self
+            .db
+            .provider
+            .get_proofs(accounts.into_iter().collect(), self.db.block_number)?;
+        for proof in &proofs {
+            self.db.verify_account(proof)?;
+            for storage_proof in &proof.storage_proof {
+                let value = match self.db.verifier {
+                    Some(_) => ProviderDb::<P>::verify_storage(proof, storage_proof.key)?,
+                    None => storage_proof.value,
+                };
+                self.storage
+                    .insert((proof.address, storage_proof.key.into()), value);
+            }
+        }
+        self.add_proofs(proofs);
+
+        Ok(())
+    }
 }
 
 impl<P: Provider> Database for ProofDb<P> {
     type Error = <ProviderDb<P> as Database>::Error;
 
     fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
-        let basic = self.db.basic(address)?;
+        let proof = match self.proofs.entry(address) {
+            Entry::Occupied(entry) => {
+                self.stats.cache_hits += 1;
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => {
+                // use `eth_getProof` to get all the account info with a single call
+                let proof = self
+                    .db
+                    .provider
+                    .get_proof(address, vec![], self.db.block_number)?;
+                self.db.verify_account(&proof)?;
+                self.stats.rpc_calls += 1;
+                entry.insert(proof)
+            }
+        };
+        let basic = self.db.account_info(proof);
         self.accounts.entry(address).or_default();
 
         Ok(basic)
     }
 
     fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        if let Some(code) = self.contracts.get(&code_hash) {
+            self.stats.cache_hits += 1;
+            return Ok(Bytecode::new_raw(code.clone()));
+        }
         let code = self.db.code_by_hash(code_hash)?;
+        if code_hash != KECCAK_EMPTY {
+            self.stats.rpc_calls += 1;
+        }
         self.contracts.insert(code_hash, code.original_bytes());
 
         Ok(code)
     }
 
     fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
-        let storage = self.db.storage(address, index)?;
+        let storage = match self.storage.entry((address, index)) {
+            Entry::Occupied(entry) => {
+                self.stats.cache_hits += 1;
+                *entry.get()
+            }
+            Entry::Vacant(entry) => {
+                let storage = if self.db.verifier.is_some() {
+                    // keep the verified proof, so that it is not requested again for the input
+                    let (storage, proof) = self.db.verified_storage(address, index)?;
+                    merge_proof(&mut self.proofs, proof);
+                    storage
+                } else {
+                    self.db.storage(address, index)?
+                };
+                self.stats.rpc_calls += 1;
+                *entry.insert(storage)
+            }
+        };
         self.accounts.entry(address).or_default().insert(index);
 
         Ok(storage)
     }
 
     fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
-        let block_hash = self.db.block_hash(number)?;
+        let block_hash = match self.block_hashes.entry(number) {
+            Entry::Occupied(entry) => {
+                self.stats.cache_hits += 1;
+                *entry.get()
+            }
+            Entry::Vacant(entry) => {
+                let block_hash = self.db.block_hash(number)?;
+                self.stats.rpc_calls += 1;
+                *entry.insert(block_hash)
+            }
+        };
         self.block_hash_numbers.insert(number);
 
         Ok(block_hash)
     }
 }
+
+/// Merges the storage proofs into the cached proof of the same account, or caches the proof.
+fn merge_proof(proofs: &mut HashMap<Address, EIP1186Proof>, proof: EIP1186Proof) {
+    match proofs.entry(proof.address) {
+        Entry::Occupied(mut entry) => entry.get_mut().storage_proof.extend(proof.storage_proof),
+        Entry::Vacant(entry) => {
+            entry.insert(proof);
+        }
+    }
+}
+
+/// A revm [Database] backed by a [TraceProvider] that learns the accessed state from traces.
+///
+/// Before a call is executed, it is traced with [TraceProvider::trace_prestate], returning every
+/// account and storage slot the call accesses. The call is then executed against this prestate
+/// without any further requests, and the proofs of all accessed state can later be requested
+/// at once. Any query that is not covered by the traces falls back to the [Provider].
+pub struct TraceDb<P> {
+    accounts: HashMap<Address, HashSet<U256>>,
+    contracts: HashMap<B256, Bytes>,
+    block_hash_numbers: HashSet<U256>,
+
+    prestate: HashMap<Address, PrestateAccount>,
+
+    db: ProviderDb<P>,
+}
+
+impl<P: TraceProvider> TraceDb<P> {
+    pub fn new(provider: P, block_number: u64) -> Self {
+        Self {
+            accounts: HashMap::new(),
+            contracts: HashMap::new(),
+            block_hash_numbers: HashSet::new(),
+            prestate: HashMap::new(),
+            db: ProviderDb::new(provider, block_number),
+        }
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.db.provider
+    }
+    pub fn block_number(&self) -> u64 {
+        self.db.block_number
+    }
+    pub fn accounts(&self) -> &HashMap<Address, HashSet<U256>> {
+        &self.accounts
+    }
+    pub fn contracts(&self) -> &HashMap<B256, Bytes> {
+        &self.contracts
+    }
+    pub fn block_hash_numbers(&self) -> &HashSet<U256> {
+        &self.block_hash_numbers
+    }
+
+    /// Traces the given call and adds the returned prestate to the database.
+    pub fn trace(&mut self, call: &TraceCall) -> Result<(), P::Error> {
+        let trace = self.provider().trace_prestate(call, self.block_number())?;
+        for (address, account) in trace {
+            match self.prestate.entry(address) {
+                Entry::Occupied(mut entry) => {
+                    let storage = &mut entry.get_mut().storage;
+                    for (key, value) in account.storage {
+                        storage.entry(key).or_insert(value);
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(account);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<P: TraceProvider> Database for TraceDb<P> {
+    type Error = <ProviderDb<P> as Database>::Error;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.accounts.entry(address).or_default();
+        // the tracer also lists touched accounts that do not exist as empty accounts
+        let account = match self.prestate.get(&address) {
+            Some(account) if account != &PrestateAccount::default() => account,
+            _ => return self.db.basic(address),
+        };
+
+        let code_hash = if account.code.is_empty() {
+            KECCAK_EMPTY
+        } else {
+            let code_hash = keccak256(&account.code);
+            self.contracts.insert(code_hash, account.code.clone());
+            code_hash
+        };
+
+        Ok(Some(AccountInfo {
+            nonce: account.nonce,
+            balance: account.balance,
+            code_hash,
+            code: None,
+        }))
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        if let Some(code) = self.contracts.get(&code_hash) {
+            return Ok(Bytecode::new_raw(code.clone()));
+        }
+        let code = self.db.code_by_hash(code_hash)?;
+        self.contracts.insert(code_hash, code.original_bytes());
+
+        Ok(code)
+    }
+
+    fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        self.accounts.entry(address).or_default().insert(index);
+        let value = self
+            .prestate
+            .get(&address)
+            .and_then(|account| account.storage.get(&B256::from(index)));
+        match value {
+            Some(value) => Ok((*value).into()),
+            None => self.db.storage(address, index),
+        }
+    }
+
+    fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
+        self.block_hash_numbers.insert(number);
+        self.db.block_hash(number)
+    }
+}
+
+/// A [ProofDb] backed by an [AsyncProvider].
+///
+/// The EVM accesses its database synchronously. Therefore, calls are executed on a blocking thread
+/// of the tokio runtime, where the requests of the [AsyncProvider] are awaited using a
+/// [BlockingProvider]. The database is shared with that thread and locked while a call is
+/// executed, so that it is not lost when the future of a call is dropped before it completes.
+pub struct AsyncProofDb<P> {
+    db: Arc<Mutex<ProofDb<BlockingProvider<P>>>>,
+}
+
+impl<P: AsyncProvider> AsyncProofDb<P> {
+    /// Creates a new [AsyncProofDb] executing the requests on the given runtime.
+    pub fn new(provider: P, block_number: u64, handle: Handle) -> Self {
+        let provider = BlockingProvider::new(provider, handle);
+        Self {
+            db: Arc::new(Mutex::new(ProofDb::new(provider, block_number))),
+        }
+    }
+
+    /// Locks the underlying [ProofDb], waiting for unfinished calls to complete.
+    pub async fn lock(&self) -> MutexGuard<'_, ProofDb<BlockingProvider<P>>> {
+        self.db.lock().await
+    }
+
+    /// Enables the verification of all responses against the given header of the block.
+    ///
+    /// This waits for unfinished calls to complete, see [ProviderDb::enable_verification].
+    pub async fn enable_verification<H: EvmBlockHeader>(&self, header: &Sealed<H>) {
+        self.db.lock().await.enable_verification(header);
+    }
+
+    /// Returns a handle to the shared [ProofDb], which is locked to execute a call.
+    pub(crate) fn shared(&self) -> Arc<Mutex<ProofDb<BlockingProvider<P>>>> {
+        Arc::clone(&self.db)
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{EvmBlockHeader, EvmInput, MerkleTrie, StateAccount};
+use alloy_primitives::{keccak256, B256};
+use anyhow::{bail, ensure, Context};
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use super::ReplayCall;
+
+impl<H: EvmBlockHeader> EvmInput<H> {
+    /// Merges two inputs for the same block into one input containing the state of both.
+    ///
+    /// This combines the inputs of preflights that were executed separately, e.g. in different
+    /// processes. The state trie and the storage tries are merged node by node, while the
+    /// contracts and the ancestors are deduplicated.
+    pub fn merge(mut self, other: EvmInput<H>) -> anyhow::Result<Self> {
+        ensure!(
+            self.header.hash_slow() == other.header.hash_slow(),
+            "inputs are for different blocks: {} and {}",
+            self.header.number(),
+            other.header.number()
+        );
+        for input in [&self, &other] {
+            ensure!(
+                input.header.state_root() == &input.state_trie.hash_slow(),
+                "root of the state trie does not match the header"
+            );
+        }
+        // both ancestor chains start at the same header, so one must be a prefix of the other
+        ensure!(
+            self.ancestors
+                .iter()
+                .zip(&other.ancestors)
+                .all(|(a, b)| a.hash_slow() == b.hash_slow()),
+            "ancestors do not match"
+        );
+
+        self.state_trie.merge(other.state_trie);
+
+        let mut storage_tries: BTreeMap<B256, MerkleTrie> = self
+            .storage_tries
+            .into_iter()
+            .map(|trie| (trie.hash_slow(), trie))
+            .collect();
+        for trie in other.storage_tries {
+            match storage_tries.entry(trie.hash_slow()) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge(trie),
+                Entry::Vacant(entry) => {
+                    entry.insert(trie);
+                }
+            }
+        }
+        self.storage_tries = storage_tries.into_values().collect();
+
+        let contracts: BTreeMap<_, _> = self
+            .contracts
+            .into_iter()
+            .chain(other.contracts)
+            .map(|code| (keccak256(&code), code))
+            .collect();
+        self.contracts = contracts.into_values().collect();
+
+        if other.ancestors.len() > self.ancestors.len() {
+            self.ancestors = other.ancestors;
+        }
+
+        Ok(self)
+    }
+}
+
+impl<H: EvmBlockHeader + Clone> EvmInput<H> {
+    /// Prunes the input down to the state accessed by the given calls.
+    ///
+    /// This is the reverse of [EvmInput::merge]. The calls are replayed like in
+    /// [EvmInput::replay] and every subtree of the tries that they do not access is replaced by
+    /// its digest. Unused contracts and ancestors are removed. It returns an error if the input
+    /// does not contain all the state accessed by the calls.
+    pub fn prune<'a>(
+        &self,
+        calls: impl IntoIterator<Item = &'a ReplayCall>,
+    ) -> anyhow::Result<Self> {
+        let (report, accesses) = self.replay_accesses(calls)?;
+        if let Some(state) = report.missing.first() {
+            bail!("{state} accessed by the calls is not contained in the input");
+        }
+
+        // collect the accessed storage keys of each storage trie
+        let mut storage_keys: BTreeMap<B256, Vec<B256>> = BTreeMap::new();
+        for (address, slots) in accesses.accounts.iter().filter(|(_, s)| !s.is_empty()) {
+            let account = self
+                .state_trie
+                .get_rlp::<StateAccount>(keccak256(address))
+                .context("invalid state value")?;
+            if let Some(account) = account {
+                let keys = slots.iter().map(|slot| keccak256(slot.to_be_bytes::<32>()));
+                storage_keys
+                    .entry(account.storage_root)
+                    .or_default()
+                    .extend(keys);
+            }
+        }
+
+        let state_trie = self
+            .state_trie
+            .prune(accesses.accounts.keys().map(keccak256));
+        let storage_tries = self
+            .storage_tries
+            .iter()
+            .filter_map(|trie| storage_keys.get(&trie.hash_slow()).map(|k| trie.prune(k)))
+            .collect();
+        let contracts = self
+            .contracts
+            .iter()
+            .filter(|code| accesses.contracts.contains(&keccak256(code)))
+            .cloned()
+            .collect();
+        // the ancestors are ordered from child to parent, keep them up to the oldest accessed
+        let ancestors = match accesses.block_numbers.first() {
+            Some(oldest) => self
+                .ancestors
+                .iter()
+                .take_while(|header| header.number() >= *oldest)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(EvmInput {
+            header: self.header.clone(),
+            state_trie,
+            storage_tries,
+            contracts,
+            ancestors,
+        })
+    }
+}
@@ -15,18 +15,47 @@
 //! Functionality that is only needed for the host and not the guest.
 
 use self::{
-    db::ProofDb,
-    provider::{EthersProvider, Provider},
+    db::{AsyncProofDb, ProofDb, TraceDb},
+    provider::{
+        AsyncProvider, EIP1186Proof, EthersProvider, ExecutionWitness, Provider, StorageProof,
+        TraceProvider,
+    },
 };
-use crate::{ethereum::EthEvmEnv, EvmBlockHeader, EvmEnv, EvmInput, MerkleTrie};
-use alloy_primitives::{Sealable, B256};
+use crate::{
+    ethereum::EthEvmEnv,
+    mpt::{NodeSet, EMPTY_ROOT_HASH},
+    EvmBlockHeader, EvmEnv, EvmInput, MerkleTrie, SolCommitment, StateAccount, VersionedEvmInput,
+};
+use alloy_primitives::{keccak256, Address, BlockNumber, Bytes, Sealable, Sealed, B256, U256};
 use anyhow::{ensure, Context};
 use ethers_providers::{Http, RetryClient};
 use log::debug;
-use revm::primitives::HashMap;
+use revm::primitives::{HashMap, HashSet};
+use serde::Serialize;
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    fmt,
+    num::NonZeroUsize,
+    ops::Range,
+    panic,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+use tokio::runtime::Handle;
 
 pub mod db;
+mod merge;
 pub mod provider;
+mod replay;
+mod snapshot;
+mod stats;
+pub mod testing;
+
+pub use crate::contract::MissingState;
+pub use alloy::rpc::types::eth::{AccessList, AccessListItem};
+pub use replay::{ReplayCall, ReplayReport, ReplayResult};
+pub use snapshot::PreflightSnapshot;
+pub use stats::{InputStats, TrieStats};
 
 /// Alias for readability, do not make public.
 pub(crate) type HostEvmEnv<P, H> = EvmEnv<ProofDb<P>, H>;
@@ -34,17 +63,109 @@ pub(crate) type HostEvmEnv<P, H> = EvmEnv<ProofDb<P>, H>;
 /// The Ethers client type.
 pub type EthersClient = ethers_providers::Provider<RetryClient<Http>>;
 
+/// The number of most recent blocks whose hashes are accessible with the `blockhash` opcode.
+pub const BLOCKHASH_WINDOW: u64 = 256;
+
+/// A block number or a tag selecting a block relative to the current state of the chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlockSelector {
+    /// The most recent block of the canonical chain.
+    #[default]
+    Latest,
+    /// The block that is the given number of blocks older than the most recent block.
+    LatestMinus(u64),
+    /// The most recent block that is safe from re-orgs under honest majority assumptions.
+    Safe,
+    /// The most recent finalized block.
+    Finalized,
+    /// The block with the given number.
+    Number(BlockNumber),
+}
+
+impl From<BlockNumber> for BlockSelector {
+    fn from(number: BlockNumber) -> Self {
+        BlockSelector::Number(number)
+    }
+}
+
+impl fmt::Display for BlockSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockSelector::Latest => f.write_str("latest"),
+            BlockSelector::LatestMinus(n) => write!(f, "latest-{n}"),
+            BlockSelector::Safe => f.write_str("safe"),
+            BlockSelector::Finalized => f.write_str("finalized"),
+            BlockSelector::Number(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+/// The status of a [SolCommitment] as returned by [check_commitment].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitmentStatus {
+    /// Whether the committed block is part of the canonical chain.
+    pub canonical: bool,
+    /// The number of upcoming blocks, in which the commitment can still be validated using the
+    /// `blockhash` opcode.
+    pub remaining_blocks: u64,
+}
+
+impl CommitmentStatus {
+    /// Returns whether the commitment can be validated in the next block.
+    pub fn is_valid(&self) -> bool {
+        self.canonical && self.remaining_blocks > 0
+    }
+}
+
+/// Checks whether the given commitment is still valid on the chain with the given head.
+///
+/// The commitment is canonical, if the provider returns a block with the same hash for the
+/// committed block number. As only the hashes of the [BLOCKHASH_WINDOW] most recent blocks are
+/// accessible on-chain, it can only be validated in a limited number of upcoming blocks. Thus, a
+/// proof should not be submitted if the commitment is not [CommitmentStatus::is_valid].
+///
+/// To check the commitment against the most recent block, use the `check_commitment` method of
+/// [AlloyProvider](provider::AlloyProvider) or [EthersProvider], which queries the current head
+/// first.
+pub fn check_commitment<P: Provider>(
+    provider: &P,
+    commitment: &SolCommitment,
+    head: BlockNumber,
+) -> anyhow::Result<CommitmentStatus> {
+    let block_number: BlockNumber = commitment
+        .blockNumber
+        .try_into()
+        .context("invalid block number")?;
+    ensure!(
+        block_number <= head,
+        "block {block_number} is newer than the head {head}"
+    );
+
+    let canonical = match provider.get_block_header(block_number)? {
+        Some(header) => header.hash_slow() == commitment.blockHash,
+        None => false,
+    };
+    // the hash is accessible in the blocks up to `block_number + BLOCKHASH_WINDOW`
+    let remaining_blocks = (block_number + BLOCKHASH_WINDOW).saturating_sub(head);
+
+    Ok(CommitmentStatus {
+        canonical,
+        remaining_blocks,
+    })
+}
+
 impl EthEvmEnv<ProofDb<EthersProvider<EthersClient>>> {
     /// Creates a new provable [EvmEnv] for Ethereum from an RPC endpoint.
-    pub fn from_rpc(url: &str, block_number: Option<u64>) -> anyhow::Result<Self> {
+    ///
+    /// The block can be given by its number or by a tag like [BlockSelector::Safe], which is
+    /// resolved to the corresponding block number first.
+    pub fn from_rpc(url: &str, block: BlockSelector) -> anyhow::Result<Self> {
         let client = EthersClient::new_client(url, 3, 500)?;
         let provider = EthersProvider::new(client);
 
-        // get the latest block number if none is provided
-        let block_number = match block_number {
-            Some(n) => n,
-            None => provider.get_block_number()?,
-        };
+        let block_number = provider
+            .get_block_number_by_tag(block)?
+            .with_context(|| format!("block {block} not found"))?;
 
         EvmEnv::from_provider(provider, block_number)
     }
@@ -62,6 +183,109 @@ impl<P: Provider> EvmEnv<ProofDb<P>, P::Header> {
 
         Ok(EvmEnv::new(db, header.seal_slow()))
     }
+
+    /// Enables the verification of every response of the provider as it arrives.
+    ///
+    /// Each account, storage value, contract and block hash queried during the preflight is
+    /// verified against the header of the environment, so that a faulty provider results in an
+    /// immediate [ProviderDbError](db::ProviderDbError) naming the invalid data, instead of an
+    /// invalid input. This requires a proof for each accessed storage slot.
+    pub fn with_verification(mut self) -> Self {
+        self.db.enable_verification(&self.header);
+        self
+    }
+
+    /// Fetches the proofs of all the state in the access list before any call is executed.
+    ///
+    /// This reuses the knowledge of a previous preflight, e.g. the list returned by
+    /// [ProofDb::access_list], to request all the proofs in bulk. See [ProofDb::prefetch]. To
+    /// verify the prefetched proofs, [EvmEnv::with_verification] must be called first.
+    pub fn with_access_list(mut self, access_list: &AccessList) -> anyhow::Result<Self> {
+        self.db
+            .prefetch(access_list)
+            .context("failed to prefetch access list")?;
+        Ok(self)
+    }
+}
+
+/// Runs `preflight` on a new provable [EvmEnv] for each of the given blocks in parallel.
+///
+/// All the environments share the same provider, so that e.g. a
+/// [CachedProvider](provider::CachedProvider) only needs to be warmed up once. The preflights are
+/// distributed over at most [thread::available_parallelism] threads, and the results are returned
+/// in the order of the given blocks.
+///
+/// ```rust,no_run
+/// # use risc0_steel::{
+/// #     config::ETH_MAINNET_CHAIN_SPEC,
+/// #     host::{preflight_parallel, provider::EthFileProvider},
+/// #     Contract,
+/// # };
+/// # use alloy_primitives::address;
+/// # use alloy_sol_types::sol;
+/// # sol! { interface IERC20 { function balanceOf(address account) external view returns (uint); } }
+/// # fn main() -> anyhow::Result<()> {
+/// let provider = EthFileProvider::from_file(&"cache.json".into())?;
+/// let account = address!("F977814e90dA44bFA03b6295A0616a897441aceC");
+/// let call = IERC20::balanceOfCall { account };
+/// let inputs = preflight_parallel(&provider, [20_000_000, 20_000_100], |env| {
+///     let mut env = env.with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+///     let contract = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+///     Contract::preflight(contract, &mut env).call_builder(&call).call()?;
+///     env.into_input()
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn preflight_parallel<P, T, F>(
+    provider: &P,
+    block_numbers: impl IntoIterator<Item = BlockNumber>,
+    preflight: F,
+) -> anyhow::Result<Vec<T>>
+where
+    P: Provider + Sync,
+    P::Header: Send,
+    T: Send,
+    F: Fn(EvmEnv<ProofDb<&P>, P::Header>) -> anyhow::Result<T> + Sync,
+{
+    let block_numbers: Vec<_> = block_numbers.into_iter().collect();
+    let threads = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(block_numbers.len());
+    let next = AtomicUsize::new(0);
+
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    // each worker processes the next unclaimed block until all are done
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&block_number) = block_numbers.get(i) else {
+                            break;
+                        };
+                        let result = EvmEnv::from_provider(provider, block_number)
+                            .and_then(&preflight)
+                            .with_context(|| format!("preflight for block {block_number} failed"));
+                        results.push((i, result));
+                    }
+                    results
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|err| panic::resume_unwind(err))
+            })
+            .collect()
+    });
+    results.sort_unstable_by_key(|(i, _)| *i);
+
+    results.into_iter().map(|(_, result)| result).collect()
 }
 
 impl<P: Provider> EvmEnv<ProofDb<P>, P::Header> {
@@ -69,78 +293,364 @@ impl<P: Provider> EvmEnv<ProofDb<P>, P::Header> {
     ///
     /// The resulting input contains inclusion proofs for all the required chain state data. It can
     /// therefore be used to execute the same calls in a verifiable way in the zkVM.
+    ///
+    /// All collections of the input are in a canonical order, i.e. storage tries are ordered by
+    /// their root and contracts by their code hash, so that preflights of the same calls always
+    /// result in the same input.
     pub fn into_input(self) -> anyhow::Result<EvmInput<P::Header>> {
-        let db = &self.db;
-
-        // use the same provider as the database
-        let provider = db.provider();
-
-        // retrieve EIP-1186 proofs for all accounts
-        let mut proofs = Vec::new();
-        for (address, storage_keys) in db.accounts() {
-            let proof = provider.get_proof(
-                *address,
-                storage_keys.iter().map(|v| B256::from(*v)).collect(),
-                db.block_number(),
-            )?;
-            proofs.push(proof);
-        }
+        let mut db = self.db;
 
-        // build the sparse MPT for the state and verify against the header
-        let state_nodes = proofs.iter().flat_map(|p| p.account_proof.iter());
-        let state_trie =
-            MerkleTrie::from_rlp_nodes(state_nodes).context("invalid account proof")?;
-        ensure!(
-            self.header.state_root() == &state_trie.hash_slow(),
-            "root of the state trie does not match the header"
-        );
-
-        // build the sparse MPT for account storages and filter duplicates
-        let mut storage_tries = HashMap::new();
-        for proof in proofs {
-            // skip non-existing accounts or accounts where no storage slots were requested
-            if proof.storage_proof.is_empty() || proof.storage_hash.is_zero() {
-                continue;
-            }
+        // retrieve the EIP-1186 proofs that are not yet cached
+        let proofs = db
+            .provider()
+            .get_proofs(db.missing_proofs(), db.block_number())?;
+        db.add_proofs(proofs);
 
-            let storage_nodes = proof.storage_proof.iter().flat_map(|p| p.proof.iter());
-            let storage_trie =
-                MerkleTrie::from_rlp_nodes(storage_nodes).context("invalid storage proof")?;
-            storage_tries.insert(storage_trie.hash_slow(), storage_trie);
-        }
-        let storage_tries: Vec<_> = storage_tries.into_values().collect();
+        // retrieve ancestor block headers
+        let range = ancestor_range(db.block_hash_numbers(), db.block_number());
+        let headers = db.provider().get_block_headers(range.clone())?;
+        let ancestors = into_ancestors(range, headers)?;
 
-        // collect the bytecode of all referenced contracts
-        let contracts: Vec<_> = db.contracts().values().cloned().collect();
+        log_stats(&db);
+        build_input(
+            self.header,
+            db.proofs().collect(),
+            db.contracts(),
+            ancestors,
+        )
+    }
+
+    /// Converts the environment into a [EvmInput] like [EvmEnv::into_input] and returns
+    /// [InputStats] about its size.
+    pub fn into_input_with_stats(self) -> anyhow::Result<(EvmInput<P::Header>, InputStats)>
+    where
+        P::Header: Serialize,
+    {
+        let accounts = self.db.accounts().clone();
+        Ok(with_stats(self.into_input()?, &accounts))
+    }
+
+    /// Converts the environment into a [VersionedEvmInput].
+    ///
+    /// The input is created as in [EvmEnv::into_input] and wrapped for the chain ID of the
+    /// environment as set by [EvmEnv::with_chain_spec].
+    pub fn into_versioned_input(self) -> anyhow::Result<VersionedEvmInput<P::Header>> {
+        let chain_id = self.cfg_env.chain_id;
+        Ok(self.into_input()?.into_versioned(chain_id))
+    }
+}
+
+impl<P: AsyncProvider> EvmEnv<AsyncProofDb<P>, P::Header> {
+    /// Creates a new provable [EvmEnv] from an [AsyncProvider].
+    ///
+    /// Calls on the returned environment are preflighted on the current tokio runtime, so it must
+    /// be called from within a runtime.
+    pub async fn from_provider_async(provider: P, block_number: u64) -> anyhow::Result<Self> {
+        let header = provider
+            .get_block_header(block_number)
+            .await?
+            .with_context(|| format!("block {block_number} not found"))?;
+
+        // create a new database backed by the provider
+        let db = AsyncProofDb::new(provider, block_number, Handle::current());
+
+        Ok(EvmEnv::new(db, header.seal_slow()))
+    }
+
+    /// Enables the verification of every response of the provider as it arrives.
+    ///
+    /// This is the asynchronous version of [EvmEnv::with_verification]. It waits for unfinished
+    /// calls, e.g. of dropped futures, to complete.
+    pub async fn with_verification(self) -> Self {
+        self.db.enable_verification(&self.header).await;
+        self
+    }
+
+    /// Converts the environment into a [EvmInput].
+    ///
+    /// This is the asynchronous version of [EvmEnv::into_input] for environments created with
+    /// [EvmEnv::from_provider_async]. All proofs and headers are requested concurrently.
+    pub async fn into_input(self) -> anyhow::Result<EvmInput<P::Header>> {
+        let mut db = self.db.lock().await;
+        let provider = db.provider().inner();
+
+        // retrieve the EIP-1186 proofs that are not yet cached
+        let proofs = provider
+            .get_proofs(db.missing_proofs(), db.block_number())
+            .await?;
+        db.add_proofs(proofs);
 
         // retrieve ancestor block headers
-        let mut ancestors = Vec::new();
-        if let Some(block_hash_min_number) = db.block_hash_numbers().iter().min() {
-            let block_hash_min_number: u64 = block_hash_min_number.to();
-            for number in (block_hash_min_number..db.block_number()).rev() {
-                let header = provider
-                    .get_block_header(number)?
-                    .with_context(|| format!("block {number} not found"))?;
-                ancestors.push(header);
-            }
+        let provider = db.provider().inner();
+        let range = ancestor_range(db.block_hash_numbers(), db.block_number());
+        let headers = provider.get_block_headers(range.clone()).await?;
+        let ancestors = into_ancestors(range, headers)?;
+
+        log_stats(&db);
+        build_input(
+            self.header,
+            db.proofs().collect(),
+            db.contracts(),
+            ancestors,
+        )
+    }
+
+    /// Converts the environment into a [EvmInput] and returns [InputStats] about its size.
+    ///
+    /// This is the asynchronous version of [EvmEnv::into_input_with_stats].
+    pub async fn into_input_with_stats(self) -> anyhow::Result<(EvmInput<P::Header>, InputStats)>
+    where
+        P::Header: Serialize,
+    {
+        let accounts = self.db.lock().await.accounts().clone();
+        Ok(with_stats(self.into_input().await?, &accounts))
+    }
+
+    /// Converts the environment into a [VersionedEvmInput].
+    ///
+    /// This is the asynchronous version of [EvmEnv::into_versioned_input].
+    pub async fn into_versioned_input(self) -> anyhow::Result<VersionedEvmInput<P::Header>> {
+        let chain_id = self.cfg_env.chain_id;
+        Ok(self.into_input().await?.into_versioned(chain_id))
+    }
+}
+
+impl<P: TraceProvider> EvmEnv<TraceDb<P>, P::Header> {
+    /// Creates a new provable [EvmEnv] from a [TraceProvider].
+    ///
+    /// Calls on the returned environment are preflighted with a single `debug_traceCall` each,
+    /// instead of querying every accessed account and storage slot individually.
+    pub fn from_trace_provider(provider: P, block_number: u64) -> anyhow::Result<Self> {
+        let header = provider
+            .get_block_header(block_number)?
+            .with_context(|| format!("block {block_number} not found"))?;
+
+        // create a new database backed by the provider
+        let db = TraceDb::new(provider, block_number);
+
+        Ok(EvmEnv::new(db, header.seal_slow()))
+    }
+
+    /// Converts the environment into a [EvmInput].
+    ///
+    /// If the provider supports execution witnesses, the witness of the next block is used to
+    /// prove all the accessed state that it covers. The EIP-1186 proofs of all the remaining
+    /// accounts are requested at once. The result is the same as with [EvmEnv::into_input] of an
+    /// environment created with [EvmEnv::from_provider].
+    pub fn into_input(self) -> anyhow::Result<EvmInput<P::Header>> {
+        let db = self.db;
+
+        // the pre-state of the next block is the state of this block
+        let witness = db.provider().get_execution_witness(db.block_number() + 1)?;
+        let mut proofs = match witness {
+            Some(witness) => witness_proofs(self.header.state_root(), &witness, db.accounts())?,
+            None => HashMap::new(),
+        };
+        debug!("accounts proven by the witness: {}", proofs.len());
+
+        // retrieve the EIP-1186 proofs of all the remaining accounts at once
+        let missing: Vec<_> = db
+            .accounts()
+            .iter()
+            .filter(|(address, _)| !proofs.contains_key(*address))
+            .map(|(address, keys)| (*address, keys.iter().map(|key| (*key).into()).collect()))
+            .collect();
+        for proof in db.provider().get_proofs(missing, db.block_number())? {
+            proofs.insert(proof.address, proof);
         }
 
-        debug!("state size: {}", state_trie.size());
-        debug!("storage tries: {}", storage_tries.len());
-        debug!(
-            "total storage size: {}",
-            storage_tries.iter().map(|t| t.size()).sum::<usize>()
-        );
-        debug!("contracts: {}", contracts.len());
-        debug!("blocks: {}", ancestors.len());
-
-        let header = self.header.into_inner();
-        Ok(EvmInput {
-            header,
-            state_trie,
-            storage_tries,
-            contracts,
+        // retrieve ancestor block headers
+        let range = ancestor_range(db.block_hash_numbers(), db.block_number());
+        let headers = db.provider().get_block_headers(range.clone())?;
+        let ancestors = into_ancestors(range, headers)?;
+
+        build_input(
+            self.header,
+            proofs.into_values().collect(),
+            db.contracts(),
             ancestors,
+        )
+    }
+
+    /// Converts the environment into a [EvmInput] like [EvmEnv::into_input] and returns
+    /// [InputStats] about its size.
+    pub fn into_input_with_stats(self) -> anyhow::Result<(EvmInput<P::Header>, InputStats)>
+    where
+        P::Header: Serialize,
+    {
+        let accounts = self.db.accounts().clone();
+        Ok(with_stats(self.into_input()?, &accounts))
+    }
+
+    /// Converts the environment into a [VersionedEvmInput].
+    ///
+    /// The input is created as in [EvmEnv::into_input] and wrapped for the chain ID of the
+    /// environment as set by [EvmEnv::with_chain_spec].
+    pub fn into_versioned_input(self) -> anyhow::Result<VersionedEvmInput<P::Header>> {
+        let chain_id = self.cfg_env.chain_id;
+        Ok(self.into_input()?.into_versioned(chain_id))
+    }
+}
+
+/// Returns the input together with its [InputStats], given the accounts accessed during the
+/// preflight.
+fn with_stats<H: Serialize>(
+    input: EvmInput<H>,
+    accounts: &HashMap<Address, HashSet<U256>>,
+) -> (EvmInput<H>, InputStats) {
+    let stats = InputStats::new(&input, accounts);
+    debug!("input size: {} bytes", stats.total_bytes);
+
+    (input, stats)
+}
+
+/// Creates the EIP-1186 proofs of all the accounts that are fully covered by the witness.
+fn witness_proofs(
+    state_root: &B256,
+    witness: &ExecutionWitness,
+    accounts: &HashMap<Address, HashSet<U256>>,
+) -> anyhow::Result<HashMap<Address, EIP1186Proof>> {
+    let nodes = NodeSet::from_rlp_nodes(&witness.state).context("invalid witness")?;
+
+    let mut proofs = HashMap::new();
+    let Some(state_trie) = nodes.trie(state_root) else {
+        return Ok(proofs);
+    };
+    for (address, keys) in accounts {
+        let key = keccak256(address);
+        let Some(account_proof) = state_trie.proof(key) else {
+            continue;
+        };
+        let account = state_trie.get_rlp::<StateAccount>(key)?;
+
+        // the empty storage trie is not part of the witness
+        let storage_trie = match &account {
+            Some(account) if account.storage_root != EMPTY_ROOT_HASH => {
+                nodes.trie(&account.storage_root)
+            }
+            _ => Some(MerkleTrie::default()),
+        };
+        let Some(storage_proof) = storage_trie.and_then(|trie| storage_proofs(&trie, keys)) else {
+            continue;
+        };
+
+        let proof = match account {
+            Some(account) => EIP1186Proof {
+                address: *address,
+                balance: account.balance,
+                code_hash: account.code_hash,
+                nonce: account.nonce,
+                storage_hash: account.storage_root,
+                account_proof,
+                storage_proof,
+            },
+            // non-existing accounts have zero values, as returned by `eth_getProof`
+            None => EIP1186Proof {
+                address: *address,
+                account_proof,
+                storage_proof,
+                ..Default::default()
+            },
+        };
+        proofs.insert(*address, proof);
+    }
+
+    Ok(proofs)
+}
+
+/// Creates the proofs of the given storage keys, if they are all resolved in the trie.
+fn storage_proofs(trie: &MerkleTrie, keys: &HashSet<U256>) -> Option<Vec<StorageProof>> {
+    keys.iter()
+        .map(|key| {
+            let key = B256::from(*key);
+            let hashed_key = keccak256(key);
+            Some(StorageProof {
+                key,
+                proof: trie.proof(hashed_key)?,
+                value: trie.get_rlp(hashed_key).ok()?.unwrap_or_default(),
+            })
         })
+        .collect()
+}
+
+/// Returns the range of all the ancestor blocks, whose hashes might have been queried.
+fn ancestor_range(block_hash_numbers: &HashSet<U256>, block_number: u64) -> Range<BlockNumber> {
+    let min_number = block_hash_numbers.iter().min().map(|n| n.to());
+    min_number.unwrap_or(block_number)..block_number
+}
+
+/// Converts the headers of the ancestor range into the ancestors ordered from child to parent.
+fn into_ancestors<H>(range: Range<BlockNumber>, headers: Vec<Option<H>>) -> anyhow::Result<Vec<H>> {
+    ensure!(headers.len() == range.clone().count(), "missing headers");
+    let mut ancestors = range
+        .zip(headers)
+        .map(|(number, header)| header.with_context(|| format!("block {number} not found")))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    ancestors.reverse();
+
+    Ok(ancestors)
+}
+
+fn log_stats<P: Provider>(db: &ProofDb<P>) {
+    let stats = db.stats();
+    debug!("rpc calls: {}", stats.rpc_calls);
+    debug!("batches: {}", stats.batches);
+    debug!("cache hits: {}", stats.cache_hits);
+}
+
+/// Builds the [EvmInput] from the EIP-1186 proofs of all the accessed accounts.
+fn build_input<H: EvmBlockHeader>(
+    header: Sealed<H>,
+    proofs: Vec<EIP1186Proof>,
+    contracts: &HashMap<B256, Bytes>,
+    ancestors: Vec<H>,
+) -> anyhow::Result<EvmInput<H>> {
+    // build the sparse MPT for the state and verify against the header
+    let state_nodes = proofs.iter().flat_map(|p| p.account_proof.iter());
+    let state_trie = MerkleTrie::from_rlp_nodes(state_nodes).context("invalid account proof")?;
+    ensure!(
+        header.state_root() == &state_trie.hash_slow(),
+        "root of the state trie does not match the header"
+    );
+
+    // build the sparse MPT for account storages and merge accounts sharing the same storage root
+    let mut storage_tries: BTreeMap<B256, MerkleTrie> = BTreeMap::new();
+    for proof in &proofs {
+        // skip non-existing accounts or accounts where no storage slots were requested
+        if proof.storage_proof.is_empty() || proof.storage_hash.is_zero() {
+            continue;
+        }
+
+        let storage_nodes = proof.storage_proof.iter().flat_map(|p| p.proof.iter());
+        let storage_trie =
+            MerkleTrie::from_rlp_nodes(storage_nodes).context("invalid storage proof")?;
+        match storage_tries.entry(storage_trie.hash_slow()) {
+            Entry::Occupied(mut entry) => entry.get_mut().merge(storage_trie),
+            Entry::Vacant(entry) => {
+                entry.insert(storage_trie);
+            }
+        }
     }
+    let storage_tries: Vec<_> = storage_tries.into_values().collect();
+
+    // collect the bytecode of all referenced contracts ordered by their code hash
+    let contracts: BTreeMap<_, _> = contracts.iter().collect();
+    let contracts: Vec<_> = contracts.into_values().cloned().collect();
+
+    debug!("state size: {}", state_trie.size());
+    debug!("storage tries: {}", storage_tries.len());
+    debug!(
+        "total storage size: {}",
+        storage_tries.iter().map(|t| t.size()).sum::<usize>()
+    );
+    debug!("contracts: {}", contracts.len());
+    debug!("blocks: {}", ancestors.len());
+
+    Ok(EvmInput {
+        header: header.into_inner(),
+        state_trie,
+        storage_tries,
+        contracts,
+        ancestors,
+    })
 }
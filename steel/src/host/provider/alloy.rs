@@ -0,0 +1,773 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    runtime_handle, AsyncProvider, EIP1186Proof, ExecutionWitness, PrestateTrace, Provider,
+    StorageProof, TraceCall, TraceProvider, DEFAULT_CONCURRENCY_LIMIT,
+};
+use crate::{
+    ethereum::EthBlockHeader,
+    host::{check_commitment, BlockSelector, CommitmentStatus},
+    SolCommitment,
+};
+use alloy::{
+    providers::{Provider as AlloyRpcProvider, ProviderBuilder, RootProvider},
+    rpc::types::eth::{BlockNumberOrTag, Header},
+    transports::{BoxTransport, Transport, TransportError},
+};
+use alloy_primitives::{
+    Address, BlockNumber, Bytes, Sealable, StorageKey, StorageValue, TxNumber, U256, U64,
+};
+use serde_json::json;
+use std::{future::Future, marker::PhantomData, ops::Range};
+use thiserror::Error;
+use tokio::runtime::{Handle, Runtime};
+
+/// An error that can occur when interacting with the provider.
+#[derive(Error, Debug)]
+pub enum AlloyProviderError {
+    #[error("transport error")]
+    Transport(#[from] TransportError),
+    #[error("block conversion error: {0}")]
+    BlockConversionError(String),
+}
+
+/// A provider that fetches data from an Ethereum node using the alloy crate.
+///
+/// It implements both [Provider] and [AsyncProvider]. When used as a [Provider], each request
+/// blocks on the current tokio runtime or on a runtime owned by the provider, if it has been
+/// created outside of a runtime.
+pub struct AlloyProvider<T = BoxTransport, P = RootProvider<BoxTransport>> {
+    provider: P,
+    runtime_handle: (Handle, Option<Runtime>),
+    concurrency_limit: usize,
+    phantom: PhantomData<T>,
+}
+
+impl AlloyProvider {
+    /// Connects to the node at the given URL.
+    ///
+    /// The transport is selected based on the URL: `http://` and `https://` use HTTP, `ws://` and
+    /// `wss://` use WebSocket and everything else is treated as the path of an IPC socket.
+    ///
+    /// This blocks until the connection is established and thus must not be called from within a
+    /// tokio runtime, where it panics. Use [AlloyProvider::connect_async] instead.
+    pub fn connect(url: &str) -> Result<Self, AlloyProviderError> {
+        let runtime_handle = runtime_handle();
+        let provider = runtime_handle
+            .0
+            .block_on(ProviderBuilder::new().on_builtin(url))?;
+
+        Ok(Self {
+            provider,
+            runtime_handle,
+            concurrency_limit: DEFAULT_CONCURRENCY_LIMIT,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Connects to the node at the given URL from within a tokio runtime.
+    ///
+    /// The transport is selected as in [AlloyProvider::connect]. The returned provider is meant to
+    /// be used as an [AsyncProvider] on the current runtime.
+    pub async fn connect_async(url: &str) -> Result<Self, AlloyProviderError> {
+        let provider = ProviderBuilder::new().on_builtin(url).await?;
+
+        Ok(Self {
+            provider,
+            runtime_handle: (Handle::current(), None),
+            concurrency_limit: DEFAULT_CONCURRENCY_LIMIT,
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<T: Transport + Clone, P: AlloyRpcProvider<T>> AlloyProvider<T, P> {
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider,
+            runtime_handle: runtime_handle(),
+            concurrency_limit: DEFAULT_CONCURRENCY_LIMIT,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of requests that are sent concurrently.
+    pub fn with_concurrency_limit(mut self, limit: usize) -> Self {
+        self.concurrency_limit = limit;
+        self
+    }
+
+    /// Fetches the current block number.
+    pub fn get_block_number(&self) -> Result<BlockNumber, AlloyProviderError> {
+        Ok(self.block_on(self.provider.get_block_number())?)
+    }
+
+    /// Fetches the number of the block with the given number or tag.
+    ///
+    /// It returns `None` if the node does not know such a block.
+    pub fn get_block_number_by_tag(
+        &self,
+        block: BlockSelector,
+    ) -> Result<Option<BlockNumber>, AlloyProviderError> {
+        let tag = match block {
+            BlockSelector::Number(number) => return Ok(Some(number)),
+            BlockSelector::Latest => return self.get_block_number().map(Some),
+            BlockSelector::LatestMinus(n) => {
+                return Ok(self.get_block_number()?.checked_sub(n));
+            }
+            BlockSelector::Safe => BlockNumberOrTag::Safe,
+            BlockSelector::Finalized => BlockNumberOrTag::Finalized,
+        };
+        let block = self.block_on(self.provider.get_block_by_number(tag, false))?;
+        Ok(block.and_then(|block| block.header.number))
+    }
+
+    /// Checks whether the given commitment is still valid on the current chain.
+    ///
+    /// This is equivalent to [check_commitment] with the current block number as the head.
+    pub fn check_commitment(&self, commitment: &SolCommitment) -> anyhow::Result<CommitmentStatus> {
+        let head = self.get_block_number()?;
+        check_commitment(self, commitment, head)
+    }
+
+    /// internal utility function to call tokio feature and wait for output
+    fn block_on<F: Future>(&self, f: F) -> F::Output {
+        self.runtime_handle.0.block_on(f)
+    }
+}
+
+impl<T: Transport + Clone, P: AlloyRpcProvider<T>> AsyncProvider for AlloyProvider<T, P> {
+    type Error = AlloyProviderError;
+    type Header = EthBlockHeader;
+
+    fn concurrency_limit(&self) -> usize {
+        self.concurrency_limit
+    }
+
+    async fn get_block_header(
+        &self,
+        block: BlockNumber,
+    ) -> Result<Option<Self::Header>, Self::Error> {
+        let block = self
+            .provider
+            .get_block_by_number(BlockNumberOrTag::Number(block), false)
+            .await?;
+        match block {
+            Some(block) => Ok(Some(
+                block
+                    .header
+                    .try_into()
+                    .map_err(AlloyProviderError::BlockConversionError)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_transaction_count(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> Result<TxNumber, Self::Error> {
+        Ok(self
+            .provider
+            .get_transaction_count(address)
+            .number(block)
+            .await?)
+    }
+
+    async fn get_balance(&self, address: Address, block: BlockNumber) -> Result<U256, Self::Error> {
+        Ok(self.provider.get_balance(address).number(block).await?)
+    }
+
+    async fn get_code(&self, address: Address, block: BlockNumber) -> Result<Bytes, Self::Error> {
+        Ok(self.provider.get_code_at(address).number(block).await?)
+    }
+
+    async fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: BlockNumber,
+    ) -> Result<StorageValue, Self::Error> {
+        Ok(self
+            .provider
+            .get_storage_at(address, key.into())
+            .number(block)
+            .await?)
+    }
+
+    async fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: BlockNumber,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        let proof = self
+            .provider
+            .get_proof(address, storage_keys)
+            .number(block)
+            .await?;
+
+        Ok(EIP1186Proof {
+            address: proof.address,
+            balance: proof.balance,
+            code_hash: proof.code_hash,
+            nonce: proof.nonce.to(),
+            storage_hash: proof.storage_hash,
+            account_proof: proof.account_proof,
+            storage_proof: proof
+                .storage_proof
+                .into_iter()
+                .map(|p| StorageProof {
+                    key: p.key.0,
+                    proof: p.proof,
+                    value: p.value,
+                })
+                .collect(),
+        })
+    }
+}
+
+impl<T: Transport + Clone, P: AlloyRpcProvider<T>> Provider for AlloyProvider<T, P> {
+    type Error = AlloyProviderError;
+    type Header = EthBlockHeader;
+
+    fn get_block_header(&self, block: BlockNumber) -> Result<Option<Self::Header>, Self::Error> {
+        self.block_on(AsyncProvider::get_block_header(self, block))
+    }
+
+    fn get_transaction_count(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> Result<TxNumber, Self::Error> {
+        self.block_on(AsyncProvider::get_transaction_count(self, address, block))
+    }
+
+    fn get_balance(&self, address: Address, block: BlockNumber) -> Result<U256, Self::Error> {
+        self.block_on(AsyncProvider::get_balance(self, address, block))
+    }
+
+    fn get_code(&self, address: Address, block: BlockNumber) -> Result<Bytes, Self::Error> {
+        self.block_on(AsyncProvider::get_code(self, address, block))
+    }
+
+    fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: BlockNumber,
+    ) -> Result<StorageValue, Self::Error> {
+        self.block_on(AsyncProvider::get_storage_at(self, address, key, block))
+    }
+
+    fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: BlockNumber,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        self.block_on(AsyncProvider::get_proof(self, address, storage_keys, block))
+    }
+
+    fn get_proofs(
+        &self,
+        accounts: Vec<(Address, Vec<StorageKey>)>,
+        block: BlockNumber,
+    ) -> Result<Vec<EIP1186Proof>, Self::Error> {
+        self.block_on(AsyncProvider::get_proofs(self, accounts, block))
+    }
+
+    fn get_block_headers(
+        &self,
+        blocks: Range<BlockNumber>,
+    ) -> Result<Vec<Option<Self::Header>>, Self::Error> {
+        self.block_on(AsyncProvider::get_block_headers(self, blocks))
+    }
+}
+
+impl<T: Transport + Clone, P: AlloyRpcProvider<T>> TraceProvider for AlloyProvider<T, P> {
+    fn trace_prestate(
+        &self,
+        call: &TraceCall,
+        block: BlockNumber,
+    ) -> Result<PrestateTrace, Self::Error> {
+        let params = (
+            call,
+            U64::from(block),
+            json!({ "tracer": "prestateTracer" }),
+        );
+        Ok(self.block_on(self.provider.raw_request("debug_traceCall".into(), params))?)
+    }
+
+    fn get_execution_witness(
+        &self,
+        block: BlockNumber,
+    ) -> Result<Option<ExecutionWitness>, Self::Error> {
+        let params = [U64::from(block)];
+        let witness = self.block_on(
+            self.provider
+                .raw_request("debug_executionWitness".into(), params),
+        );
+        match witness {
+            Ok(witness) => Ok(Some(witness)),
+            // the method is not supported or the block is not yet available
+            Err(err) if err.is_error_resp() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl TryFrom<Header> for EthBlockHeader {
+    type Error = String;
+
+    fn try_from(header: Header) -> Result<Self, Self::Error> {
+        let expected_hash = header.hash;
+        let header = EthBlockHeader {
+            parent_hash: header.parent_hash,
+            ommers_hash: header.uncles_hash,
+            beneficiary: header.miner,
+            state_root: header.state_root,
+            transactions_root: header.transactions_root,
+            receipts_root: header.receipts_root,
+            logs_bloom: header.logs_bloom,
+            difficulty: header.difficulty,
+            number: header.number.ok_or("number is missing")?,
+            gas_limit: header
+                .gas_limit
+                .try_into()
+                .map_err(|_| "invalid gas limit")?,
+            gas_used: header.gas_used.try_into().map_err(|_| "invalid gas used")?,
+            timestamp: header.timestamp,
+            extra_data: header.extra_data,
+            mix_hash: header.mix_hash.ok_or("mix_hash is missing")?,
+            nonce: header.nonce.ok_or("nonce is missing")?,
+            base_fee_per_gas: U256::from(
+                header
+                    .base_fee_per_gas
+                    .ok_or("base_fee_per_gas is missing")?,
+            ),
+            withdrawals_root: header.withdrawals_root,
+            blob_gas_used: header
+                .blob_gas_used
+                .map(|x| x.try_into().map_err(|_| "invalid blob gas used"))
+                .transpose()?,
+            excess_blob_gas: header
+                .excess_blob_gas
+                .map(|x| x.try_into().map_err(|_| "invalid excess blob gas"))
+                .transpose()?,
+            parent_beacon_block_root: header.parent_beacon_block_root,
+        };
+
+        // the header must hash to the block hash reported by the RPC
+        if let Some(expected_hash) = expected_hash {
+            let hash = header.hash_slow();
+            if hash != expected_hash {
+                return Err(format!(
+                    "header hash mismatch for block {}: expected {}, got {}",
+                    header.number, expected_hash, hash
+                ));
+            }
+        }
+
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AlloyProvider;
+    use crate::{
+        ethereum::EthBlockHeader,
+        host::{
+            provider::{
+                self, EIP1186Proof, EthFileProvider, PrestateAccount, Provider, TraceCall,
+                TraceProvider,
+            },
+            BlockSelector, BLOCKHASH_WINDOW,
+        },
+        SolCommitment,
+    };
+    use alloy_primitives::{
+        address, hex, keccak256, Address, Bytes, Sealable, StorageKey, B256, U256, U64,
+    };
+    use serde_json::{json, Value};
+    use std::{
+        collections::BTreeMap,
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+    use tungstenite::Message;
+
+    const RPC_CACHE_FILE: &str = "testdata/rpc_cache.json";
+
+    /// Starts a minimal JSON-RPC server over HTTP and returns its URL.
+    fn mock_server(handler: fn(&str, &[Value]) -> Value) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                thread::spawn(move || serve(stream, handler));
+            }
+        });
+        url
+    }
+
+    fn serve(stream: TcpStream, handler: fn(&str, &[Value]) -> Value) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        loop {
+            // read the headers until the empty line
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    return;
+                }
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let response = respond(&serde_json::from_slice(&body).unwrap(), handler);
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+        }
+    }
+
+    /// Returns the JSON-RPC response to the given request.
+    fn respond(request: &Value, handler: fn(&str, &[Value]) -> Value) -> String {
+        let method = request["method"].as_str().unwrap();
+        let params = request["params"].as_array().cloned().unwrap_or_default();
+        json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": handler(method, &params),
+        })
+        .to_string()
+    }
+
+    /// Starts a minimal JSON-RPC server over WebSocket and returns its URL.
+    fn mock_ws_server(handler: fn(&str, &[Value]) -> Value) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut socket = tungstenite::accept(stream.unwrap()).unwrap();
+                thread::spawn(move || {
+                    while let Ok(message) = socket.read() {
+                        if let Message::Text(text) = message {
+                            let response = respond(&serde_json::from_str(&text).unwrap(), handler);
+                            socket.send(Message::Text(response)).unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    /// Starts a minimal JSON-RPC server over an IPC socket and returns its path.
+    #[cfg(unix)]
+    fn mock_ipc_server(handler: fn(&str, &[Value]) -> Value) -> String {
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("steel-mock-{}.ipc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                thread::spawn(move || {
+                    let mut writer = stream.try_clone().unwrap();
+                    let requests = serde_json::Deserializer::from_reader(stream).into_iter();
+                    for request in requests.map_while(Result::ok) {
+                        writeln!(writer, "{}", respond(&request, handler)).unwrap();
+                    }
+                });
+            }
+        });
+        path.to_str().unwrap().to_string()
+    }
+
+    const BLOCK: u64 = 5702742;
+    const PROOF_BLOCK: u64 = 5702743;
+    const ADDRESS: Address = address!("3c31cdb4d84ca36f17a4a3215b1a56d2569daed8");
+    const CODE: &[u8] = &hex!("6080604052");
+
+    fn cached_header(number: u64) -> EthBlockHeader {
+        let provider = EthFileProvider::from_file(&RPC_CACHE_FILE.into()).unwrap();
+        provider.get_block_header(number).unwrap().unwrap()
+    }
+
+    fn cached_proof() -> EIP1186Proof {
+        let provider = EthFileProvider::from_file(&RPC_CACHE_FILE.into()).unwrap();
+        provider
+            .get_proof(ADDRESS, vec![StorageKey::ZERO], PROOF_BLOCK)
+            .unwrap()
+    }
+
+    fn handler(method: &str, params: &[Value]) -> Value {
+        // all the state queries must be for the proof block
+        match method {
+            "eth_getBlockByNumber" | "eth_blockNumber" | "debug_executionWitness" => {}
+            "debug_traceCall" => assert_eq!(params[1], json!(U64::from(PROOF_BLOCK))),
+            _ => assert_eq!(params.last().unwrap(), &json!(U64::from(PROOF_BLOCK))),
+        }
+
+        let proof = cached_proof();
+        match method {
+            "eth_getBlockByNumber"
+                if params[0] == json!(U64::from(BLOCK)) || params[0] == json!("safe") =>
+            {
+                let header = cached_header(BLOCK);
+                json!({
+                    "hash": header.hash_slow(),
+                    "parentHash": header.parent_hash,
+                    "sha3Uncles": header.ommers_hash,
+                    "miner": header.beneficiary,
+                    "stateRoot": header.state_root,
+                    "transactionsRoot": header.transactions_root,
+                    "receiptsRoot": header.receipts_root,
+                    "logsBloom": header.logs_bloom,
+                    "difficulty": header.difficulty,
+                    "number": U64::from(header.number),
+                    "gasLimit": U64::from(header.gas_limit),
+                    "gasUsed": U64::from(header.gas_used),
+                    "timestamp": U64::from(header.timestamp),
+                    "extraData": header.extra_data,
+                    "mixHash": header.mix_hash,
+                    "nonce": header.nonce,
+                    "baseFeePerGas": header.base_fee_per_gas,
+                    "withdrawalsRoot": header.withdrawals_root,
+                    "blobGasUsed": header.blob_gas_used.map(U64::from),
+                    "excessBlobGas": header.excess_blob_gas.map(U64::from),
+                    "parentBeaconBlockRoot": header.parent_beacon_block_root,
+                    "uncles": [],
+                    "transactions": [],
+                })
+            }
+            "eth_getBlockByNumber" => Value::Null,
+            "eth_blockNumber" => json!(U64::from(PROOF_BLOCK)),
+            "eth_getTransactionCount" => json!(U64::from(proof.nonce)),
+            "eth_getBalance" => json!(proof.balance),
+            "eth_getCode" => json!(Bytes::from_static(CODE)),
+            "eth_getStorageAt" => json!(proof.storage_proof[0].value),
+            "eth_getProof" => json!({
+                "address": proof.address,
+                "balance": proof.balance,
+                "codeHash": proof.code_hash,
+                "nonce": U64::from(proof.nonce),
+                "storageHash": proof.storage_hash,
+                "accountProof": proof.account_proof,
+                "storageProof": proof.storage_proof.iter().map(|p| json!({
+                    "key": p.key,
+                    "value": p.value,
+                    "proof": p.proof,
+                })).collect::<Vec<_>>(),
+            }),
+            "debug_traceCall" => {
+                assert_eq!(params[0]["to"], json!(ADDRESS));
+                assert_eq!(params[2], json!({ "tracer": "prestateTracer" }));
+                // default values are omitted and the nonce is a plain number
+                json!({
+                    ADDRESS.to_string(): {
+                        "balance": proof.balance,
+                        "nonce": proof.nonce,
+                        "code": Bytes::from_static(CODE),
+                        "storage": { B256::ZERO.to_string(): B256::from(proof.storage_proof[0].value) },
+                    },
+                    Address::ZERO.to_string(): {},
+                })
+            }
+            // the witness nodes are returned as a map by their hash
+            "debug_executionWitness" if params[0] == json!(U64::from(PROOF_BLOCK + 1)) => json!({
+                "state": proof.account_proof.iter().map(|n| (keccak256(n), n)).collect::<BTreeMap<_, _>>(),
+                "codes": {},
+            }),
+            "debug_executionWitness" => Value::Null,
+            _ => panic!("unexpected method: {method}"),
+        }
+    }
+
+    #[test]
+    fn mock_rpc_block_tags() {
+        let provider = AlloyProvider::connect(&mock_server(handler)).unwrap();
+
+        let block = |tag| provider.get_block_number_by_tag(tag).unwrap();
+        assert_eq!(block(BlockSelector::Number(BLOCK)), Some(BLOCK));
+        assert_eq!(block(BlockSelector::Latest), Some(PROOF_BLOCK));
+        assert_eq!(block(BlockSelector::LatestMinus(1)), Some(BLOCK));
+        assert_eq!(block(BlockSelector::LatestMinus(PROOF_BLOCK + 1)), None);
+        assert_eq!(block(BlockSelector::Safe), Some(BLOCK));
+        assert_eq!(block(BlockSelector::Finalized), None);
+    }
+
+    #[test]
+    fn mock_rpc_check_commitment() {
+        let provider = AlloyProvider::connect(&mock_server(handler)).unwrap();
+        let commitment = SolCommitment {
+            blockHash: cached_header(BLOCK).hash_slow(),
+            blockNumber: U256::from(BLOCK),
+        };
+
+        // the head is the latest block returned by the node
+        let status = provider.check_commitment(&commitment).unwrap();
+        assert!(status.canonical);
+        assert_eq!(
+            status.remaining_blocks,
+            BLOCK + BLOCKHASH_WINDOW - PROOF_BLOCK
+        );
+    }
+
+    #[test]
+    fn mock_rpc_trace() {
+        let provider = AlloyProvider::connect(&mock_server(handler)).unwrap();
+        let expected = cached_proof();
+
+        let call = TraceCall {
+            to: ADDRESS,
+            ..Default::default()
+        };
+        let trace = provider.trace_prestate(&call, PROOF_BLOCK).unwrap();
+        let storage = BTreeMap::from([(B256::ZERO, expected.storage_proof[0].value.into())]);
+        assert_eq!(
+            trace[&ADDRESS],
+            PrestateAccount {
+                balance: expected.balance,
+                nonce: expected.nonce,
+                code: Bytes::from_static(CODE),
+                storage,
+            }
+        );
+        assert_eq!(trace[&Address::ZERO], PrestateAccount::default());
+
+        let witness = provider.get_execution_witness(PROOF_BLOCK + 1).unwrap();
+        let mut nodes = witness.unwrap().state;
+        let mut expected_nodes = expected.account_proof;
+        nodes.sort();
+        expected_nodes.sort();
+        assert_eq!(nodes, expected_nodes);
+    }
+
+    /// Checks the provider over the transport of the URL inside and outside of a runtime.
+    fn check_transport(url: &str) {
+        let provider = AlloyProvider::connect(url).unwrap();
+        let header = provider.get_block_header(BLOCK).unwrap().unwrap();
+        assert_eq!(header.hash_slow(), cached_header(PROOF_BLOCK).parent_hash);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let proof = runtime.block_on(async {
+            let provider = AlloyProvider::connect_async(url).await.unwrap();
+            provider::AsyncProvider::get_proof(
+                &provider,
+                ADDRESS,
+                vec![StorageKey::ZERO],
+                PROOF_BLOCK,
+            )
+            .await
+            .unwrap()
+        });
+        assert_eq!(proof, cached_proof());
+    }
+
+    #[test]
+    fn mock_rpc_ws() {
+        check_transport(&mock_ws_server(handler));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mock_rpc_ipc() {
+        check_transport(&mock_ipc_server(handler));
+    }
+
+    static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+    static MAX_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+    /// Handler that records the maximum number of concurrent requests.
+    fn counting_handler(method: &str, params: &[Value]) -> Value {
+        let in_flight = IN_FLIGHT.fetch_add(1, Ordering::SeqCst) + 1;
+        MAX_IN_FLIGHT.fetch_max(in_flight, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(20));
+        let result = handler(method, params);
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+
+        result
+    }
+
+    #[test]
+    fn mock_rpc() {
+        let provider = AlloyProvider::connect(&mock_server(handler)).unwrap();
+
+        // the converted header must hash to the parent hash of its child
+        let header = provider.get_block_header(BLOCK).unwrap().unwrap();
+        assert_eq!(header.hash_slow(), cached_header(PROOF_BLOCK).parent_hash);
+        assert!(provider.get_block_header(BLOCK - 1).unwrap().is_none());
+
+        let expected = cached_proof();
+        let proof = provider
+            .get_proof(ADDRESS, vec![StorageKey::ZERO], PROOF_BLOCK)
+            .unwrap();
+        assert_eq!(proof, expected);
+
+        let nonce = provider.get_transaction_count(ADDRESS, PROOF_BLOCK);
+        assert_eq!(nonce.unwrap(), expected.nonce);
+        let balance = provider.get_balance(ADDRESS, PROOF_BLOCK);
+        assert_eq!(balance.unwrap(), expected.balance);
+        let code = provider.get_code(ADDRESS, PROOF_BLOCK);
+        assert_eq!(code.unwrap().as_ref(), CODE);
+        let value = provider.get_storage_at(ADDRESS, StorageKey::ZERO, PROOF_BLOCK);
+        assert_eq!(value.unwrap(), expected.storage_proof[0].value);
+    }
+
+    #[test]
+    fn mock_rpc_concurrency_limit() {
+        IN_FLIGHT.store(0, Ordering::SeqCst);
+        MAX_IN_FLIGHT.store(0, Ordering::SeqCst);
+        let provider = AlloyProvider::connect(&mock_server(counting_handler))
+            .unwrap()
+            .with_concurrency_limit(2);
+
+        let accounts = vec![(ADDRESS, vec![StorageKey::ZERO]); 6];
+        let proofs = provider.get_proofs(accounts, PROOF_BLOCK).unwrap();
+        assert_eq!(proofs, vec![cached_proof(); 6]);
+
+        let headers = provider.get_block_headers(BLOCK - 1..BLOCK + 1).unwrap();
+        assert!(headers[0].is_none());
+        let parent_hash = cached_header(PROOF_BLOCK).parent_hash;
+        assert_eq!(headers[1].as_ref().unwrap().hash_slow(), parent_hash);
+
+        // the requests must be sent concurrently, but never more than the limit
+        assert_eq!(MAX_IN_FLIGHT.load(Ordering::SeqCst), 2);
+    }
+}
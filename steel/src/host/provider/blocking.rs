@@ -0,0 +1,104 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{AsyncProvider, EIP1186Proof, Provider};
+use alloy_primitives::{Address, BlockNumber, Bytes, StorageKey, StorageValue, TxNumber, U256};
+use std::ops::Range;
+use tokio::runtime::Handle;
+
+/// A [Provider] that blocks on the requests of an [AsyncProvider].
+///
+/// Each request is executed on the given runtime using [Handle::block_on]. This must therefore
+/// not be used from within an async context, but only from a blocking thread, e.g. one spawned
+/// with [tokio::task::spawn_blocking].
+pub struct BlockingProvider<P> {
+    inner: P,
+    handle: Handle,
+}
+
+impl<P: AsyncProvider> BlockingProvider<P> {
+    /// Creates a new [BlockingProvider] executing the requests on the given runtime.
+    pub fn new(provider: P, handle: Handle) -> Self {
+        Self {
+            inner: provider,
+            handle,
+        }
+    }
+
+    /// Returns the underlying [AsyncProvider].
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+}
+
+impl<P: AsyncProvider> Provider for BlockingProvider<P> {
+    type Error = P::Error;
+    type Header = P::Header;
+
+    fn get_block_header(&self, block: BlockNumber) -> Result<Option<Self::Header>, Self::Error> {
+        self.handle.block_on(self.inner.get_block_header(block))
+    }
+
+    fn get_transaction_count(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> Result<TxNumber, Self::Error> {
+        self.handle
+            .block_on(self.inner.get_transaction_count(address, block))
+    }
+
+    fn get_balance(&self, address: Address, block: BlockNumber) -> Result<U256, Self::Error> {
+        self.handle.block_on(self.inner.get_balance(address, block))
+    }
+
+    fn get_code(&self, address: Address, block: BlockNumber) -> Result<Bytes, Self::Error> {
+        self.handle.block_on(self.inner.get_code(address, block))
+    }
+
+    fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: BlockNumber,
+    ) -> Result<StorageValue, Self::Error> {
+        self.handle
+            .block_on(self.inner.get_storage_at(address, key, block))
+    }
+
+    fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: BlockNumber,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        self.handle
+            .block_on(self.inner.get_proof(address, storage_keys, block))
+    }
+
+    fn get_proofs(
+        &self,
+        accounts: Vec<(Address, Vec<StorageKey>)>,
+        block: BlockNumber,
+    ) -> Result<Vec<EIP1186Proof>, Self::Error> {
+        self.handle.block_on(self.inner.get_proofs(accounts, block))
+    }
+
+    fn get_block_headers(
+        &self,
+        blocks: Range<BlockNumber>,
+    ) -> Result<Vec<Option<Self::Header>>, Self::Error> {
+        self.handle.block_on(self.inner.get_block_headers(blocks))
+    }
+}
@@ -12,10 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{EIP1186Proof, Provider, StorageProof};
-use crate::ethereum::EthBlockHeader;
-use ethers_core::types::{Block, Bytes, H160, H256, U256};
+use super::{
+    runtime_handle, AsyncProvider, EIP1186Proof, ExecutionWitness, PrestateTrace, Provider,
+    StorageProof, TraceCall, TraceProvider, DEFAULT_CONCURRENCY_LIMIT,
+};
+use crate::{
+    ethereum::EthBlockHeader,
+    host::{check_commitment, BlockSelector, CommitmentStatus},
+    SolCommitment,
+};
+use alloy_primitives::Sealable;
+use ethers_core::types::{Block, BlockNumber, Bytes, H160, H256, U256};
 use ethers_providers::{Middleware, MiddlewareError};
+use serde_json::json;
+use std::ops::Range;
 use thiserror::Error;
 use tokio::runtime::{Handle, Runtime};
 
@@ -29,51 +39,89 @@ pub enum EthersProviderError<M: MiddlewareError> {
 }
 
 /// A provider that fetches data from an Ethereum node using the ethers crate.
+///
+/// It implements both [Provider] and [AsyncProvider]. When used as a [Provider], each request
+/// blocks on the current tokio runtime or on a runtime owned by the provider, if it has been
+/// created outside of a runtime.
 pub struct EthersProvider<M: Middleware> {
     client: M,
     runtime_handle: (Handle, Option<Runtime>),
+    concurrency_limit: usize,
 }
 
 impl<M: Middleware> EthersProvider<M> {
     pub fn new(client: M) -> Self {
-        // if we are not in a tokio runtime, we need to create a new handle
-        let runtime_handle = match Handle::try_current() {
-            Ok(handle) => (handle, None),
-            Err(_) => {
-                let runtime = Runtime::new().unwrap();
-                (runtime.handle().clone(), Some(runtime))
-            }
-        };
-
         Self {
             client,
-            runtime_handle,
+            runtime_handle: runtime_handle(),
+            concurrency_limit: DEFAULT_CONCURRENCY_LIMIT,
         }
     }
 
+    /// Sets the maximum number of requests that are sent concurrently.
+    pub fn with_concurrency_limit(mut self, limit: usize) -> Self {
+        self.concurrency_limit = limit;
+        self
+    }
+
     /// Fetches the current block number.
     pub fn get_block_number(&self) -> Result<alloy_primitives::BlockNumber, M::Error> {
         Ok(self.block_on(self.client.get_block_number())?.as_u64())
     }
 
+    /// Fetches the number of the block with the given number or tag.
+    ///
+    /// It returns `None` if the node does not know such a block.
+    pub fn get_block_number_by_tag(
+        &self,
+        block: BlockSelector,
+    ) -> Result<Option<alloy_primitives::BlockNumber>, M::Error> {
+        let tag = match block {
+            BlockSelector::Number(number) => return Ok(Some(number)),
+            BlockSelector::Latest => return self.get_block_number().map(Some),
+            BlockSelector::LatestMinus(n) => {
+                return Ok(self.get_block_number()?.checked_sub(n));
+            }
+            BlockSelector::Safe => BlockNumber::Safe,
+            BlockSelector::Finalized => BlockNumber::Finalized,
+        };
+        let block = self.block_on(self.client.get_block(tag))?;
+        Ok(block.and_then(|block| block.number).map(|n| n.as_u64()))
+    }
+
+    /// Checks whether the given commitment is still valid on the current chain.
+    ///
+    /// This is equivalent to [check_commitment] with the current block number as the head.
+    pub fn check_commitment(&self, commitment: &SolCommitment) -> anyhow::Result<CommitmentStatus>
+    where
+        M::Error: 'static,
+    {
+        let head = self.get_block_number()?;
+        check_commitment(self, commitment, head)
+    }
+
     /// internal utility function to call tokio feature and wait for output
     fn block_on<F: core::future::Future>(&self, f: F) -> F::Output {
         self.runtime_handle.0.block_on(f)
     }
 }
 
-impl<M: Middleware> Provider for EthersProvider<M>
+impl<M: Middleware> AsyncProvider for EthersProvider<M>
 where
     M::Error: 'static,
 {
     type Error = EthersProviderError<M::Error>;
     type Header = EthBlockHeader;
 
-    fn get_block_header(
+    fn concurrency_limit(&self) -> usize {
+        self.concurrency_limit
+    }
+
+    async fn get_block_header(
         &self,
         block: alloy_primitives::BlockNumber,
     ) -> Result<Option<Self::Header>, Self::Error> {
-        let block = self.block_on(self.client.get_block(block))?;
+        let block = self.client.get_block(block).await?;
         match block {
             Some(block) => Ok(Some(
                 block
@@ -84,44 +132,43 @@ where
         }
     }
 
-    fn get_transaction_count(
+    async fn get_transaction_count(
         &self,
         address: alloy_primitives::Address,
         block: alloy_primitives::BlockNumber,
     ) -> Result<alloy_primitives::TxNumber, Self::Error> {
         let address = to_ethers_h160(address);
         let count = self
-            .block_on(
-                self.client
-                    .get_transaction_count(address, Some(block.into())),
-            )
+            .client
+            .get_transaction_count(address, Some(block.into()))
+            .await
             .map(from_ethers_u256)?;
         Ok(count.to())
     }
 
-    fn get_balance(
+    async fn get_balance(
         &self,
         address: alloy_primitives::Address,
         block: alloy_primitives::BlockNumber,
     ) -> Result<alloy_primitives::U256, Self::Error> {
         let address = to_ethers_h160(address);
-        Ok(from_ethers_u256(self.block_on(
-            self.client.get_balance(address, Some(block.into())),
-        )?))
+        Ok(from_ethers_u256(
+            self.client.get_balance(address, Some(block.into())).await?,
+        ))
     }
 
-    fn get_code(
+    async fn get_code(
         &self,
         address: alloy_primitives::Address,
         block: alloy_primitives::BlockNumber,
     ) -> Result<alloy_primitives::Bytes, Self::Error> {
         let address = to_ethers_h160(address);
-        Ok(from_ethers_bytes(self.block_on(
-            self.client.get_code(address, Some(block.into())),
-        )?))
+        Ok(from_ethers_bytes(
+            self.client.get_code(address, Some(block.into())).await?,
+        ))
     }
 
-    fn get_storage_at(
+    async fn get_storage_at(
         &self,
         address: alloy_primitives::Address,
         key: alloy_primitives::StorageKey,
@@ -129,12 +176,15 @@ where
     ) -> Result<alloy_primitives::StorageValue, Self::Error> {
         let address = to_ethers_h160(address);
         let key = to_ethers_h256(key);
-        let value = self.block_on(self.client.get_storage_at(address, key, Some(block.into())))?;
+        let value = self
+            .client
+            .get_storage_at(address, key, Some(block.into()))
+            .await?;
 
         Ok(from_ethers_h256(value).into())
     }
 
-    fn get_proof(
+    async fn get_proof(
         &self,
         address: alloy_primitives::Address,
         storage_keys: Vec<alloy_primitives::StorageKey>,
@@ -142,11 +192,10 @@ where
     ) -> Result<EIP1186Proof, Self::Error> {
         let address = to_ethers_h160(address);
         let storage_keys = storage_keys.into_iter().map(to_ethers_h256).collect();
-        let proof = self.block_on(self.client.get_proof(
-            address,
-            storage_keys,
-            Some(block.into()),
-        ))?;
+        let proof = self
+            .client
+            .get_proof(address, storage_keys, Some(block.into()))
+            .await?;
 
         Ok(EIP1186Proof {
             address: address.0.into(),
@@ -172,11 +221,123 @@ where
     }
 }
 
+impl<M: Middleware> Provider for EthersProvider<M>
+where
+    M::Error: 'static,
+{
+    type Error = EthersProviderError<M::Error>;
+    type Header = EthBlockHeader;
+
+    fn get_block_header(
+        &self,
+        block: alloy_primitives::BlockNumber,
+    ) -> Result<Option<Self::Header>, Self::Error> {
+        self.block_on(AsyncProvider::get_block_header(self, block))
+    }
+
+    fn get_transaction_count(
+        &self,
+        address: alloy_primitives::Address,
+        block: alloy_primitives::BlockNumber,
+    ) -> Result<alloy_primitives::TxNumber, Self::Error> {
+        self.block_on(AsyncProvider::get_transaction_count(self, address, block))
+    }
+
+    fn get_balance(
+        &self,
+        address: alloy_primitives::Address,
+        block: alloy_primitives::BlockNumber,
+    ) -> Result<alloy_primitives::U256, Self::Error> {
+        self.block_on(AsyncProvider::get_balance(self, address, block))
+    }
+
+    fn get_code(
+        &self,
+        address: alloy_primitives::Address,
+        block: alloy_primitives::BlockNumber,
+    ) -> Result<alloy_primitives::Bytes, Self::Error> {
+        self.block_on(AsyncProvider::get_code(self, address, block))
+    }
+
+    fn get_storage_at(
+        &self,
+        address: alloy_primitives::Address,
+        key: alloy_primitives::StorageKey,
+        block: alloy_primitives::BlockNumber,
+    ) -> Result<alloy_primitives::StorageValue, Self::Error> {
+        self.block_on(AsyncProvider::get_storage_at(self, address, key, block))
+    }
+
+    fn get_proof(
+        &self,
+        address: alloy_primitives::Address,
+        storage_keys: Vec<alloy_primitives::StorageKey>,
+        block: alloy_primitives::BlockNumber,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        self.block_on(AsyncProvider::get_proof(self, address, storage_keys, block))
+    }
+
+    fn get_proofs(
+        &self,
+        accounts: Vec<(alloy_primitives::Address, Vec<alloy_primitives::StorageKey>)>,
+        block: alloy_primitives::BlockNumber,
+    ) -> Result<Vec<EIP1186Proof>, Self::Error> {
+        self.block_on(AsyncProvider::get_proofs(self, accounts, block))
+    }
+
+    fn get_block_headers(
+        &self,
+        blocks: Range<alloy_primitives::BlockNumber>,
+    ) -> Result<Vec<Option<Self::Header>>, Self::Error> {
+        self.block_on(AsyncProvider::get_block_headers(self, blocks))
+    }
+}
+
+impl<M: Middleware> TraceProvider for EthersProvider<M>
+where
+    M::Error: 'static,
+{
+    fn trace_prestate(
+        &self,
+        call: &TraceCall,
+        block: alloy_primitives::BlockNumber,
+    ) -> Result<PrestateTrace, Self::Error> {
+        let params = (
+            call,
+            alloy_primitives::U64::from(block),
+            json!({ "tracer": "prestateTracer" }),
+        );
+        let trace = self
+            .block_on(self.client.provider().request("debug_traceCall", params))
+            .map_err(M::Error::from_provider_err)?;
+        Ok(trace)
+    }
+
+    fn get_execution_witness(
+        &self,
+        block: alloy_primitives::BlockNumber,
+    ) -> Result<Option<ExecutionWitness>, Self::Error> {
+        let params = [alloy_primitives::U64::from(block)];
+        let witness = self.block_on(
+            self.client
+                .provider()
+                .request("debug_executionWitness", params),
+        );
+        match witness {
+            Ok(witness) => Ok(Some(witness)),
+            // the method is not supported or the block is not yet available
+            Err(err) if err.as_error_response().is_some() => Ok(None),
+            Err(err) => Err(M::Error::from_provider_err(err).into()),
+        }
+    }
+}
+
 impl<T> TryFrom<Block<T>> for EthBlockHeader {
     type Error = String;
 
     fn try_from(block: Block<T>) -> Result<Self, Self::Error> {
-        Ok(EthBlockHeader {
+        let expected_hash = block.hash.map(from_ethers_h256);
+        let header = EthBlockHeader {
             parent_hash: from_ethers_h256(block.parent_hash),
             ommers_hash: from_ethers_h256(block.uncles_hash),
             beneficiary: block.author.ok_or("author missing")?.0.into(),
@@ -209,7 +370,20 @@ impl<T> TryFrom<Block<T>> for EthBlockHeader {
             blob_gas_used: block.blob_gas_used.map(|x| x.try_into().unwrap()),
             excess_blob_gas: block.excess_blob_gas.map(|x| x.try_into().unwrap()),
             parent_beacon_block_root: block.parent_beacon_block_root.map(from_ethers_h256),
-        })
+        };
+
+        // the header must hash to the block hash reported by the RPC
+        if let Some(expected_hash) = expected_hash {
+            let hash = header.hash_slow();
+            if hash != expected_hash {
+                return Err(format!(
+                    "header hash mismatch for block {}: expected {}, got {}",
+                    header.number, expected_hash, hash
+                ));
+            }
+        }
+
+        Ok(header)
     }
 }
 
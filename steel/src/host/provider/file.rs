@@ -12,47 +12,98 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{EIP1186Proof, NullProvider, Provider};
+use super::{EIP1186Proof, NullProvider, Provider, StorageProof};
 use crate::{ethereum::EthBlockHeader, EvmBlockHeader};
-use alloy_primitives::{Address, BlockNumber, Bytes, StorageKey, StorageValue, TxNumber, U256};
-use anyhow::Context;
+use alloy_primitives::{
+    keccak256, Address, BlockNumber, Bytes, StorageKey, StorageValue, TxNumber, B256, U256,
+};
+use anyhow::{bail, ensure, Context};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    cell::RefCell,
-    collections::{hash_map::Entry, BTreeSet, HashMap},
-    fs::{self, File},
-    io::{BufReader, BufWriter},
+    collections::{BTreeSet, HashMap},
+    fs::{self, File, OpenOptions},
+    hash::Hash,
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     marker::PhantomData,
-    path::PathBuf,
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
 };
 
-/// A provider that caches responses from an underlying provider in a JSON file.
+/// Magic bytes at the start of every frame of a binary cache file.
+const FRAME_MAGIC: [u8; 4] = *b"STLC";
+/// Version of the binary frame layout.
+const FRAME_VERSION: u8 = 1;
+/// Frame flag indicating a zstd compressed payload.
+const FLAG_ZSTD: u8 = 1;
+/// Size of the frame header: magic, version, flags, payload length and keccak256 checksum.
+const FRAME_HEADER_LEN: usize = 4 + 1 + 1 + 8 + 32;
+
+/// The format in which a [CachedProvider] stores its cache file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CacheFormat {
+    /// Human-readable JSON. The entire file is rewritten on every flush.
+    #[default]
+    Json,
+    /// Append-only sequence of bincode encoded frames. Each flush only appends the entries added
+    /// since the previous flush, and every frame is protected by a keccak256 checksum.
+    Binary,
+    /// Like [CacheFormat::Binary], but the payload of every frame is compressed using zstd.
+    Zstd,
+}
+
+/// A provider that caches responses from an underlying provider in a file.
 /// Queries are first checked against the cache, and if not found, the provider is invoked.
-/// The cache is saved when the provider is dropped.
+///
+/// Account proofs and storage proofs are cached separately, so that an EIP-1186 proof can be
+/// assembled from the cache, even if its storage keys were requested in different queries. Only
+/// the proofs that are missing are requested from the underlying provider.
+///
+/// New responses are only written to the file on [CachedProvider::flush]. As a fallback, the
+/// provider also tries to flush when it is dropped, but errors at that point can only be logged.
+///
+/// The provider is [Sync] if the underlying provider is, so that a single cache can be shared by
+/// multiple threads, e.g. using [preflight_parallel](crate::host::preflight_parallel). The cache
+/// is only locked while accessing it, and not while waiting for the underlying provider.
 pub struct CachedProvider<P: Provider>
 where
     P::Header: Clone + Serialize + DeserializeOwned,
 {
     inner: P,
-    cache: RefCell<JsonCache<P::Header>>,
+    cache: Mutex<FileCache<P::Header>>,
 }
 
 impl<P: Provider> CachedProvider<P>
 where
     P::Header: Clone + Serialize + DeserializeOwned,
 {
-    /// Creates a new [CachedProvider]. If the cache file exists, it will be read and deserialized.
-    /// Otherwise, a new file will be created when dropped.
+    /// Creates a new [CachedProvider]. If the cache file exists, it will be read and deserialized
+    /// and its format is kept. Otherwise, a new JSON file will be created on flush.
     pub fn new(cache_path: PathBuf, provider: P) -> anyhow::Result<Self> {
-        let cache = match JsonCache::from_file(cache_path.clone()) {
+        Self::open(cache_path, provider, None)
+    }
+
+    /// Creates a new [CachedProvider] storing its cache in the given format. An existing cache
+    /// file in a different format is converted on the next flush.
+    pub fn with_format(
+        cache_path: PathBuf,
+        provider: P,
+        format: CacheFormat,
+    ) -> anyhow::Result<Self> {
+        Self::open(cache_path, provider, Some(format))
+    }
+
+    fn open(cache_path: PathBuf, provider: P, format: Option<CacheFormat>) -> anyhow::Result<Self> {
+        let cache = match FileCache::from_file(cache_path.clone(), format) {
             Ok(cache) => cache,
-            Err(err) => match err.downcast_ref::<std::io::Error>() {
-                Some(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => {
-                    // create the file and directory if it doesn't exist
+            Err(err) => match err.downcast_ref::<io::Error>() {
+                Some(io_err) if io_err.kind() == io::ErrorKind::NotFound => {
+                    // create the directory if it doesn't exist
                     if let Some(parent) = cache_path.parent() {
                         fs::create_dir_all(parent).context("failed to create directory")?;
                     }
-                    JsonCache::empty(cache_path)
+                    FileCache::empty(cache_path, format.unwrap_or_default())
                 }
                 _ => return Err(err),
             },
@@ -60,9 +111,44 @@ where
 
         Ok(Self {
             inner: provider,
-            cache: RefCell::new(cache),
+            cache: Mutex::new(cache),
         })
     }
+
+    /// Writes all the responses that have not been saved yet to the cache file.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.cache().flush()
+    }
+
+    /// Rewrites the entire cache file. For binary formats, this merges all the appended frames
+    /// into a single one.
+    pub fn compact(&self) -> anyhow::Result<()> {
+        self.cache().compact()
+    }
+
+    /// Locks the cache. A poisoned lock is ignored, as entries are always inserted atomically.
+    fn cache(&self) -> MutexGuard<'_, FileCache<P::Header>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the cached value for the query or calls `f` and caches its result.
+    fn cached<K, V>(
+        &self,
+        map: fn(&mut CacheData<P::Header>) -> &mut HashMap<K, V>,
+        query: K,
+        f: impl FnOnce() -> Result<V, P::Error>,
+    ) -> Result<V, P::Error>
+    where
+        K: Eq + Hash + Clone,
+        V: Clone,
+    {
+        if let Some(value) = map(&mut self.cache().data).get(&query).cloned() {
+            return Ok(value);
+        }
+        let value = f()?;
+        self.cache().insert(map, query, value.clone());
+        Ok(value)
+    }
 }
 
 /// [FileProvider] for Ethereum.
@@ -76,12 +162,12 @@ impl<H> FileProvider<H>
 where
     H: EvmBlockHeader + Clone + Serialize + DeserializeOwned,
 {
-    /// Creates a new [FileProvider] loading the given file.
+    /// Creates a new [FileProvider] loading the given file in any [CacheFormat].
     pub fn from_file(file_path: &PathBuf) -> anyhow::Result<Self> {
-        let cache = JsonCache::load(file_path)?;
+        let (data, _) = CacheData::load(file_path)?;
         Ok(Self {
             inner: NullProvider(PhantomData),
-            cache: RefCell::new(cache),
+            cache: Mutex::new(FileCache::read_only(data)),
         })
     }
 }
@@ -94,15 +180,11 @@ where
     type Header = P::Header;
 
     fn get_block_header(&self, block: BlockNumber) -> Result<Option<Self::Header>, Self::Error> {
-        match self
-            .cache
-            .borrow_mut()
-            .partial_blocks
-            .entry(BlockQuery { block_no: block })
-        {
-            Entry::Occupied(entry) => Ok(entry.get().clone()),
-            Entry::Vacant(entry) => Ok(entry.insert(self.inner.get_block_header(block)?).clone()),
-        }
+        self.cached(
+            |data| &mut data.partial_blocks,
+            BlockQuery { block_no: block },
+            || self.inner.get_block_header(block),
+        )
     }
 
     fn get_transaction_count(
@@ -110,46 +192,36 @@ where
         address: Address,
         block: BlockNumber,
     ) -> Result<TxNumber, Self::Error> {
-        match self
-            .cache
-            .borrow_mut()
-            .transaction_count
-            .entry(AccountQuery {
+        self.cached(
+            |data| &mut data.transaction_count,
+            AccountQuery {
                 block_no: block,
                 address,
-            }) {
-            Entry::Occupied(entry) => Ok(*entry.get()),
-            Entry::Vacant(entry) => {
-                let count = self.inner.get_transaction_count(address, block)?;
-                Ok(*entry.insert(count))
-            }
-        }
+            },
+            || self.inner.get_transaction_count(address, block),
+        )
     }
 
     fn get_balance(&self, address: Address, block: BlockNumber) -> Result<U256, Self::Error> {
-        match self.cache.borrow_mut().balance.entry(AccountQuery {
-            block_no: block,
-            address,
-        }) {
-            Entry::Occupied(entry) => Ok(*entry.get()),
-            Entry::Vacant(entry) => {
-                let balance = self.inner.get_balance(address, block)?;
-                Ok(*entry.insert(balance))
-            }
-        }
+        self.cached(
+            |data| &mut data.balance,
+            AccountQuery {
+                block_no: block,
+                address,
+            },
+            || self.inner.get_balance(address, block),
+        )
     }
 
     fn get_code(&self, address: Address, block: BlockNumber) -> Result<Bytes, Self::Error> {
-        match self.cache.borrow_mut().code.entry(AccountQuery {
-            block_no: block,
-            address,
-        }) {
-            Entry::Occupied(entry) => Ok(entry.get().clone()),
-            Entry::Vacant(entry) => {
-                let code = self.inner.get_code(address, block)?;
-                Ok(entry.insert(code).clone())
-            }
-        }
+        self.cached(
+            |data| &mut data.code,
+            AccountQuery {
+                block_no: block,
+                address,
+            },
+            || self.inner.get_code(address, block),
+        )
     }
 
     fn get_storage_at(
@@ -158,17 +230,15 @@ where
         key: StorageKey,
         block: BlockNumber,
     ) -> Result<StorageValue, Self::Error> {
-        match self.cache.borrow_mut().storage.entry(StorageQuery {
-            block_no: block,
-            address,
-            key,
-        }) {
-            Entry::Occupied(entry) => Ok(*entry.get()),
-            Entry::Vacant(entry) => {
-                let storage = self.inner.get_storage_at(address, key, block)?;
-                Ok(*entry.insert(storage))
-            }
-        }
+        self.cached(
+            |data| &mut data.storage,
+            StorageQuery {
+                block_no: block,
+                address,
+                key,
+            },
+            || self.inner.get_storage_at(address, key, block),
+        )
     }
 
     fn get_proof(
@@ -177,30 +247,98 @@ where
         storage_keys: Vec<StorageKey>,
         block: BlockNumber,
     ) -> Result<EIP1186Proof, Self::Error> {
-        match self.cache.borrow_mut().proofs.entry(ProofQuery {
-            block_no: block,
-            address,
-            storage_keys: storage_keys.iter().cloned().collect(),
-        }) {
-            Entry::Occupied(entry) => Ok(entry.get().clone()),
-            Entry::Vacant(entry) => {
-                let proof = self.inner.get_proof(address, storage_keys, block)?;
-                Ok(entry.insert(proof).clone())
+        // only request the storage proofs that are not cached yet
+        let missing_keys = self
+            .cache()
+            .data
+            .missing_proof_keys(address, &storage_keys, block);
+        if let Some(keys) = missing_keys {
+            let proof = self.inner.get_proof(address, keys, block)?;
+            self.cache().insert_proof(proof, block);
+        }
+
+        Ok(self
+            .cache()
+            .data
+            .assemble_proof(address, &storage_keys, block))
+    }
+
+    fn get_proofs(
+        &self,
+        accounts: Vec<(Address, Vec<StorageKey>)>,
+        block: BlockNumber,
+    ) -> Result<Vec<EIP1186Proof>, Self::Error> {
+        // request all the proofs missing in the cache at once
+        let missing: Vec<_> = {
+            let cache = self.cache();
+            accounts
+                .iter()
+                .filter_map(|(address, storage_keys)| {
+                    let keys = cache
+                        .data
+                        .missing_proof_keys(*address, storage_keys, block)?;
+                    Some((*address, keys))
+                })
+                .collect()
+        };
+        let proofs = self.inner.get_proofs(missing, block)?;
+
+        let mut cache = self.cache();
+        for proof in proofs {
+            cache.insert_proof(proof, block);
+        }
+        Ok(accounts
+            .iter()
+            .map(|(address, storage_keys)| cache.data.assemble_proof(*address, storage_keys, block))
+            .collect())
+    }
+
+    fn get_block_headers(
+        &self,
+        blocks: Range<BlockNumber>,
+    ) -> Result<Vec<Option<Self::Header>>, Self::Error> {
+        // request the range spanning all the headers missing in the cache
+        let missing: Vec<_> = {
+            let cache = self.cache();
+            blocks
+                .clone()
+                .filter(|&block_no| {
+                    !cache
+                        .data
+                        .partial_blocks
+                        .contains_key(&BlockQuery { block_no })
+                })
+                .collect()
+        };
+        if let (Some(&first), Some(&last)) = (missing.first(), missing.last()) {
+            let headers = self.inner.get_block_headers(first..last + 1)?;
+            let mut cache = self.cache();
+            for (block_no, header) in (first..).zip(headers) {
+                cache.insert(
+                    |data| &mut data.partial_blocks,
+                    BlockQuery { block_no },
+                    header,
+                );
             }
         }
+
+        let cache = self.cache();
+        Ok(blocks
+            .map(|block_no| cache.data.partial_blocks[&BlockQuery { block_no }].clone())
+            .collect())
     }
 }
 
-/// A simple JSON cache for storing responses from a provider.
+/// The responses of a provider stored in a cache.
 #[derive(Debug, Deserialize, Serialize)]
-struct JsonCache<H: DeserializeOwned + Serialize> {
-    #[serde(skip)]
-    file_path: Option<PathBuf>,
-
+struct CacheData<H: DeserializeOwned + Serialize> {
     #[serde(with = "ordered_map")]
     partial_blocks: HashMap<BlockQuery, Option<H>>,
-    #[serde(with = "ordered_map")]
-    proofs: HashMap<ProofQuery, EIP1186Proof>,
+    /// Account proofs without any storage proofs.
+    #[serde(default, with = "ordered_map")]
+    account_proofs: HashMap<AccountQuery, EIP1186Proof>,
+    #[serde(default, with = "ordered_map")]
+    storage_proofs: HashMap<StorageQuery, StorageProof>,
     #[serde(with = "ordered_map")]
     transaction_count: HashMap<AccountQuery, TxNumber>,
     #[serde(with = "ordered_map")]
@@ -211,49 +349,409 @@ struct JsonCache<H: DeserializeOwned + Serialize> {
     storage: HashMap<StorageQuery, StorageValue>,
 }
 
-impl<H: DeserializeOwned + Serialize> JsonCache<H> {
-    /// Creates a new empty cache. It will be saved to the given file when dropped.
-    fn empty(file_path: PathBuf) -> Self {
+impl<H: DeserializeOwned + Serialize> Default for CacheData<H> {
+    fn default() -> Self {
         Self {
-            file_path: Some(file_path),
             partial_blocks: HashMap::new(),
-            proofs: HashMap::new(),
+            account_proofs: HashMap::new(),
+            storage_proofs: HashMap::new(),
             transaction_count: HashMap::new(),
             balance: HashMap::new(),
             code: HashMap::new(),
             storage: HashMap::new(),
         }
     }
+}
 
-    /// Creates a new cache backed by the given file. It updates the file when dropped.
-    fn from_file(file_path: PathBuf) -> anyhow::Result<Self> {
-        Self::load(&file_path).map(|mut cache| {
-            cache.file_path = Some(file_path);
-            cache
-        })
+impl<H: DeserializeOwned + Serialize> CacheData<H> {
+    /// Merges all the entries of `other` into `self`.
+    fn extend(&mut self, other: Self) {
+        self.partial_blocks.extend(other.partial_blocks);
+        self.account_proofs.extend(other.account_proofs);
+        self.storage_proofs.extend(other.storage_proofs);
+        self.transaction_count.extend(other.transaction_count);
+        self.balance.extend(other.balance);
+        self.code.extend(other.code);
+        self.storage.extend(other.storage);
     }
 
-    /// Loads a cache from a file. Nothing is saved when the cache is dropped.
-    fn load(file_path: &PathBuf) -> anyhow::Result<Self> {
+    /// Returns the storage keys that must be requested to assemble the account's proof, or `None`
+    /// if the proof is completely cached.
+    fn missing_proof_keys(
+        &self,
+        address: Address,
+        storage_keys: &[StorageKey],
+        block: BlockNumber,
+    ) -> Option<Vec<StorageKey>> {
+        let missing: Vec<_> = storage_keys
+            .iter()
+            .filter(|&&key| {
+                !self.storage_proofs.contains_key(&StorageQuery {
+                    block_no: block,
+                    address,
+                    key,
+                })
+            })
+            .cloned()
+            .collect();
+        let account = AccountQuery {
+            block_no: block,
+            address,
+        };
+        (!missing.is_empty() || !self.account_proofs.contains_key(&account)).then_some(missing)
+    }
+
+    /// Assembles the account's proof for the given storage keys from the cached proofs.
+    ///
+    /// It panics if any of the proofs is not cached.
+    fn assemble_proof(
+        &self,
+        address: Address,
+        storage_keys: &[StorageKey],
+        block: BlockNumber,
+    ) -> EIP1186Proof {
+        let mut proof = self.account_proofs[&AccountQuery {
+            block_no: block,
+            address,
+        }]
+            .clone();
+        proof.storage_proof = storage_keys
+            .iter()
+            .map(|&key| {
+                self.storage_proofs[&StorageQuery {
+                    block_no: block,
+                    address,
+                    key,
+                }]
+                    .clone()
+            })
+            .collect();
+        proof
+    }
+
+    /// Loads the data from a file, detecting its format. It also returns the format and the
+    /// length of the valid prefix of the file.
+    fn load(file_path: &PathBuf) -> anyhow::Result<(Self, (CacheFormat, u64))> {
         let file = File::open(file_path).context("failed to open cache file")?;
-        let reader = BufReader::new(file);
-        serde_json::from_reader(reader).context("failed to deserialize cache")
+        let mut reader = BufReader::new(file);
+        if !reader.fill_buf()?.starts_with(&FRAME_MAGIC) {
+            let json: JsonCacheData<H> =
+                serde_json::from_reader(reader).context("failed to deserialize JSON cache")?;
+            return Ok((json.into(), (CacheFormat::Json, 0)));
+        }
+
+        let mut data = Self::default();
+        let mut format = CacheFormat::Binary;
+        let mut offset = 0;
+        while let Some((frame, compressed)) = read_frame(&mut reader, &mut offset)? {
+            if compressed {
+                format = CacheFormat::Zstd;
+            }
+            let frame: Self =
+                bincode::deserialize(&frame).context("failed to deserialize cache frame")?;
+            data.extend(frame);
+        }
+
+        Ok((data, (format, offset)))
     }
+}
 
-    /// Saves the cache to the file.
-    fn save(&self) -> anyhow::Result<()> {
-        if let Some(file_path) = &self.file_path {
-            let file = File::create(file_path).context("failed to create cache file")?;
-            let writer = BufWriter::new(file);
-            serde_json::to_writer_pretty(writer, self).context("failed to serialize cache")?;
+/// JSON representation of [CacheData]. It also accepts caches with complete proofs per query, as
+/// stored by previous versions, which are split on load.
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct JsonCacheData<H: DeserializeOwned + Serialize> {
+    #[serde(flatten)]
+    data: CacheData<H>,
+    #[serde(default, with = "ordered_map")]
+    proofs: HashMap<ProofQuery, EIP1186Proof>,
+}
+
+impl<H: DeserializeOwned + Serialize> From<JsonCacheData<H>> for CacheData<H> {
+    fn from(json: JsonCacheData<H>) -> Self {
+        let mut data = json.data;
+        for (query, mut proof) in json.proofs {
+            for storage_proof in mem::take(&mut proof.storage_proof) {
+                let storage_query = StorageQuery {
+                    block_no: query.block_no,
+                    address: query.address,
+                    key: storage_proof.key,
+                };
+                data.storage_proofs.insert(storage_query, storage_proof);
+            }
+            let account_query = AccountQuery {
+                block_no: query.block_no,
+                address: query.address,
+            };
+            data.account_proofs.insert(account_query, proof);
+        }
+        data
+    }
+}
+
+/// Reads the next frame at `offset` and returns its decompressed payload. On success, `offset` is
+/// advanced to the end of the frame. A truncated frame at the end of the file, e.g. from an
+/// interrupted append, is ignored.
+fn read_frame(reader: &mut impl Read, offset: &mut u64) -> anyhow::Result<Option<(Vec<u8>, bool)>> {
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    match read_exact_or_eof(reader, &mut header)? {
+        0 => return Ok(None),
+        n if n < FRAME_HEADER_LEN => {
+            log::warn!("ignoring truncated cache frame at offset {}", offset);
+            return Ok(None);
         }
+        _ => {}
+    }
+    ensure!(
+        header[..4] == FRAME_MAGIC,
+        "invalid cache frame at offset {}",
+        offset
+    );
+    ensure!(
+        header[4] == FRAME_VERSION,
+        "unsupported cache frame version: {}",
+        header[4]
+    );
+    let flags = header[5];
+    let len = u64::from_le_bytes(header[6..14].try_into().unwrap());
+    let checksum = B256::from_slice(&header[14..]);
+
+    let mut payload = vec![0u8; len.try_into().context("cache frame too large")?];
+    if read_exact_or_eof(reader, &mut payload)? < payload.len() {
+        log::warn!("ignoring truncated cache frame at offset {}", offset);
+        return Ok(None);
+    }
+    if keccak256(&payload) != checksum {
+        bail!(
+            "cache file corrupted: checksum mismatch at offset {}",
+            offset
+        );
+    }
+    *offset += (FRAME_HEADER_LEN + payload.len()) as u64;
+
+    let compressed = flags & FLAG_ZSTD != 0;
+    if compressed {
+        payload = zstd::decode_all(payload.as_slice()).context("failed to decompress frame")?;
+    }
+    Ok(Some((payload, compressed)))
+}
+
+/// Reads until `buf` is full or EOF is reached and returns the number of bytes read.
+fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+/// Encodes the data as a single frame of a binary cache file.
+fn encode_frame<H: DeserializeOwned + Serialize>(
+    data: &CacheData<H>,
+    compress: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let mut payload = bincode::serialize(data).context("failed to serialize cache")?;
+    let mut flags = 0;
+    if compress {
+        payload = zstd::encode_all(payload.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL)
+            .context("failed to compress cache")?;
+        flags |= FLAG_ZSTD;
+    }
+
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&FRAME_MAGIC);
+    frame.push(FRAME_VERSION);
+    frame.push(flags);
+    frame.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    frame.extend_from_slice(keccak256(&payload).as_slice());
+    frame.extend_from_slice(&payload);
+
+    Ok(frame)
+}
+
+/// Atomically replaces the file with the output of `write` by writing to a temporary file first.
+fn write_atomic(
+    file_path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut tmp_path = file_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let file = File::create(&tmp_path).context("failed to create temporary cache file")?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    let file = writer.into_inner().context("failed to write cache file")?;
+    file.sync_all().context("failed to sync cache file")?;
+    fs::rename(&tmp_path, file_path).context("failed to replace cache file")?;
+
+    Ok(())
+}
+
+/// The file backing a [FileCache].
+#[derive(Debug)]
+struct CacheFile {
+    path: PathBuf,
+    format: CacheFormat,
+    /// The offset at which new frames are appended, or `None` if the file must be rewritten.
+    append_offset: Option<u64>,
+}
+
+/// A cache for storing responses from a provider, optionally backed by a file.
+#[derive(Debug)]
+struct FileCache<H: DeserializeOwned + Serialize> {
+    data: CacheData<H>,
+    /// Entries that have not been appended to the file yet. Only tracked for binary formats.
+    pending: CacheData<H>,
+    dirty: bool,
+    file: Option<CacheFile>,
+}
+
+impl<H: DeserializeOwned + Serialize> FileCache<H> {
+    /// Creates a new empty cache. It will be saved to the given file on flush.
+    fn empty(file_path: PathBuf, format: CacheFormat) -> Self {
+        Self {
+            data: CacheData::default(),
+            pending: CacheData::default(),
+            dirty: false,
+            file: Some(CacheFile {
+                path: file_path,
+                format,
+                append_offset: None,
+            }),
+        }
+    }
+
+    /// Creates a cache containing the given data, which is never saved.
+    fn read_only(data: CacheData<H>) -> Self {
+        Self {
+            data,
+            pending: CacheData::default(),
+            dirty: false,
+            file: None,
+        }
+    }
+
+    /// Creates a new cache backed by the given file. If `format` is `None`, the format of the
+    /// existing file is used.
+    fn from_file(file_path: PathBuf, format: Option<CacheFormat>) -> anyhow::Result<Self> {
+        let (data, (file_format, valid_len)) = CacheData::load(&file_path)?;
+        let format = format.unwrap_or(file_format);
+        // binary frames can be appended independent of their compression
+        let append_offset =
+            (file_format != CacheFormat::Json && format != CacheFormat::Json).then_some(valid_len);
+
+        Ok(Self {
+            data,
+            pending: CacheData::default(),
+            // conversions are written on the next flush
+            dirty: append_offset.is_none() && format != file_format,
+            file: Some(CacheFile {
+                path: file_path,
+                format,
+                append_offset,
+            }),
+        })
+    }
+
+    /// Inserts a new entry into the cache, unless the key is already present.
+    fn insert<K, V>(&mut self, map: fn(&mut CacheData<H>) -> &mut HashMap<K, V>, key: K, value: V)
+    where
+        K: Eq + Hash + Clone,
+        V: Clone,
+    {
+        if map(&mut self.data).contains_key(&key) {
+            return;
+        }
+        if matches!(&self.file, Some(file) if file.format != CacheFormat::Json) {
+            map(&mut self.pending).insert(key.clone(), value.clone());
+        }
+        map(&mut self.data).insert(key, value);
+        self.dirty = true;
+    }
+
+    /// Splits the proof into its account proof and storage proofs and inserts them separately.
+    fn insert_proof(&mut self, mut proof: EIP1186Proof, block: BlockNumber) {
+        let address = proof.address;
+        for storage_proof in mem::take(&mut proof.storage_proof) {
+            let query = StorageQuery {
+                block_no: block,
+                address,
+                key: storage_proof.key,
+            };
+            self.insert(|data| &mut data.storage_proofs, query, storage_proof);
+        }
+        let query = AccountQuery {
+            block_no: block,
+            address,
+        };
+        self.insert(|data| &mut data.account_proofs, query, proof);
+    }
+
+    /// Saves all the new entries to the file.
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+
+        match (file.format, file.append_offset) {
+            (CacheFormat::Json, _) => write_atomic(&file.path, |writer| {
+                serde_json::to_writer_pretty(writer, &self.data)
+                    .context("failed to serialize cache")
+            })?,
+            (format, Some(offset)) => {
+                let frame = encode_frame(&self.pending, format == CacheFormat::Zstd)?;
+                let mut f = OpenOptions::new()
+                    .write(true)
+                    .open(&file.path)
+                    .context("failed to open cache file")?;
+                // drop any truncated frame left by an interrupted append
+                f.set_len(offset)?;
+                f.seek(SeekFrom::Start(offset))?;
+                f.write_all(&frame)
+                    .context("failed to append to cache file")?;
+                f.sync_data().context("failed to sync cache file")?;
+                file.append_offset = Some(offset + frame.len() as u64);
+            }
+            (format, None) => {
+                let frame = encode_frame(&self.data, format == CacheFormat::Zstd)?;
+                write_atomic(&file.path, |writer| {
+                    writer
+                        .write_all(&frame)
+                        .context("failed to write cache file")
+                })?;
+                file.append_offset = Some(frame.len() as u64);
+            }
+        }
+        self.pending = CacheData::default();
+        self.dirty = false;
+
         Ok(())
     }
+
+    /// Rewrites the entire file.
+    fn compact(&mut self) -> anyhow::Result<()> {
+        if let Some(file) = &mut self.file {
+            file.append_offset = None;
+            self.dirty = true;
+        }
+        self.flush()
+    }
 }
 
-impl<H: DeserializeOwned + Serialize> Drop for JsonCache<H> {
+impl<H: DeserializeOwned + Serialize> Drop for FileCache<H> {
     fn drop(&mut self) {
-        self.save().expect("failed to save cache");
+        if let Err(err) = self.flush() {
+            log::warn!("failed to save cache: {:#}", err);
+        }
     }
 }
 
@@ -268,6 +766,7 @@ struct BlockQuery {
     block_no: BlockNumber,
 }
 
+/// Query of a complete proof, only used by the legacy JSON cache layout.
 #[derive(Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
 struct ProofQuery {
     block_no: BlockNumber,
@@ -309,3 +808,200 @@ mod ordered_map {
         Ok(vec.into_iter().collect())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::provider::{GenesisAccount, MemoryProvider};
+    use alloy_primitives::address;
+
+    const ADDRESS: Address = address!("1111111111111111111111111111111111111111");
+    const BLOCK: BlockNumber = 100;
+
+    fn memory_provider() -> MemoryProvider {
+        let account = GenesisAccount {
+            balance: U256::from(1000),
+            nonce: 1,
+            storage: [(B256::ZERO, B256::with_last_byte(42))].into(),
+            ..Default::default()
+        };
+        MemoryProvider::new()
+            .with_account(ADDRESS, account)
+            .with_block_number(BLOCK)
+    }
+
+    fn cache_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("steel-cache-{}", std::process::id()));
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn roundtrip(format: CacheFormat) {
+        let path = cache_path(&format!("{:?}", format));
+        {
+            let provider =
+                CachedProvider::with_format(path.clone(), memory_provider(), format).unwrap();
+            provider.get_block_headers(BLOCK - 2..BLOCK + 1).unwrap();
+            provider.get_balance(ADDRESS, BLOCK).unwrap();
+            provider.flush().unwrap();
+            provider
+                .get_proof(ADDRESS, vec![B256::ZERO], BLOCK)
+                .unwrap();
+            provider.flush().unwrap();
+        }
+
+        let expected = memory_provider();
+        let provider = EthFileProvider::from_file(&path).unwrap();
+        let header = provider.get_block_header(BLOCK).unwrap().unwrap();
+        assert_eq!(header.state_root, expected.state_root());
+        assert_eq!(
+            provider.get_balance(ADDRESS, BLOCK).unwrap(),
+            U256::from(1000)
+        );
+        assert_eq!(
+            provider
+                .get_proof(ADDRESS, vec![B256::ZERO], BLOCK)
+                .unwrap(),
+            expected
+                .get_proof(ADDRESS, vec![B256::ZERO], BLOCK)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn roundtrip_json() {
+        roundtrip(CacheFormat::Json);
+    }
+
+    #[test]
+    fn roundtrip_binary() {
+        roundtrip(CacheFormat::Binary);
+    }
+
+    #[test]
+    fn roundtrip_zstd() {
+        roundtrip(CacheFormat::Zstd);
+    }
+
+    #[test]
+    fn append_only() {
+        let path = cache_path("append");
+        let provider =
+            CachedProvider::with_format(path.clone(), memory_provider(), CacheFormat::Binary)
+                .unwrap();
+        provider.get_balance(ADDRESS, BLOCK).unwrap();
+        provider.flush().unwrap();
+        let first_len = fs::metadata(&path).unwrap().len();
+
+        // flushing without new entries does not touch the file
+        provider.get_balance(ADDRESS, BLOCK).unwrap();
+        provider.flush().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), first_len);
+
+        // new entries are appended as a new frame
+        provider.get_transaction_count(ADDRESS, BLOCK).unwrap();
+        provider.flush().unwrap();
+        let second_len = fs::metadata(&path).unwrap().len();
+        assert!(second_len > first_len);
+
+        // compaction merges all the frames into one
+        provider.compact().unwrap();
+        let compacted_len = fs::metadata(&path).unwrap().len();
+        assert!(compacted_len < second_len);
+
+        let provider = EthFileProvider::from_file(&path).unwrap();
+        assert_eq!(provider.get_transaction_count(ADDRESS, BLOCK).unwrap(), 1);
+    }
+
+    #[test]
+    fn corrupted() {
+        let path = cache_path("corrupted");
+        {
+            let provider =
+                CachedProvider::with_format(path.clone(), memory_provider(), CacheFormat::Zstd)
+                    .unwrap();
+            provider.get_balance(ADDRESS, BLOCK).unwrap();
+        }
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, bytes).unwrap();
+
+        let err = EthFileProvider::from_file(&path).err().unwrap();
+        assert!(err.to_string().contains("checksum mismatch"), "{:#}", err);
+    }
+
+    fn truncated(format: CacheFormat) {
+        let path = cache_path(&format!("truncated-{:?}", format));
+        let provider =
+            CachedProvider::with_format(path.clone(), memory_provider(), format).unwrap();
+        provider.get_balance(ADDRESS, BLOCK).unwrap();
+        provider.flush().unwrap();
+        provider.get_transaction_count(ADDRESS, BLOCK).unwrap();
+        provider.flush().unwrap();
+        drop(provider);
+
+        // simulate an interrupted append of the second frame
+        let len = fs::metadata(&path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+
+        // the truncated frame is dropped and overwritten by the next append
+        let provider = CachedProvider::new(path.clone(), memory_provider()).unwrap();
+        assert!(!provider
+            .cache()
+            .data
+            .transaction_count
+            .contains_key(&AccountQuery {
+                block_no: BLOCK,
+                address: ADDRESS,
+            }));
+        provider.get_code(ADDRESS, BLOCK).unwrap();
+        provider.flush().unwrap();
+        drop(provider);
+
+        let provider = EthFileProvider::from_file(&path).unwrap();
+        provider.get_balance(ADDRESS, BLOCK).unwrap();
+        provider.get_code(ADDRESS, BLOCK).unwrap();
+    }
+
+    #[test]
+    fn truncated_binary() {
+        truncated(CacheFormat::Binary);
+    }
+
+    #[test]
+    fn truncated_zstd() {
+        truncated(CacheFormat::Zstd);
+    }
+
+    #[test]
+    fn composable_proofs() {
+        let (a, b) = (B256::ZERO, B256::with_last_byte(1));
+        let path = cache_path("composable");
+        {
+            let provider = CachedProvider::new(path.clone(), memory_provider()).unwrap();
+            provider.get_proof(ADDRESS, vec![a], BLOCK).unwrap();
+            provider
+                .get_proofs(vec![(ADDRESS, vec![b])], BLOCK)
+                .unwrap();
+            provider.flush().unwrap();
+        }
+
+        // the file provider must assemble the proofs without querying the (panicking) provider
+        let expected = memory_provider();
+        let provider = EthFileProvider::from_file(&path).unwrap();
+        assert_eq!(
+            provider.get_proof(ADDRESS, vec![b, a], BLOCK).unwrap(),
+            expected.get_proof(ADDRESS, vec![b, a], BLOCK).unwrap()
+        );
+        assert_eq!(
+            provider.get_proofs(vec![(ADDRESS, vec![])], BLOCK).unwrap(),
+            expected.get_proofs(vec![(ADDRESS, vec![])], BLOCK).unwrap()
+        );
+    }
+}
@@ -0,0 +1,447 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{quantity, EIP1186Proof, Provider, StorageProof};
+use crate::{
+    ethereum::EthBlockHeader, host::BLOCKHASH_WINDOW, mpt::EMPTY_ROOT_HASH, MerkleTrie,
+    StateAccount, KECCAK_EMPTY,
+};
+use alloy_primitives::{
+    b256, keccak256, Address, BlockNumber, Bloom, Bytes, Sealable, StorageKey, StorageValue,
+    TxNumber, B256, B64, U256,
+};
+use anyhow::Context;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+/// Hash of the RLP encoding of an empty list, i.e. the ommers hash of post-merge blocks.
+const EMPTY_OMMERS_HASH: B256 =
+    b256!("1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347");
+
+/// An error that can occur when querying a [MemoryProvider].
+#[derive(Error, Debug)]
+pub enum MemoryProviderError {
+    #[error("unknown block: {0}")]
+    UnknownBlock(BlockNumber),
+}
+
+/// An account of a [MemoryProvider] in the format of the `alloc` field of a genesis file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GenesisAccount {
+    #[serde(default)]
+    pub balance: U256,
+    #[serde(default, with = "quantity")]
+    pub nonce: TxNumber,
+    #[serde(default)]
+    pub code: Bytes,
+    #[serde(default)]
+    pub storage: BTreeMap<B256, B256>,
+}
+
+/// A provider serving a state that is completely defined in memory.
+///
+/// The state roots and all EIP-1186 proofs are computed locally, so that the provider can be used
+/// to test contracts with Steel without any RPC node or cache file. All the blocks share the same
+/// state: the head block, configured with [MemoryProvider::with_header], and its
+/// [BLOCKHASH_WINDOW] ancestors.
+#[derive(Debug, Clone)]
+pub struct MemoryProvider {
+    accounts: BTreeMap<Address, GenesisAccount>,
+    header: EthBlockHeader,
+
+    /// The state derived from the accounts, it is only computed on the first query.
+    state: OnceCell<MemoryState>,
+}
+
+#[derive(Debug, Clone)]
+struct MemoryState {
+    state_trie: MerkleTrie,
+    storage_tries: HashMap<Address, MerkleTrie>,
+    headers: BTreeMap<BlockNumber, EthBlockHeader>,
+}
+
+impl Default for MemoryProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryProvider {
+    /// Creates a new [MemoryProvider] without any accounts, where the head is the block 0.
+    pub fn new() -> Self {
+        let header = EthBlockHeader {
+            parent_hash: B256::ZERO,
+            ommers_hash: EMPTY_OMMERS_HASH,
+            beneficiary: Address::ZERO,
+            state_root: EMPTY_ROOT_HASH,
+            transactions_root: EMPTY_ROOT_HASH,
+            receipts_root: EMPTY_ROOT_HASH,
+            logs_bloom: Bloom::ZERO,
+            difficulty: U256::ZERO,
+            number: 0,
+            gas_limit: 30_000_000,
+            gas_used: 0,
+            timestamp: 0,
+            extra_data: Bytes::new(),
+            mix_hash: B256::ZERO,
+            nonce: B64::ZERO,
+            base_fee_per_gas: U256::ZERO,
+            withdrawals_root: Some(EMPTY_ROOT_HASH),
+            blob_gas_used: Some(0),
+            excess_blob_gas: Some(0),
+            parent_beacon_block_root: Some(B256::ZERO),
+        };
+        Self {
+            accounts: BTreeMap::new(),
+            header,
+            state: OnceCell::new(),
+        }
+    }
+
+    /// Creates a new [MemoryProvider] from a genesis file.
+    ///
+    /// Both a complete genesis file and only its `alloc` field are accepted.
+    pub fn from_genesis(json: &str) -> anyhow::Result<Self> {
+        let mut genesis: Value = serde_json::from_str(json).context("invalid JSON")?;
+        let alloc = match genesis.get_mut("alloc") {
+            Some(alloc) => alloc.take(),
+            None => genesis,
+        };
+        let accounts: BTreeMap<Address, GenesisAccount> =
+            serde_json::from_value(alloc).context("invalid genesis alloc")?;
+
+        Ok(accounts
+            .into_iter()
+            .fold(Self::new(), |provider, (address, account)| {
+                provider.with_account(address, account)
+            }))
+    }
+
+    /// Adds the account with the given address, replacing any existing account.
+    pub fn with_account(mut self, address: Address, account: GenesisAccount) -> Self {
+        self.accounts.insert(address, account);
+        self.state = OnceCell::new();
+        self
+    }
+
+    /// Sets the number of the head block.
+    pub fn with_block_number(mut self, number: BlockNumber) -> Self {
+        self.header.number = number;
+        self.state = OnceCell::new();
+        self
+    }
+
+    /// Sets the header of the head block.
+    ///
+    /// The state root of the header is always replaced with the root of the provider's state.
+    /// Its ancestors are derived from the header, differing only in their number, timestamp and
+    /// parent hash.
+    pub fn with_header(mut self, header: EthBlockHeader) -> Self {
+        self.header = header;
+        self.state = OnceCell::new();
+        self
+    }
+
+    /// Returns the number of the head block.
+    pub fn block_number(&self) -> BlockNumber {
+        self.header.number
+    }
+
+    /// Returns the root of the state trie.
+    pub fn state_root(&self) -> B256 {
+        self.state().state_trie.hash_slow()
+    }
+
+    fn state(&self) -> &MemoryState {
+        self.state.get_or_init(|| {
+            let storage_tries: HashMap<_, _> = self
+                .accounts
+                .iter()
+                .map(|(address, account)| (*address, storage_trie(account)))
+                .collect();
+            let state_trie = MerkleTrie::from_leaves(self.accounts.iter().map(|(address, acc)| {
+                let account = StateAccount {
+                    nonce: acc.nonce,
+                    balance: acc.balance,
+                    storage_root: storage_tries[address].hash_slow(),
+                    code_hash: code_hash(&acc.code),
+                };
+                (keccak256(address), alloy_rlp::encode(account))
+            }));
+
+            // derive the head block and its ancestors, starting with the oldest one
+            let mut headers = BTreeMap::new();
+            let mut parent_hash = B256::ZERO;
+            let first = self.header.number.saturating_sub(BLOCKHASH_WINDOW);
+            for number in first..=self.header.number {
+                let age = self.header.number - number;
+                let header = EthBlockHeader {
+                    parent_hash,
+                    state_root: state_trie.hash_slow(),
+                    number,
+                    timestamp: self.header.timestamp.saturating_sub(12 * age),
+                    ..self.header.clone()
+                };
+                parent_hash = header.hash_slow();
+                headers.insert(number, header);
+            }
+
+            MemoryState {
+                state_trie,
+                storage_tries,
+                headers,
+            }
+        })
+    }
+
+    /// Returns the account at the given block, or an error if the block is unknown.
+    fn account(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> Result<Option<&GenesisAccount>, MemoryProviderError> {
+        if !self.state().headers.contains_key(&block) {
+            return Err(MemoryProviderError::UnknownBlock(block));
+        }
+        Ok(self.accounts.get(&address))
+    }
+}
+
+impl Provider for MemoryProvider {
+    type Error = MemoryProviderError;
+    type Header = EthBlockHeader;
+
+    fn get_block_header(&self, block: BlockNumber) -> Result<Option<Self::Header>, Self::Error> {
+        Ok(self.state().headers.get(&block).cloned())
+    }
+
+    fn get_transaction_count(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> Result<TxNumber, Self::Error> {
+        Ok(self.account(address, block)?.map_or(0, |acc| acc.nonce))
+    }
+
+    fn get_balance(&self, address: Address, block: BlockNumber) -> Result<U256, Self::Error> {
+        Ok(self
+            .account(address, block)?
+            .map_or(U256::ZERO, |acc| acc.balance))
+    }
+
+    fn get_code(&self, address: Address, block: BlockNumber) -> Result<Bytes, Self::Error> {
+        let account = self.account(address, block)?;
+        Ok(account.map(|acc| acc.code.clone()).unwrap_or_default())
+    }
+
+    fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: BlockNumber,
+    ) -> Result<StorageValue, Self::Error> {
+        let account = self.account(address, block)?;
+        let value = account.and_then(|acc| acc.storage.get(&key));
+        Ok(value.map_or(U256::ZERO, |value| (*value).into()))
+    }
+
+    fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: BlockNumber,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        let account = self.account(address, block)?;
+        let state = self.state();
+        let account_proof = state.state_trie.proof(keccak256(address)).unwrap();
+
+        let Some(account) = account else {
+            // for non-existent accounts, all values are zero, as returned by `eth_getProof`
+            let storage_proof = storage_keys
+                .into_iter()
+                .map(|key| StorageProof {
+                    key,
+                    ..Default::default()
+                })
+                .collect();
+            return Ok(EIP1186Proof {
+                address,
+                account_proof,
+                storage_proof,
+                ..Default::default()
+            });
+        };
+
+        let storage_trie = &state.storage_tries[&address];
+        let storage_proof = storage_keys
+            .into_iter()
+            .map(|key| StorageProof {
+                key,
+                proof: storage_trie.proof(keccak256(key)).unwrap(),
+                value: account
+                    .storage
+                    .get(&key)
+                    .map_or(U256::ZERO, |v| (*v).into()),
+            })
+            .collect();
+
+        Ok(EIP1186Proof {
+            address,
+            balance: account.balance,
+            code_hash: code_hash(&account.code),
+            nonce: account.nonce,
+            storage_hash: storage_trie.hash_slow(),
+            account_proof,
+            storage_proof,
+        })
+    }
+}
+
+/// Returns the storage trie of the account, omitting slots with a zero value.
+fn storage_trie(account: &GenesisAccount) -> MerkleTrie {
+    MerkleTrie::from_leaves(
+        account
+            .storage
+            .iter()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(key, value)| {
+                (
+                    keccak256(key),
+                    alloy_rlp::encode(U256::from_be_bytes(value.0)),
+                )
+            }),
+    )
+}
+
+fn code_hash(code: &Bytes) -> B256 {
+    if code.is_empty() {
+        KECCAK_EMPTY
+    } else {
+        keccak256(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{address, b256};
+
+    const ADDRESS: Address = address!("1111111111111111111111111111111111111111");
+
+    fn provider() -> MemoryProvider {
+        let mut storage = BTreeMap::new();
+        storage.insert(B256::ZERO, B256::with_last_byte(42));
+        storage.insert(B256::with_last_byte(1), B256::ZERO);
+        let account = GenesisAccount {
+            balance: U256::from(1000),
+            nonce: 1,
+            code: Bytes::from_static(&[0x00]),
+            storage,
+        };
+        MemoryProvider::new()
+            .with_account(ADDRESS, account)
+            .with_account(Address::ZERO, GenesisAccount::default())
+            .with_block_number(1000)
+    }
+
+    #[test]
+    fn proof() {
+        let provider = provider();
+        let header = provider.get_block_header(1000).unwrap().unwrap();
+        assert_eq!(header.state_root, provider.state_root());
+
+        let keys = vec![B256::ZERO, B256::with_last_byte(1), B256::with_last_byte(2)];
+        let proof = provider.get_proof(ADDRESS, keys, 1000).unwrap();
+        assert_eq!(proof.balance, U256::from(1000));
+        assert_eq!(proof.nonce, 1);
+        assert_eq!(proof.code_hash, keccak256([0x00]));
+
+        // the proofs must be valid for the state root of the header
+        let state_trie = MerkleTrie::from_rlp_nodes(&proof.account_proof).unwrap();
+        assert_eq!(state_trie.hash_slow(), header.state_root);
+        let account = state_trie.get_rlp::<StateAccount>(keccak256(ADDRESS));
+        assert_eq!(account.unwrap().unwrap().storage_root, proof.storage_hash);
+        for storage_proof in &proof.storage_proof {
+            let trie = MerkleTrie::from_rlp_nodes(&storage_proof.proof).unwrap();
+            assert_eq!(trie.hash_slow(), proof.storage_hash);
+            let value = trie.get_rlp::<U256>(keccak256(storage_proof.key)).unwrap();
+            assert_eq!(value.unwrap_or_default(), storage_proof.value);
+        }
+        assert_eq!(proof.storage_proof[0].value, U256::from(42));
+
+        // accounts without any storage have an empty storage trie
+        let proof = provider.get_proof(Address::ZERO, vec![], 1000).unwrap();
+        assert_eq!(proof.code_hash, KECCAK_EMPTY);
+        assert_eq!(proof.storage_hash, EMPTY_ROOT_HASH);
+
+        // non-existing accounts are proven to be absent from the state trie
+        let address = address!("2222222222222222222222222222222222222222");
+        let proof = provider.get_proof(address, vec![], 1000).unwrap();
+        assert_eq!(proof.code_hash, B256::ZERO);
+        let state_trie = MerkleTrie::from_rlp_nodes(&proof.account_proof).unwrap();
+        assert_eq!(state_trie.hash_slow(), header.state_root);
+        assert_eq!(state_trie.get(keccak256(address)), None);
+    }
+
+    #[test]
+    fn ancestors() {
+        let provider = provider();
+        let head = provider.get_block_header(1000).unwrap().unwrap();
+        let parent = provider.get_block_header(999).unwrap().unwrap();
+        assert_eq!(head.parent_hash, parent.hash_slow());
+        assert!(provider.get_block_header(1000 - 256).unwrap().is_some());
+        assert!(provider.get_block_header(1000 - 257).unwrap().is_none());
+        assert!(provider.get_block_header(1001).unwrap().is_none());
+
+        provider.get_balance(ADDRESS, 1001).unwrap_err();
+    }
+
+    #[test]
+    fn genesis() {
+        let json = r#"{
+            "config": { "chainId": 1337 },
+            "alloc": {
+                "0x1111111111111111111111111111111111111111": {
+                    "balance": "0x3e8",
+                    "nonce": "0x1",
+                    "code": "0x00",
+                    "storage": {
+                        "0x0000000000000000000000000000000000000000000000000000000000000000": "0x000000000000000000000000000000000000000000000000000000000000002a",
+                        "0x0000000000000000000000000000000000000000000000000000000000000001": "0x0000000000000000000000000000000000000000000000000000000000000000"
+                    }
+                },
+                "0x0000000000000000000000000000000000000000": {}
+            }
+        }"#;
+        let genesis = MemoryProvider::from_genesis(json)
+            .unwrap()
+            .with_block_number(1000);
+        assert_eq!(genesis.state_root(), provider().state_root());
+
+        // only the alloc is accepted as well
+        let alloc: Value = serde_json::from_str(json).unwrap();
+        let alloc = MemoryProvider::from_genesis(&alloc["alloc"].to_string()).unwrap();
+        assert_eq!(alloc.state_root(), genesis.state_root());
+
+        // the root of an empty state is the empty root
+        let empty = MemoryProvider::new();
+        assert_eq!(
+            empty.state_root(),
+            b256!("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+        );
+    }
+}
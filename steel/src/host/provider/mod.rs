@@ -16,14 +16,30 @@ use crate::EvmBlockHeader;
 use alloy_primitives::{
     Address, BlockNumber, Bytes, StorageKey, StorageValue, TxNumber, B256, U256,
 };
-use serde::{Deserialize, Serialize};
-use std::{convert::Infallible, error::Error as StdError, fmt::Debug, marker::PhantomData};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    collections::BTreeMap, convert::Infallible, error::Error as StdError, fmt::Debug,
+    future::Future, marker::PhantomData, ops::Range, sync::Arc,
+};
+use tokio::runtime::{Handle, Runtime};
 
+mod alloy;
+mod blocking;
 mod ethers;
 mod file;
+mod memory;
+mod quorum;
+
+pub use alloy::{AlloyProvider, AlloyProviderError};
+pub use blocking::BlockingProvider;
+pub use ethers::{EthersProvider, EthersProviderError};
+pub use file::{CacheFormat, CachedProvider, EthFileProvider, FileProvider};
+pub use memory::{GenesisAccount, MemoryProvider, MemoryProviderError};
+pub use quorum::{Disagreement, EndpointHealth, QuorumPolicy, QuorumProvider, QuorumProviderError};
 
-pub use ethers::EthersProvider;
-pub use file::{CachedProvider, EthFileProvider, FileProvider};
+/// The default maximum number of concurrent requests of an [AsyncProvider].
+pub const DEFAULT_CONCURRENCY_LIMIT: usize = 16;
 
 /// A trait for providers that fetch data from the Ethereum blockchain.
 pub trait Provider {
@@ -50,6 +66,270 @@ pub trait Provider {
         storage_keys: Vec<StorageKey>,
         block: BlockNumber,
     ) -> Result<EIP1186Proof, Self::Error>;
+
+    /// Returns the EIP-1186 proofs for all the given accounts and their storage keys.
+    ///
+    /// The default implementation requests each proof sequentially.
+    fn get_proofs(
+        &self,
+        accounts: Vec<(Address, Vec<StorageKey>)>,
+        block: BlockNumber,
+    ) -> Result<Vec<EIP1186Proof>, Self::Error> {
+        accounts
+            .into_iter()
+            .map(|(address, storage_keys)| self.get_proof(address, storage_keys, block))
+            .collect()
+    }
+    /// Returns the headers of all the blocks in the given range.
+    ///
+    /// The default implementation requests each header sequentially.
+    fn get_block_headers(
+        &self,
+        blocks: Range<BlockNumber>,
+    ) -> Result<Vec<Option<Self::Header>>, Self::Error> {
+        blocks.map(|block| self.get_block_header(block)).collect()
+    }
+}
+
+/// Implements [Provider] for a smart pointer by forwarding all calls to the pointee.
+macro_rules! forward_provider {
+    ($($ty:ty),*) => {$(
+        impl<P: Provider + ?Sized> Provider for $ty {
+            type Error = P::Error;
+            type Header = P::Header;
+
+            fn get_block_header(
+                &self,
+                block: BlockNumber,
+            ) -> Result<Option<Self::Header>, Self::Error> {
+                (**self).get_block_header(block)
+            }
+            fn get_transaction_count(
+                &self,
+                address: Address,
+                block: BlockNumber,
+            ) -> Result<TxNumber, Self::Error> {
+                (**self).get_transaction_count(address, block)
+            }
+            fn get_balance(&self, address: Address, block: BlockNumber) -> Result<U256, Self::Error> {
+                (**self).get_balance(address, block)
+            }
+            fn get_code(&self, address: Address, block: BlockNumber) -> Result<Bytes, Self::Error> {
+                (**self).get_code(address, block)
+            }
+            fn get_storage_at(
+                &self,
+                address: Address,
+                key: StorageKey,
+                block: BlockNumber,
+            ) -> Result<StorageValue, Self::Error> {
+                (**self).get_storage_at(address, key, block)
+            }
+            fn get_proof(
+                &self,
+                address: Address,
+                storage_keys: Vec<StorageKey>,
+                block: BlockNumber,
+            ) -> Result<EIP1186Proof, Self::Error> {
+                (**self).get_proof(address, storage_keys, block)
+            }
+            fn get_proofs(
+                &self,
+                accounts: Vec<(Address, Vec<StorageKey>)>,
+                block: BlockNumber,
+            ) -> Result<Vec<EIP1186Proof>, Self::Error> {
+                (**self).get_proofs(accounts, block)
+            }
+            fn get_block_headers(
+                &self,
+                blocks: Range<BlockNumber>,
+            ) -> Result<Vec<Option<Self::Header>>, Self::Error> {
+                (**self).get_block_headers(blocks)
+            }
+        }
+    )*};
+}
+
+// allow sharing a single provider, e.g. one with a warm cache, between multiple environments
+forward_provider!(&P, Arc<P>);
+
+/// An asynchronous version of [Provider].
+///
+/// This allows preflighting calls on an existing async runtime, e.g. using
+/// [EvmEnv::from_provider_async], where many preflights can be run concurrently.
+///
+/// [EvmEnv::from_provider_async]: crate::EvmEnv::from_provider_async
+pub trait AsyncProvider: Send + Sync {
+    type Error: StdError + Send + Sync + 'static;
+    type Header: EvmBlockHeader + Send;
+
+    fn get_block_header(
+        &self,
+        block: BlockNumber,
+    ) -> impl Future<Output = Result<Option<Self::Header>, Self::Error>> + Send;
+    fn get_transaction_count(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> impl Future<Output = Result<TxNumber, Self::Error>> + Send;
+    fn get_balance(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> impl Future<Output = Result<U256, Self::Error>> + Send;
+    fn get_code(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> impl Future<Output = Result<Bytes, Self::Error>> + Send;
+    fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: BlockNumber,
+    ) -> impl Future<Output = Result<StorageValue, Self::Error>> + Send;
+    fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: BlockNumber,
+    ) -> impl Future<Output = Result<EIP1186Proof, Self::Error>> + Send;
+
+    /// Returns the maximum number of requests that are sent concurrently.
+    fn concurrency_limit(&self) -> usize {
+        DEFAULT_CONCURRENCY_LIMIT
+    }
+    /// Returns the EIP-1186 proofs for all the given accounts and their storage keys.
+    ///
+    /// The default implementation requests the proofs concurrently, limited by
+    /// [AsyncProvider::concurrency_limit].
+    fn get_proofs(
+        &self,
+        accounts: Vec<(Address, Vec<StorageKey>)>,
+        block: BlockNumber,
+    ) -> impl Future<Output = Result<Vec<EIP1186Proof>, Self::Error>> + Send {
+        buffered(
+            accounts,
+            self.concurrency_limit(),
+            move |(address, keys)| self.get_proof(address, keys, block),
+        )
+    }
+    /// Returns the headers of all the blocks in the given range.
+    ///
+    /// The default implementation requests the headers concurrently, limited by
+    /// [AsyncProvider::concurrency_limit].
+    fn get_block_headers(
+        &self,
+        blocks: Range<BlockNumber>,
+    ) -> impl Future<Output = Result<Vec<Option<Self::Header>>, Self::Error>> + Send {
+        buffered(blocks, self.concurrency_limit(), move |block| {
+            self.get_block_header(block)
+        })
+    }
+}
+
+/// A trait for providers that can trace calls and return execution witnesses.
+///
+/// This allows preflighting calls with a [TraceDb], which learns all the accessed state with a
+/// single trace instead of querying each account and storage slot individually.
+///
+/// [TraceDb]: crate::host::db::TraceDb
+pub trait TraceProvider: Provider {
+    /// Traces the call with the `prestateTracer` using `debug_traceCall` and returns the state of
+    /// all the accounts and storage slots it accessed.
+    fn trace_prestate(
+        &self,
+        call: &TraceCall,
+        block: BlockNumber,
+    ) -> Result<PrestateTrace, Self::Error>;
+
+    /// Returns the execution witness of the given block using `debug_executionWitness`.
+    ///
+    /// The witness contains the trie nodes of the parent state that are required to execute the
+    /// block. The default implementation returns `None`, indicating that the node does not support
+    /// execution witnesses.
+    fn get_execution_witness(
+        &self,
+        _block: BlockNumber,
+    ) -> Result<Option<ExecutionWitness>, Self::Error> {
+        Ok(None)
+    }
+}
+
+/// The call to be traced with [TraceProvider::trace_prestate].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceCall {
+    pub from: Address,
+    pub to: Address,
+    #[serde(with = "quantity")]
+    pub gas: u64,
+    pub gas_price: U256,
+    pub value: U256,
+    pub data: Bytes,
+}
+
+/// The result of the `prestateTracer` containing every accessed account.
+pub type PrestateTrace = BTreeMap<Address, PrestateAccount>;
+
+/// The state of an account before the traced call, as returned by the `prestateTracer`.
+///
+/// Fields are omitted by the tracer if they have their default value.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PrestateAccount {
+    #[serde(default)]
+    pub balance: U256,
+    #[serde(default, with = "quantity")]
+    pub nonce: TxNumber,
+    #[serde(default)]
+    pub code: Bytes,
+    #[serde(default)]
+    pub storage: BTreeMap<B256, B256>,
+}
+
+/// Response for `debug_executionWitness`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ExecutionWitness {
+    /// RLP-encoded nodes of the state trie and of the storage tries.
+    #[serde(deserialize_with = "deserialize_nodes")]
+    pub state: Vec<Bytes>,
+}
+
+/// Deserializes the witness nodes, which are returned either as a list or as a map by their hash.
+fn deserialize_nodes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Bytes>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Nodes {
+        List(Vec<Bytes>),
+        Map(BTreeMap<B256, Bytes>),
+    }
+    match Nodes::deserialize(deserializer)? {
+        Nodes::List(nodes) => Ok(nodes),
+        Nodes::Map(nodes) => Ok(nodes.into_values().collect()),
+    }
+}
+
+/// (De)serializes an `u64` as a hex quantity, also accepting plain JSON numbers.
+mod quantity {
+    use alloy_primitives::U64;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        U64::from(*value).serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Quantity {
+            Number(u64),
+            Hex(U64),
+        }
+        match Quantity::deserialize(deserializer)? {
+            Quantity::Number(n) => Ok(n),
+            Quantity::Hex(n) => Ok(n.to()),
+        }
+    }
 }
 
 /// Data structure with proof for one single storage-entry
@@ -108,3 +388,32 @@ impl<H: EvmBlockHeader> Provider for NullProvider<H> {
         panic!("Unexpected provider call")
     }
 }
+
+/// Executes the request for each item with at most `limit` requests running concurrently.
+/// The responses are returned in the order of the items.
+async fn buffered<I, F, T, E>(
+    items: I,
+    limit: usize,
+    request: impl FnMut(I::Item) -> F,
+) -> Result<Vec<T>, E>
+where
+    I: IntoIterator,
+    F: Future<Output = Result<T, E>>,
+{
+    stream::iter(items)
+        .map(request)
+        .buffered(limit.max(1))
+        .try_collect()
+        .await
+}
+
+/// Returns a handle to the current tokio runtime, or creates a new runtime if there is none.
+fn runtime_handle() -> (Handle, Option<Runtime>) {
+    match Handle::try_current() {
+        Ok(handle) => (handle, None),
+        Err(_) => {
+            let runtime = Runtime::new().unwrap();
+            (runtime.handle().clone(), Some(runtime))
+        }
+    }
+}
@@ -0,0 +1,455 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{EIP1186Proof, Provider};
+use crate::EvmBlockHeader;
+use alloy_primitives::{
+    Address, BlockNumber, Bytes, Sealable, StorageKey, StorageValue, TxNumber, B256, U256,
+};
+use std::{
+    error::Error as StdError,
+    ops::Range,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+use thiserror::Error;
+
+/// Error type for the [QuorumProvider].
+#[derive(Error, Debug)]
+pub enum QuorumProviderError<E: StdError> {
+    #[error("all endpoints failed")]
+    AllFailed(#[source] E),
+    #[error("no quorum for {query}: {agreeing} endpoints agreed, {required} required")]
+    NoQuorum {
+        query: String,
+        agreeing: usize,
+        required: usize,
+    },
+}
+
+/// The agreement required by a [QuorumProvider] to accept a response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuorumPolicy {
+    /// All endpoints must return the same response.
+    All,
+    /// More than half of all endpoints must return the same response.
+    ///
+    /// Endpoints are queried in order only until a majority agrees. Later endpoints are neither
+    /// checked nor counted in their [EndpointHealth], so a [Disagreement] only lists the endpoints
+    /// queried before that. Use [QuorumPolicy::All] to check every endpoint for each query.
+    #[default]
+    Majority,
+    /// The first successful response is accepted. Failing endpoints are skipped, and endpoints
+    /// that failed recently are tried last.
+    FirstSuccess,
+}
+
+/// The health of a single endpoint of a [QuorumProvider].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EndpointHealth {
+    /// Number of successful requests.
+    pub successes: u64,
+    /// Number of failed requests.
+    pub failures: u64,
+    /// Number of failed requests since the last successful one.
+    pub consecutive_failures: u64,
+    /// Number of responses that differed from the accepted response.
+    pub disagreements: u64,
+}
+
+/// A query for which some endpoints returned a different response than the majority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disagreement {
+    /// Description of the query, e.g. `get_storage_at(0x…, 0x…, 100)`.
+    pub query: String,
+    /// Indices of the endpoints that returned the most common response.
+    pub agreeing: Vec<usize>,
+    /// Indices of the endpoints that returned a different response.
+    pub disagreeing: Vec<usize>,
+}
+
+/// A provider cross-checking the responses of multiple endpoints.
+///
+/// Each query is sent to the inner providers and only answered if their responses satisfy the
+/// configured [QuorumPolicy]. The endpoints are queried in order until enough of them agree, so
+/// the remaining endpoints are not queried when the quorum has been reached. This detects a
+/// malicious or faulty RPC node already during the preflight, instead of when the state proofs are
+/// verified. Block headers are compared by their hash. The provider tracks the [EndpointHealth] of
+/// each endpoint and reports every [Disagreement].
+pub struct QuorumProvider<P> {
+    providers: Vec<P>,
+    policy: QuorumPolicy,
+    health: Mutex<Vec<EndpointHealth>>,
+    disagreements: Mutex<Vec<Disagreement>>,
+}
+
+impl<P: Provider> QuorumProvider<P> {
+    /// Creates a new [QuorumProvider] with the [QuorumPolicy::Majority] policy.
+    ///
+    /// It panics if no providers are given.
+    pub fn new(providers: Vec<P>) -> Self {
+        assert!(!providers.is_empty(), "no providers given");
+        let health = vec![EndpointHealth::default(); providers.len()];
+        Self {
+            providers,
+            policy: QuorumPolicy::default(),
+            health: Mutex::new(health),
+            disagreements: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Sets the agreement required to accept a response.
+    pub fn with_policy(mut self, policy: QuorumPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns the inner providers.
+    pub fn providers(&self) -> &[P] {
+        &self.providers
+    }
+
+    /// Returns the current health of each endpoint, in the order of the providers.
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        lock(&self.health).clone()
+    }
+
+    /// Returns all disagreements observed so far.
+    pub fn disagreements(&self) -> Vec<Disagreement> {
+        lock(&self.disagreements).clone()
+    }
+
+    /// Runs the query on the inner providers according to the policy. Responses are compared by
+    /// the value returned by `key`.
+    fn query<T, K: PartialEq>(
+        &self,
+        query: impl FnOnce() -> String,
+        f: impl Fn(&P) -> Result<T, P::Error>,
+        key: impl Fn(&T) -> K,
+    ) -> Result<T, QuorumProviderError<P::Error>> {
+        let n = self.providers.len();
+        match self.policy {
+            QuorumPolicy::All => self.agreement(n, query, f, key),
+            QuorumPolicy::Majority => self.agreement(n / 2 + 1, query, f, key),
+            QuorumPolicy::FirstSuccess => self.first_success(f),
+        }
+    }
+
+    fn first_success<T>(
+        &self,
+        f: impl Fn(&P) -> Result<T, P::Error>,
+    ) -> Result<T, QuorumProviderError<P::Error>> {
+        // try the healthiest endpoints first, keeping the configured order otherwise
+        let mut order: Vec<_> = (0..self.providers.len()).collect();
+        {
+            let health = lock(&self.health);
+            order.sort_by_key(|&i| health[i].consecutive_failures);
+        }
+
+        let mut last_err = None;
+        for i in order {
+            match self.request(i, &f) {
+                Ok(value) => return Ok(value),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(QuorumProviderError::AllFailed(last_err.unwrap()))
+    }
+
+    fn agreement<T, K: PartialEq>(
+        &self,
+        required: usize,
+        query: impl FnOnce() -> String,
+        f: impl Fn(&P) -> Result<T, P::Error>,
+        key: impl Fn(&T) -> K,
+    ) -> Result<T, QuorumProviderError<P::Error>> {
+        // group the successful responses by their key, until one group reaches the quorum
+        let mut groups: Vec<(K, T, Vec<usize>)> = Vec::new();
+        let mut last_err = None;
+        for i in 0..self.providers.len() {
+            match self.request(i, &f) {
+                Ok(value) => {
+                    let k = key(&value);
+                    let agreeing = match groups.iter_mut().find(|(other, ..)| *other == k) {
+                        Some((.., endpoints)) => {
+                            endpoints.push(i);
+                            endpoints.len()
+                        }
+                        None => {
+                            groups.push((k, value, vec![i]));
+                            1
+                        }
+                    };
+                    if agreeing >= required {
+                        break;
+                    }
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        let Some(best) = (0..groups.len()).max_by_key(|&j| groups[j].2.len()) else {
+            return Err(QuorumProviderError::AllFailed(last_err.unwrap()));
+        };
+        let (_, value, agreeing) = groups.swap_remove(best);
+
+        let query = query();
+        if !groups.is_empty() {
+            let mut disagreeing: Vec<_> = groups.into_iter().flat_map(|(.., e)| e).collect();
+            disagreeing.sort_unstable();
+            log::warn!(
+                "endpoints {:?} disagree with endpoints {:?} on {}",
+                disagreeing,
+                agreeing,
+                query
+            );
+
+            let mut health = lock(&self.health);
+            for &i in &disagreeing {
+                health[i].disagreements += 1;
+            }
+            lock(&self.disagreements).push(Disagreement {
+                query: query.clone(),
+                agreeing: agreeing.clone(),
+                disagreeing,
+            });
+        }
+
+        if agreeing.len() < required {
+            return Err(QuorumProviderError::NoQuorum {
+                query,
+                agreeing: agreeing.len(),
+                required,
+            });
+        }
+        Ok(value)
+    }
+
+    /// Sends the request to a single endpoint and updates its health.
+    fn request<T>(&self, i: usize, f: impl Fn(&P) -> Result<T, P::Error>) -> Result<T, P::Error> {
+        let result = f(&self.providers[i]);
+        let mut health = lock(&self.health);
+        let health = &mut health[i];
+        match &result {
+            Ok(_) => {
+                health.successes += 1;
+                health.consecutive_failures = 0;
+            }
+            Err(err) => {
+                log::warn!("endpoint {} failed: {}", i, err);
+                health.failures += 1;
+                health.consecutive_failures += 1;
+            }
+        }
+        result
+    }
+}
+
+/// Locks the mutex. A poisoned lock is ignored, as the protected data is always consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn header_hash<H: EvmBlockHeader>(header: &Option<H>) -> Option<B256> {
+    header.as_ref().map(Sealable::hash_slow)
+}
+
+impl<P: Provider> Provider for QuorumProvider<P> {
+    type Error = QuorumProviderError<P::Error>;
+    type Header = P::Header;
+
+    fn get_block_header(&self, block: BlockNumber) -> Result<Option<Self::Header>, Self::Error> {
+        self.query(
+            || format!("get_block_header({block})"),
+            |p| p.get_block_header(block),
+            header_hash,
+        )
+    }
+
+    fn get_transaction_count(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> Result<TxNumber, Self::Error> {
+        self.query(
+            || format!("get_transaction_count({address}, {block})"),
+            |p| p.get_transaction_count(address, block),
+            |count| *count,
+        )
+    }
+
+    fn get_balance(&self, address: Address, block: BlockNumber) -> Result<U256, Self::Error> {
+        self.query(
+            || format!("get_balance({address}, {block})"),
+            |p| p.get_balance(address, block),
+            |balance| *balance,
+        )
+    }
+
+    fn get_code(&self, address: Address, block: BlockNumber) -> Result<Bytes, Self::Error> {
+        self.query(
+            || format!("get_code({address}, {block})"),
+            |p| p.get_code(address, block),
+            |code| code.clone(),
+        )
+    }
+
+    fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: BlockNumber,
+    ) -> Result<StorageValue, Self::Error> {
+        self.query(
+            || format!("get_storage_at({address}, {key}, {block})"),
+            |p| p.get_storage_at(address, key, block),
+            |value| *value,
+        )
+    }
+
+    fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: BlockNumber,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        self.query(
+            || format!("get_proof({address}, {storage_keys:?}, {block})"),
+            |p| p.get_proof(address, storage_keys.clone(), block),
+            |proof| proof.clone(),
+        )
+    }
+
+    fn get_proofs(
+        &self,
+        accounts: Vec<(Address, Vec<StorageKey>)>,
+        block: BlockNumber,
+    ) -> Result<Vec<EIP1186Proof>, Self::Error> {
+        self.query(
+            || format!("get_proofs({accounts:?}, {block})"),
+            |p| p.get_proofs(accounts.clone(), block),
+            |proofs| proofs.clone(),
+        )
+    }
+
+    fn get_block_headers(
+        &self,
+        blocks: Range<BlockNumber>,
+    ) -> Result<Vec<Option<Self::Header>>, Self::Error> {
+        self.query(
+            || format!("get_block_headers({blocks:?})"),
+            |p| p.get_block_headers(blocks.clone()),
+            |headers| headers.iter().map(header_hash).collect::<Vec<_>>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::provider::{GenesisAccount, MemoryProvider};
+    use alloy_primitives::address;
+
+    const ADDRESS: Address = address!("1111111111111111111111111111111111111111");
+    const BLOCK: BlockNumber = 100;
+
+    fn memory_provider(balance: u64, block_number: BlockNumber) -> MemoryProvider {
+        let account = GenesisAccount {
+            balance: U256::from(balance),
+            ..Default::default()
+        };
+        MemoryProvider::new()
+            .with_account(ADDRESS, account)
+            .with_block_number(block_number)
+    }
+
+    #[test]
+    fn majority() {
+        let provider = QuorumProvider::new(vec![
+            memory_provider(1, BLOCK),
+            memory_provider(2, BLOCK),
+            memory_provider(1, BLOCK),
+        ]);
+        assert_eq!(provider.get_balance(ADDRESS, BLOCK).unwrap(), U256::from(1));
+        // the headers differ in their state root
+        provider.get_block_header(BLOCK).unwrap();
+
+        let disagreements = provider.disagreements();
+        assert_eq!(disagreements.len(), 2);
+        assert_eq!(disagreements[0].agreeing, vec![0, 2]);
+        assert_eq!(disagreements[0].disagreeing, vec![1]);
+        assert_eq!(provider.health()[1].disagreements, 2);
+        assert_eq!(provider.health()[0].disagreements, 0);
+
+        // queries answered identically by all endpoints are not reported
+        provider.get_transaction_count(ADDRESS, BLOCK).unwrap();
+        assert_eq!(provider.disagreements().len(), 2);
+        // the last endpoint is not queried, once the first two agree
+        let health = provider.health();
+        assert_eq!((health[1].successes, health[2].successes), (3, 2));
+    }
+
+    #[test]
+    fn no_majority() {
+        // the failing endpoint counts against the quorum
+        let provider = QuorumProvider::new(vec![
+            memory_provider(1, BLOCK),
+            memory_provider(2, BLOCK),
+            memory_provider(1, BLOCK - 1),
+        ]);
+        let err = provider.get_balance(ADDRESS, BLOCK).unwrap_err();
+        assert!(matches!(
+            err,
+            QuorumProviderError::NoQuorum {
+                agreeing: 1,
+                required: 2,
+                ..
+            }
+        ));
+        assert_eq!(provider.health()[2].failures, 1);
+    }
+
+    #[test]
+    fn all() {
+        let provider = QuorumProvider::new(vec![
+            memory_provider(1, BLOCK),
+            memory_provider(1, BLOCK),
+            memory_provider(2, BLOCK),
+        ])
+        .with_policy(QuorumPolicy::All);
+        provider.get_transaction_count(ADDRESS, BLOCK).unwrap();
+        let err = provider.get_balance(ADDRESS, BLOCK).unwrap_err();
+        assert!(matches!(err, QuorumProviderError::NoQuorum { .. }));
+    }
+
+    #[test]
+    fn first_success() {
+        let provider = QuorumProvider::new(vec![
+            memory_provider(1, BLOCK - 1),
+            memory_provider(2, BLOCK),
+        ])
+        .with_policy(QuorumPolicy::FirstSuccess);
+        assert_eq!(provider.get_balance(ADDRESS, BLOCK).unwrap(), U256::from(2));
+        assert_eq!(provider.health()[0].consecutive_failures, 1);
+
+        // the failed endpoint is now tried last
+        provider.get_balance(ADDRESS, BLOCK).unwrap();
+        let health = provider.health();
+        assert_eq!((health[0].failures, health[1].successes), (1, 2));
+
+        let provider = QuorumProvider::new(vec![memory_provider(1, BLOCK - 1)])
+            .with_policy(QuorumPolicy::FirstSuccess);
+        let err = provider.get_balance(ADDRESS, BLOCK).unwrap_err();
+        assert!(matches!(err, QuorumProviderError::AllFailed(_)));
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    contract::{execute, new_evm, MissingState, MissingStateHook, WrapStateDb},
+    new_guest_env, EvmBlockHeader, EvmEnv, EvmInput, MerkleTrie, StateDb,
+};
+use alloy_primitives::{Address, Bytes, B256, U256};
+use anyhow::anyhow;
+use revm::{
+    primitives::{AccountInfo, Bytecode, CfgEnvWithHandlerCfg, TxEnv},
+    Database,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    panic::{self, AssertUnwindSafe},
+};
+
+/// A contract call executed during the preflight that can be replayed with [EvmInput::replay].
+#[derive(Debug, Clone)]
+pub struct ReplayCall {
+    pub(crate) signature: String,
+    pub(crate) cfg: CfgEnvWithHandlerCfg,
+    pub(crate) tx: TxEnv,
+    pub(crate) output: Result<Bytes, String>,
+}
+
+impl ReplayCall {
+    /// Returns the signature of the called function.
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    /// Returns the data returned by the call in the preflight or the error message if it failed.
+    pub fn output(&self) -> &Result<Bytes, String> {
+        &self.output
+    }
+}
+
+/// The result of a single replayed call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayResult {
+    /// The signature of the called function.
+    pub signature: String,
+    /// The output of the call in the preflight.
+    pub preflight: Result<Bytes, String>,
+    /// The output of the call when replayed with the input.
+    pub replay: Result<Bytes, String>,
+}
+
+impl ReplayResult {
+    /// Returns whether the replayed call returned the same output as in the preflight.
+    pub fn is_match(&self) -> bool {
+        self.preflight == self.replay
+    }
+}
+
+/// The report of [EvmInput::replay].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The results of all replayed calls in order.
+    pub calls: Vec<ReplayResult>,
+    /// All the state accessed by the calls that is not contained in the input.
+    pub missing: BTreeSet<MissingState>,
+}
+
+impl ReplayReport {
+    /// Returns whether all calls returned the same output as in the preflight and all the
+    /// accessed state was contained in the input.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.calls.iter().all(ReplayResult::is_match)
+    }
+
+    /// Returns the calls whose output differs from the preflight.
+    pub fn mismatches(&self) -> impl Iterator<Item = &ReplayResult> {
+        self.calls.iter().filter(|result| !result.is_match())
+    }
+}
+
+impl<D, H> EvmEnv<D, H> {
+    /// Returns all the calls that have been preflighted with this environment.
+    pub fn preflight_calls(&self) -> &[ReplayCall] {
+        &self.preflight_calls
+    }
+}
+
+impl<H: EvmBlockHeader + Clone> EvmInput<H> {
+    /// Replays the given calls on the host, exactly as the guest would execute them.
+    ///
+    /// The guest [StateDb] is built from a copy of the input and the calls are executed against
+    /// it. Instead of panicking like in the guest, any state that the input does not cover is
+    /// collected in the returned [ReplayReport], together with the outputs of the calls and the
+    /// outputs recorded during the preflight, see [EvmEnv::preflight_calls].
+    ///
+    /// It returns an error if the input itself is invalid, i.e. when its state root does not
+    /// match the header or its ancestors do not form a valid chain.
+    pub fn replay<'a>(
+        &self,
+        calls: impl IntoIterator<Item = &'a ReplayCall>,
+    ) -> anyhow::Result<ReplayReport> {
+        Ok(self.replay_accesses(calls)?.0)
+    }
+
+    /// Replays the given calls and additionally returns all the state they accessed.
+    pub(super) fn replay_accesses<'a>(
+        &self,
+        calls: impl IntoIterator<Item = &'a ReplayCall>,
+    ) -> anyhow::Result<(ReplayReport, Accesses)> {
+        let env = panic::catch_unwind(AssertUnwindSafe(|| {
+            new_guest_env(
+                self.header.clone(),
+                self.state_trie.clone(),
+                self.storage_tries.clone(),
+                self.contracts.clone(),
+                &self.ancestors,
+            )
+        }))
+        .map_err(|err| anyhow!("invalid input: {}", panic_message(&*err)))?;
+
+        let mut report = ReplayReport::default();
+        let mut accesses = Accesses::default();
+        for call in calls {
+            let mut db = ReplayDb::new(&env.db);
+            let evm = new_evm(&mut db, call.cfg.clone(), &env.header);
+            let output = execute(evm, call.tx.clone(), &call.signature);
+
+            report.missing.append(&mut db.db.into_hook().0);
+            accesses.extend(db.accesses);
+            report.calls.push(ReplayResult {
+                signature: call.signature.clone(),
+                preflight: call.output.clone(),
+                replay: output,
+            });
+        }
+
+        Ok((report, accesses))
+    }
+}
+
+/// The state accessed by replayed calls.
+#[derive(Debug, Default)]
+pub(super) struct Accesses {
+    /// Accessed accounts and their accessed storage slots.
+    pub(super) accounts: BTreeMap<Address, BTreeSet<U256>>,
+    /// Hashes of the accessed bytecode.
+    pub(super) contracts: BTreeSet<B256>,
+    /// Numbers of the blocks whose hash was accessed.
+    pub(super) block_numbers: BTreeSet<u64>,
+}
+
+impl Accesses {
+    fn extend(&mut self, other: Accesses) {
+        for (address, slots) in other.accounts {
+            self.accounts.entry(address).or_default().extend(slots);
+        }
+        self.contracts.extend(other.contracts);
+        self.block_numbers.extend(other.block_numbers);
+    }
+}
+
+/// Collects the missing state instead of panicking like in the guest.
+#[derive(Debug, Default)]
+struct CollectMissing(BTreeSet<MissingState>);
+
+impl MissingStateHook for CollectMissing {
+    type Error = MissingState;
+
+    fn missing(&mut self, state: MissingState) -> Self::Error {
+        self.0.insert(state);
+        state
+    }
+}
+
+/// The guest database that collects missing state and records all accesses.
+struct ReplayDb<'a> {
+    db: WrapStateDb<'a, MerkleTrie, CollectMissing>,
+    accesses: Accesses,
+}
+
+impl<'a> ReplayDb<'a> {
+    fn new(inner: &'a StateDb) -> Self {
+        Self {
+            db: WrapStateDb::with_hook(inner, CollectMissing::default()),
+            accesses: Accesses::default(),
+        }
+    }
+}
+
+impl Database for ReplayDb<'_> {
+    type Error = MissingState;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.accesses.accounts.entry(address).or_default();
+        self.db.basic(address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.accesses.contracts.insert(code_hash);
+        self.db.code_by_hash(code_hash)
+    }
+
+    fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        self.accesses
+            .accounts
+            .entry(address)
+            .or_default()
+            .insert(index);
+        self.db.storage(address, index)
+    }
+
+    fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
+        // block number is never bigger then u64::MAX
+        self.accesses.block_numbers.insert(number.to());
+        self.db.block_hash(number)
+    }
+}
+
+/// Returns the message of a panic payload.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
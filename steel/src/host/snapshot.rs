@@ -0,0 +1,110 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{db::ProofDb, provider::Provider, BLOCKHASH_WINDOW};
+use crate::{EvmBlockHeader, EvmEnv};
+use alloy_primitives::{Address, Bytes, Sealable, U256};
+use anyhow::{ensure, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A serializable record of the state accessed during a preflight.
+///
+/// It is created with [EvmEnv::snapshot] and allows to continue the preflight later, e.g. in a
+/// different process: [EvmEnv::from_snapshot] resumes the session at the same block, so that
+/// further calls can be added, while [EvmEnv::from_snapshot_at] re-targets it to another block,
+/// so that a fresh input can be created without executing the calls again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreflightSnapshot<H> {
+    /// The header of the block the preflight was executed on.
+    pub header: H,
+    /// The accessed accounts with their accessed storage slots.
+    pub accounts: BTreeMap<Address, BTreeSet<U256>>,
+    /// The bytecode of the executed contracts by the address of their account.
+    pub contracts: BTreeMap<Address, Bytes>,
+    /// The numbers of the blocks whose hash was accessed.
+    pub block_hash_numbers: BTreeSet<u64>,
+}
+
+impl<P: Provider> EvmEnv<ProofDb<P>, P::Header> {
+    /// Returns a [PreflightSnapshot] of all the state accessed by the calls so far.
+    pub fn snapshot(&self) -> PreflightSnapshot<P::Header>
+    where
+        P::Header: Clone,
+    {
+        let db = &self.db;
+        PreflightSnapshot {
+            header: self.header.inner().clone(),
+            accounts: db
+                .accounts()
+                .iter()
+                .map(|(address, slots)| (*address, slots.iter().copied().collect()))
+                .collect(),
+            contracts: db.contracts_by_address(),
+            block_hash_numbers: db.block_hash_numbers().iter().map(|n| n.to()).collect(),
+        }
+    }
+
+    /// Resumes the preflight recorded in the snapshot.
+    ///
+    /// The environment uses the header of the snapshot and contains all of its accessed state,
+    /// so that the input created with [EvmEnv::into_input] covers the calls of the previous
+    /// session as well as any further calls. It returns an error if the header of the snapshot
+    /// does not match the header returned by the provider, e.g. after a re-org.
+    pub fn from_snapshot(
+        provider: P,
+        snapshot: PreflightSnapshot<P::Header>,
+    ) -> anyhow::Result<Self> {
+        let number = snapshot.header.number();
+        let header = provider
+            .get_block_header(number)?
+            .with_context(|| format!("block {number} not found"))?;
+        ensure!(
+            header.hash_slow() == snapshot.header.hash_slow(),
+            "header of the snapshot does not match block {number}"
+        );
+
+        let mut db = ProofDb::new(provider, number);
+        db.restore(&snapshot)
+            .context("failed to restore snapshot")?;
+
+        Ok(EvmEnv::new(db, snapshot.header.seal_slow()))
+    }
+
+    /// Re-targets the preflight recorded in the snapshot to another block.
+    ///
+    /// The environment accesses the same accounts, storage slots and block hashes as the
+    /// previous session, but at the given block. The calls are not executed again, so if they
+    /// would access different state at that block, the resulting input does not cover them.
+    /// This can be checked with [EvmInput::replay](crate::EvmInput::replay).
+    pub fn from_snapshot_at(
+        provider: P,
+        snapshot: &PreflightSnapshot<P::Header>,
+        block_number: u64,
+    ) -> anyhow::Result<Self> {
+        for number in &snapshot.block_hash_numbers {
+            ensure!(
+                *number < block_number && block_number - number <= BLOCKHASH_WINDOW,
+                "hash of block {number} is not accessible from block {block_number}"
+            );
+        }
+
+        let mut env = EvmEnv::from_provider(provider, block_number)?;
+        env.db
+            .restore(snapshot)
+            .context("failed to restore snapshot")?;
+
+        Ok(env)
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{EvmInput, MerkleTrie};
+use alloy_primitives::{keccak256, Address, B256, U256};
+use revm::primitives::{HashMap, HashSet};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Statistics about the size of an [EvmInput].
+///
+/// The size of the input largely determines the cost of proving it. All byte sizes are the
+/// lengths of the bincode serialization and are only an estimate of the data written to the zkVM:
+/// The risc0 serializer encodes the input as a sequence of `u32` words, so the actual size is
+/// larger, in particular for byte sequences. The sizes should therefore be used to compare
+/// inputs, not to predict the exact number of words read by the guest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputStats {
+    /// Number of accessed storage slots of each account.
+    pub storage_slots: BTreeMap<Address, usize>,
+    /// Size of the state trie.
+    pub state_trie: TrieStats,
+    /// Size of each storage trie, by its root.
+    pub storage_tries: BTreeMap<B256, TrieStats>,
+    /// Size of each contract's bytecode in bytes, by its code hash.
+    pub contracts: BTreeMap<B256, usize>,
+    /// Number of ancestor headers, i.e. how many blocks back block hashes are accessed.
+    pub ancestor_depth: usize,
+    /// Size of all the ancestor headers in bytes.
+    pub ancestors_bytes: usize,
+    /// Size of the complete input in bytes.
+    pub total_bytes: usize,
+}
+
+/// Statistics about the size of a [MerkleTrie].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrieStats {
+    /// Number of full nodes, i.e. nodes that are not just represented by their hash.
+    pub nodes: usize,
+    /// Size of the serialized trie in bytes.
+    pub bytes: usize,
+}
+
+impl TrieStats {
+    fn new(trie: &MerkleTrie) -> Self {
+        Self {
+            nodes: trie.size(),
+            bytes: serialized_size(trie),
+        }
+    }
+}
+
+impl InputStats {
+    /// Computes the statistics of the input, given the accessed storage slots of each account.
+    pub(crate) fn new<H: Serialize>(
+        input: &EvmInput<H>,
+        accounts: &HashMap<Address, HashSet<U256>>,
+    ) -> Self {
+        Self {
+            storage_slots: accounts
+                .iter()
+                .map(|(address, slots)| (*address, slots.len()))
+                .collect(),
+            state_trie: TrieStats::new(&input.state_trie),
+            storage_tries: input
+                .storage_tries
+                .iter()
+                .map(|trie| (trie.hash_slow(), TrieStats::new(trie)))
+                .collect(),
+            contracts: input
+                .contracts
+                .iter()
+                .map(|code| (keccak256(code), code.len()))
+                .collect(),
+            ancestor_depth: input.ancestors.len(),
+            ancestors_bytes: serialized_size(&input.ancestors),
+            total_bytes: serialized_size(input),
+        }
+    }
+
+    /// Returns the total number of accessed storage slots.
+    pub fn total_storage_slots(&self) -> usize {
+        self.storage_slots.values().sum()
+    }
+
+    /// Returns the total size of all storage tries in bytes.
+    pub fn storage_tries_bytes(&self) -> usize {
+        self.storage_tries.values().map(|stats| stats.bytes).sum()
+    }
+
+    /// Returns the total size of all contracts in bytes.
+    pub fn contracts_bytes(&self) -> usize {
+        self.contracts.values().sum()
+    }
+}
+
+fn serialized_size<T: Serialize + ?Sized>(value: &T) -> usize {
+    bincode::serialized_size(value).expect("serialization failed") as usize
+}
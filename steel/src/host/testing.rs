@@ -0,0 +1,267 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Utilities to write deterministic tests for Steel.
+//!
+//! Tests use a [TestProvider] backed by a fixture file, which is recorded from a real RPC node
+//! once and then replayed offline:
+//!
+//! ```rust no_run
+//! # use risc0_steel::{config::ETH_MAINNET_CHAIN_SPEC, host::testing::{self, CallOverrides, TestProvider}};
+//! # use alloy_primitives::address;
+//! # use alloy_sol_types::sol;
+//! sol! {
+//!     #[derive(Debug, PartialEq, Eq)]
+//!     interface IERC20 {
+//!         function balanceOf(address account) external view returns (uint);
+//!     }
+//! }
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! // records from `RPC_URL` if set, and replays `fixture.json` otherwise
+//! let provider = TestProvider::new("testdata/fixture.json")?;
+//! let call = IERC20::balanceOfCall {
+//!     account: address!("F977814e90dA44bFA03b6295A0616a897441aceC"),
+//! };
+//! let result = testing::assert_eth_call(
+//!     provider,
+//!     19493153,
+//!     &ETH_MAINNET_CHAIN_SPEC,
+//!     address!("dAC17F958D2ee523a2206206994597C13D831ec7"),
+//!     &call,
+//!     &CallOverrides::default(),
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use super::{
+    provider::{
+        CachedProvider, EIP1186Proof, EthFileProvider, EthersProvider, EthersProviderError,
+        Provider,
+    },
+    EthersClient,
+};
+use crate::{
+    config::ChainSpec,
+    ethereum::{EthBlockHeader, EthEvmEnv, EthFlatEvmInput},
+    CallBuilder, Contract,
+};
+use alloy_primitives::{Address, BlockNumber, Bytes, StorageKey, StorageValue, TxNumber, U256};
+use alloy_sol_types::SolCall;
+use anyhow::Context;
+use ethers_providers::ProviderError;
+use std::{env, fmt::Debug, ops::Range, path::PathBuf};
+
+/// The environment variable containing the URL of the RPC node to record fixtures from.
+pub const RPC_URL_ENV: &str = "RPC_URL";
+
+/// A [Provider] for tests that records responses of an RPC node in a fixture file and replays
+/// them offline.
+///
+/// If the [RPC_URL_ENV] environment variable is set, all queries are forwarded to that node and
+/// new responses are added to the fixture. Otherwise, all queries are answered from the fixture
+/// without any network access, and it panics if a query is not contained in it.
+pub enum TestProvider {
+    /// Records the responses of the RPC node.
+    Record(Box<CachedProvider<EthersProvider<EthersClient>>>),
+    /// Replays the responses from the fixture.
+    Replay(Box<EthFileProvider>),
+}
+
+impl TestProvider {
+    /// Creates a new [TestProvider] for the given fixture file.
+    pub fn new(fixture: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let fixture = fixture.into();
+        match env::var(RPC_URL_ENV) {
+            Ok(url) if !url.is_empty() => {
+                let client = EthersClient::new_client(&url, 3, 500)?;
+                let provider = CachedProvider::new(fixture, EthersProvider::new(client))?;
+                Ok(Self::Record(Box::new(provider)))
+            }
+            _ => {
+                let provider = EthFileProvider::from_file(&fixture).with_context(|| {
+                    format!(
+                        "failed to load fixture {}, set {RPC_URL_ENV} to record it",
+                        fixture.display()
+                    )
+                })?;
+                Ok(Self::Replay(Box::new(provider)))
+            }
+        }
+    }
+
+    /// Returns whether the provider records the responses of an RPC node.
+    pub fn is_recording(&self) -> bool {
+        matches!(self, Self::Record(_))
+    }
+
+    /// Writes all the recorded responses to the fixture file.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        match self {
+            Self::Record(provider) => provider.flush(),
+            Self::Replay(_) => Ok(()),
+        }
+    }
+}
+
+/// Forwards the query to the active provider.
+macro_rules! dispatch {
+    ($self:ident.$method:ident($($arg:expr),*)) => {
+        match $self {
+            Self::Record(provider) => provider.$method($($arg),*),
+            Self::Replay(provider) => provider.$method($($arg),*).map_err(|err| match err {}),
+        }
+    };
+}
+
+impl Provider for TestProvider {
+    type Error = EthersProviderError<ProviderError>;
+    type Header = EthBlockHeader;
+
+    fn get_block_header(&self, block: BlockNumber) -> Result<Option<Self::Header>, Self::Error> {
+        dispatch!(self.get_block_header(block))
+    }
+    fn get_transaction_count(
+        &self,
+        address: Address,
+        block: BlockNumber,
+    ) -> Result<TxNumber, Self::Error> {
+        dispatch!(self.get_transaction_count(address, block))
+    }
+    fn get_balance(&self, address: Address, block: BlockNumber) -> Result<U256, Self::Error> {
+        dispatch!(self.get_balance(address, block))
+    }
+    fn get_code(&self, address: Address, block: BlockNumber) -> Result<Bytes, Self::Error> {
+        dispatch!(self.get_code(address, block))
+    }
+    fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: BlockNumber,
+    ) -> Result<StorageValue, Self::Error> {
+        dispatch!(self.get_storage_at(address, key, block))
+    }
+    fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: BlockNumber,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        dispatch!(self.get_proof(address, storage_keys, block))
+    }
+    fn get_proofs(
+        &self,
+        accounts: Vec<(Address, Vec<StorageKey>)>,
+        block: BlockNumber,
+    ) -> Result<Vec<EIP1186Proof>, Self::Error> {
+        dispatch!(self.get_proofs(accounts, block))
+    }
+    fn get_block_headers(
+        &self,
+        blocks: Range<BlockNumber>,
+    ) -> Result<Vec<Option<Self::Header>>, Self::Error> {
+        dispatch!(self.get_block_headers(blocks))
+    }
+}
+
+/// Optional parameters of a call, which are applied to any [CallBuilder].
+#[derive(Debug, Clone, Default)]
+pub struct CallOverrides {
+    /// The caller of the function call.
+    pub from: Option<Address>,
+    /// The gas limit of the function call.
+    pub gas: Option<u64>,
+    /// The gas price of the function call.
+    pub gas_price: Option<U256>,
+    /// The value of the function call.
+    pub value: Option<U256>,
+}
+
+impl CallOverrides {
+    /// Applies the overrides to the given builder.
+    pub fn apply<C, E>(&self, mut builder: CallBuilder<C, E>) -> CallBuilder<C, E> {
+        if let Some(from) = self.from {
+            builder = builder.from(from);
+        }
+        if let Some(gas) = self.gas {
+            builder = builder.gas(gas);
+        }
+        if let Some(gas_price) = self.gas_price {
+            builder = builder.gas_price(gas_price);
+        }
+        if let Some(value) = self.value {
+            builder = builder.value(value);
+        }
+        builder
+    }
+}
+
+/// Preflights the call on the host and executes it like the guest would, asserting that both
+/// return the same result.
+///
+/// The call is executed in the guest environment created from the [EvmInput] as well as from its
+/// [FlatEvmInput] encoding. It panics if any of the steps fails or if the results differ, and
+/// returns the result otherwise.
+///
+/// [EvmInput]: crate::EvmInput
+/// [FlatEvmInput]: crate::FlatEvmInput
+pub fn assert_eth_call<C, P>(
+    provider: P,
+    block: u64,
+    chain_spec: &ChainSpec,
+    address: Address,
+    call: &C,
+    overrides: &CallOverrides,
+) -> C::Return
+where
+    C: SolCall,
+    C::Return: PartialEq + Debug,
+    P: Provider<Header = EthBlockHeader>,
+{
+    let mut env = EthEvmEnv::from_provider(provider, block)
+        .expect("failed to create environment")
+        .with_chain_spec(chain_spec);
+    let mut contract = Contract::preflight(address, &mut env);
+    let preflight_result = overrides
+        .apply(contract.call_builder(call))
+        .call()
+        .expect("preflight failed");
+
+    let input = env.into_input().expect("failed to create input");
+    let flat_input = input.to_flat();
+
+    let env = input.into_env().with_chain_spec(chain_spec);
+    let contract = Contract::new(address, &env);
+    let result = overrides.apply(contract.call_builder(call)).call();
+    assert_eq!(
+        result, preflight_result,
+        "mismatch in preflight and execution"
+    );
+
+    // execute the same call using the flat encoding of the input
+    let env = EthFlatEvmInput::decode(&flat_input)
+        .expect("failed to decode flat input")
+        .into_env()
+        .with_chain_spec(chain_spec);
+    let contract = Contract::new(address, &env);
+    let flat_result = overrides.apply(contract.call_builder(call)).call();
+    assert_eq!(
+        flat_result, preflight_result,
+        "mismatch in preflight and flat execution"
+    );
+
+    result
+}
@@ -16,23 +16,30 @@
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
 use alloy_primitives::{
-    b256, keccak256, Address, BlockNumber, Bytes, Sealable, Sealed, TxNumber, B256, U256,
+    b256, keccak256, Address, BlockNumber, Bytes, ChainId, Sealable, Sealed, TxNumber, B256, U256,
 };
+use alloy_rlp::Decodable;
 use alloy_rlp_derive::{RlpDecodable, RlpEncodable};
 
 use revm::primitives::{BlockEnv, CfgEnvWithHandlerCfg, HashMap, SpecId};
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, rc::Rc};
+use std::{fmt::Debug, sync::Arc};
 
+mod bindings;
 pub mod config;
 mod contract;
+mod envelope;
 pub mod ethereum;
+mod flat;
 #[cfg(feature = "host")]
 pub mod host;
 mod mpt;
+pub mod tokens;
 
-pub use contract::{CallBuilder, Contract};
-pub use mpt::MerkleTrie;
+pub use contract::{CallBuilder, CallEnv, Contract};
+pub use envelope::{EnvelopeError, VersionedEvmInput, INPUT_MAGIC, INPUT_VERSION};
+pub use flat::{FlatDecodeError, FlatEvmInput};
+pub use mpt::{FlatMerkleTrie, MerkleTrie, ParseNodeError, Trie, TrieEntry, TrieIter};
 
 /// The serializable input to derive and validate a [EvmEnv].
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,40 +56,60 @@ impl<H: EvmBlockHeader> EvmInput<H> {
     ///
     /// This method verifies that the state matches the state root in the header and panics if not.
     pub fn into_env(self) -> GuestEvmEnv<H> {
-        // verify that the state root matches the state trie
-        let state_root = self.state_trie.hash_slow();
-        assert_eq!(self.header.state_root(), &state_root, "State root mismatch");
-
-        // seal the header to compute its block hash
-        let header = self.header.seal_slow();
-
-        // validate that ancestor headers form a valid chain
-        let mut block_hashes = HashMap::with_capacity(self.ancestors.len() + 1);
-        block_hashes.insert(header.number(), header.seal());
-
-        let mut previous_header = header.inner();
-        for ancestor in &self.ancestors {
-            let ancestor_hash = ancestor.hash_slow();
-            assert_eq!(
-                previous_header.parent_hash(),
-                &ancestor_hash,
-                "Invalid chain: block {} is not the parent of block {}",
-                ancestor.number(),
-                previous_header.number()
-            );
-            block_hashes.insert(ancestor.number(), ancestor_hash);
-            previous_header = ancestor;
-        }
-
-        let db = StateDb::new(
+        new_guest_env(
+            self.header,
             self.state_trie,
             self.storage_tries,
             self.contracts,
-            block_hashes,
-        );
+            &self.ancestors,
+        )
+    }
 
-        EvmEnv::new(db, header)
+    /// Wraps the input in a [VersionedEvmInput] for the chain with the given ID.
+    pub fn into_versioned(self, chain_id: ChainId) -> VersionedEvmInput<H> {
+        VersionedEvmInput::new(self, chain_id)
+    }
+}
+
+/// Creates a new guest [EvmEnv] from the given components of an input.
+///
+/// It verifies that the state matches the state root in the header and that the ancestors form a
+/// valid chain and panics if not.
+fn new_guest_env<H: EvmBlockHeader, T: Trie>(
+    header: H,
+    state_trie: T,
+    storage_tries: impl IntoIterator<Item = T>,
+    contracts: impl IntoIterator<Item = Bytes>,
+    ancestors: &[H],
+) -> GuestEvmEnv<H, T> {
+    // verify that the state root matches the state trie
+    let state_root = state_trie.hash_slow();
+    assert_eq!(header.state_root(), &state_root, "State root mismatch");
+
+    // seal the header to compute its block hash
+    let header = header.seal_slow();
+
+    // validate that ancestor headers form a valid chain
+    let mut block_hashes = HashMap::with_capacity(ancestors.len() + 1);
+    block_hashes.insert(header.number(), header.seal());
+
+    let mut previous_header = header.inner();
+    for ancestor in ancestors {
+        let ancestor_hash = ancestor.hash_slow();
+        assert_eq!(
+            previous_header.parent_hash(),
+            &ancestor_hash,
+            "Invalid chain: block {} is not the parent of block {}",
+            ancestor.number(),
+            previous_header.number()
+        );
+        block_hashes.insert(ancestor.number(), ancestor_hash);
+        previous_header = ancestor;
     }
+
+    let db = StateDb::new(state_trie, storage_tries, contracts, block_hashes);
+
+    EvmEnv::new(db, header)
 }
 
 // Keep everything in the Steel library private except the commitment.
@@ -100,14 +127,24 @@ mod private {
 /// Solidity struct representing the committed block used for validation.
 pub use private::Commitment as SolCommitment;
 
+/// Items used by the exported macros, not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use alloy_primitives::Address;
+    pub use alloy_sol_types;
+    pub use paste::paste;
+}
+
 /// Alias for readability, do not make public.
-pub(crate) type GuestEvmEnv<H> = EvmEnv<StateDb, H>;
+pub(crate) type GuestEvmEnv<H, T = MerkleTrie> = EvmEnv<StateDb<T>, H>;
 
 /// The environment to execute the contract calls in.
 pub struct EvmEnv<D, H> {
     db: D,
     cfg_env: CfgEnvWithHandlerCfg,
     header: Sealed<H>,
+    #[cfg(feature = "host")]
+    preflight_calls: Vec<host::ReplayCall>,
 }
 
 impl<D, H: EvmBlockHeader> EvmEnv<D, H> {
@@ -120,6 +157,8 @@ impl<D, H: EvmBlockHeader> EvmEnv<D, H> {
             db,
             cfg_env,
             header,
+            #[cfg(feature = "host")]
+            preflight_calls: Vec::new(),
         }
     }
 
@@ -144,24 +183,30 @@ impl<D, H: EvmBlockHeader> EvmEnv<D, H> {
     pub fn header(&self) -> &H {
         self.header.inner()
     }
+
+    /// Returns the database of the environment.
+    pub fn db(&self) -> &D {
+        &self.db
+    }
 }
 
 /// A simple read-only EVM database.
 ///
-/// It is backed by a single [MerkleTrie] for the accounts and one [MerkleTrie] each for the
-/// accounts' storages. It panics when data is queried that is not contained in the tries.
-pub struct StateDb {
-    state_trie: MerkleTrie,
-    storage_tries: HashMap<B256, Rc<MerkleTrie>>,
+/// It is backed by a single [Trie] for the accounts and one [Trie] each for the accounts'
+/// storages. Calls executed in the guest panic when they query data that is not contained in
+/// the tries.
+pub struct StateDb<T = MerkleTrie> {
+    state_trie: T,
+    storage_tries: HashMap<B256, Arc<T>>,
     contracts: HashMap<B256, Bytes>,
     block_hashes: HashMap<u64, B256>,
 }
 
-impl StateDb {
+impl<T: Trie> StateDb<T> {
     /// Creates a new state database from the given tries.
     pub fn new(
-        state_trie: MerkleTrie,
-        storage_tries: impl IntoIterator<Item = MerkleTrie>,
+        state_trie: T,
+        storage_tries: impl IntoIterator<Item = T>,
         contracts: impl IntoIterator<Item = Bytes>,
         block_hashes: HashMap<u64, B256>,
     ) -> Self {
@@ -171,7 +216,7 @@ impl StateDb {
             .collect();
         let storage_tries = storage_tries
             .into_iter()
-            .map(|trie| (trie.hash_slow(), Rc::new(trie)))
+            .map(|trie| (trie.hash_slow(), Arc::new(trie)))
             .collect();
         Self {
             state_trie,
@@ -181,29 +226,21 @@ impl StateDb {
         }
     }
 
-    fn account(&self, address: Address) -> Option<StateAccount> {
-        self.state_trie
-            .get_rlp(keccak256(address))
-            .expect("invalid state value")
+    /// Returns the account, or `None` if it is neither proven to exist nor to not exist.
+    fn account(&self, address: Address) -> Option<Option<StateAccount>> {
+        let rlp = self.state_trie.try_get(keccak256(address))?;
+        Some(rlp.map(|mut rlp| StateAccount::decode(&mut rlp).expect("invalid state value")))
     }
 
-    fn code_by_hash(&self, hash: B256) -> &Bytes {
-        self.contracts
-            .get(&hash)
-            .unwrap_or_else(|| panic!("code not found: {}", hash))
+    fn code_by_hash(&self, hash: B256) -> Option<&Bytes> {
+        self.contracts.get(&hash)
     }
 
-    fn block_hash(&self, number: U256) -> B256 {
-        // block number is never bigger then u64::MAX
-        let number: u64 = number.to();
-        let hash = self
-            .block_hashes
-            .get(&number)
-            .unwrap_or_else(|| panic!("block not found: {}", number));
-        *hash
+    fn block_hash(&self, number: u64) -> Option<B256> {
+        self.block_hashes.get(&number).copied()
     }
 
-    fn storage_trie(&self, root: &B256) -> Option<&Rc<MerkleTrie>> {
+    fn storage_trie(&self, root: &B256) -> Option<&Arc<T>> {
         self.storage_tries.get(root)
     }
 }
@@ -239,6 +276,13 @@ impl Default for StateAccount {
 
 /// An EVM abstraction of a block header.
 pub trait EvmBlockHeader: Sealable {
+    /// Unique identifier of the header type.
+    ///
+    /// It is part of every [VersionedEvmInput] to detect when host and guest use different
+    /// header types. The default `0` denotes an unspecified type, implementors should override it
+    /// with an ID that is not used by any other header type.
+    const HEADER_TYPE_ID: u16 = 0;
+
     /// Returns the hash of the parent block's header.
     fn parent_hash(&self) -> &B256;
     /// Returns the block number.
@@ -12,15 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use alloy_primitives::{b256, keccak256, B256};
-use alloy_rlp::{BufMut, Decodable, Encodable, Header, EMPTY_STRING_CODE};
+use alloy_primitives::{b256, keccak256, utils::Keccak256, B256};
+use alloy_rlp::{Decodable, Encodable, Header, EMPTY_STRING_CODE};
 use nybbles::Nibbles;
+use once_cell::sync::OnceCell;
 use revm::primitives::HashMap;
 use rlp as legacy_rlp;
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    ops::{Bound, Deref, RangeBounds},
+};
 use thiserror::Error as ThisError;
 
+mod flat;
+
+pub use flat::FlatMerkleTrie;
+
 /// Root hash of an empty Merkle Patricia trie, i.e. `keccak256(RLP(""))`.
 pub const EMPTY_ROOT_HASH: B256 =
     b256!("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
@@ -31,19 +39,105 @@ pub enum ParseNodeError {
     /// Error that occurs when parsing the RLP encoding of a node.
     #[error("RLP error")]
     Rlp(#[from] legacy_rlp::DecoderError),
+    /// Error that occurs when the flat encoding of a trie is invalid.
+    #[error("invalid flat trie: {0}")]
+    Flat(&'static str),
+}
+
+/// Read access to a sparse Merkle Patricia trie.
+///
+/// This abstracts over the different representations of a trie, i.e. the owned [MerkleTrie] and
+/// the borrowed [FlatMerkleTrie].
+pub trait Trie {
+    /// Returns a reference to the byte value corresponding to the key.
+    ///
+    /// It panics when neither inclusion nor exclusion of the key can be guaranteed.
+    fn get(&self, key: impl AsRef<[u8]>) -> Option<&[u8]>;
+
+    /// Returns a reference to the byte value corresponding to the key.
+    ///
+    /// Unlike [Trie::get], it returns `None` instead of panicking when neither inclusion nor
+    /// exclusion of the key can be guaranteed.
+    fn try_get(&self, key: impl AsRef<[u8]>) -> Option<Option<&[u8]>>;
+
+    /// Returns the hash of the trie's root node.
+    fn hash_slow(&self) -> B256;
+
+    /// Returns the RLP decoded value corresponding to the key.
+    ///
+    /// It panics when neither inclusion nor exclusion of the key can be guaranteed or when the
+    /// value is not RLP decodable.
+    #[inline]
+    fn get_rlp<T: Decodable>(&self, key: impl AsRef<[u8]>) -> alloy_rlp::Result<Option<T>> {
+        match self.get(key) {
+            Some(mut bytes) => Ok(Some(T::decode(&mut bytes)?)),
+            None => Ok(None),
+        }
+    }
 }
 
 /// A sparse Merkle Patricia trie storing byte values.
-#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
-pub struct MerkleTrie(Node);
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MerkleTrie {
+    root: Node,
+    /// Memoized hash of the root node, it is only computed once.
+    ///
+    /// The references of all the other nodes are memoized in their parent, see [Child].
+    #[serde(skip)]
+    root_hash: OnceCell<B256>,
+}
+
+impl PartialEq for MerkleTrie {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root
+    }
+}
+
+impl Eq for MerkleTrie {}
+
+impl Trie for MerkleTrie {
+    #[inline]
+    fn get(&self, key: impl AsRef<[u8]>) -> Option<&[u8]> {
+        MerkleTrie::get(self, key)
+    }
+
+    #[inline]
+    fn try_get(&self, key: impl AsRef<[u8]>) -> Option<Option<&[u8]>> {
+        MerkleTrie::try_get(self, key)
+    }
+
+    #[inline]
+    fn hash_slow(&self) -> B256 {
+        MerkleTrie::hash_slow(self)
+    }
+}
 
 impl MerkleTrie {
+    /// Creates a new trie with the given root node.
+    #[inline]
+    fn new(root: Node) -> Self {
+        Self {
+            root,
+            root_hash: OnceCell::new(),
+        }
+    }
+
     /// Returns a reference to the byte value corresponding to the key.
     ///
     /// It panics when neither inclusion nor exclusion of the key can be guaranteed.
     #[inline]
     pub fn get(&self, key: impl AsRef<[u8]>) -> Option<&[u8]> {
-        self.0.get(Nibbles::unpack(key).as_slice())
+        self.root.get(Nibbles::unpack(key).as_slice())
+    }
+
+    /// Returns a reference to the byte value corresponding to the key.
+    ///
+    /// Unlike [MerkleTrie::get], it returns `None` instead of panicking when neither inclusion nor
+    /// exclusion of the key can be guaranteed.
+    #[inline]
+    pub(crate) fn try_get(&self, key: impl AsRef<[u8]>) -> Option<Option<&[u8]>> {
+        self.root.try_get(Nibbles::unpack(key).as_slice())
     }
 
     /// Returns the RLP decoded value corresponding to the key.
@@ -63,18 +157,15 @@ impl MerkleTrie {
     /// A full node is a node that needs to be fully encoded to compute the root hash.
     #[inline]
     pub fn size(&self) -> usize {
-        self.0.size()
+        self.root.size()
     }
 
     /// Returns the hash of the trie's root node.
+    ///
+    /// The hash is only computed on the first call, any subsequent call returns the cached value.
     #[inline]
     pub fn hash_slow(&self) -> B256 {
-        // compute the keccak hash of the RLP encoded root node
-        match self.0 {
-            Node::Null => EMPTY_ROOT_HASH,
-            Node::Digest(digest) => digest,
-            ref node => keccak256(node.rlp_encoded()),
-        }
+        *self.root_hash.get_or_init(|| self.root.hash())
     }
 
     /// Creates a new trie from the given RLP encoded nodes.
@@ -101,12 +192,223 @@ impl MerkleTrie {
         }
 
         let root_node = root_node_opt.unwrap_or_default();
-        let trie = MerkleTrie(resolve_trie(root_node.clone(), &nodes_by_hash));
+        let trie = MerkleTrie::new(resolve_trie(root_node.clone(), &nodes_by_hash));
         // Optional: Verify the resolved trie's hash matches the initial root's hash
-        debug_assert!(trie.hash_slow() == MerkleTrie(root_node).hash_slow());
+        debug_assert!(trie.hash_slow() == root_node.hash());
 
         Ok(trie)
     }
+
+    /// Creates a new fully resolved trie containing the given keys and values.
+    ///
+    /// Empty values are not allowed, as they would not be part of the trie.
+    #[cfg(feature = "host")]
+    pub(crate) fn from_leaves(leaves: impl IntoIterator<Item = (B256, Vec<u8>)>) -> Self {
+        let leaves: std::collections::BTreeMap<_, _> = leaves
+            .into_iter()
+            .map(|(key, value)| (Nibbles::unpack(key), value))
+            .collect();
+        let leaves: Vec<_> = leaves.into_iter().collect();
+
+        MerkleTrie::new(build_node(&leaves, 0))
+    }
+
+    /// Returns the RLP-encoded nodes on the path to the given key, like the proofs returned by
+    /// `eth_getProof`.
+    ///
+    /// It returns `None` when an unresolved node is on the path, i.e. when neither inclusion nor
+    /// exclusion of the key can be shown.
+    #[cfg(feature = "host")]
+    pub(crate) fn proof(&self, key: impl AsRef<[u8]>) -> Option<Vec<alloy_primitives::Bytes>> {
+        let mut nodes = Vec::new();
+        match &self.root {
+            Node::Null => {}
+            Node::Digest(_) => return None,
+            root => {
+                nodes.push(root.rlp_encoded());
+                if !root.proof(Nibbles::unpack(key).as_slice(), &mut nodes) {
+                    return None;
+                }
+            }
+        }
+
+        Some(nodes.into_iter().map(Into::into).collect())
+    }
+
+    /// Merges the resolved nodes of the other trie into this trie.
+    ///
+    /// Both tries must have the same root hash, i.e. they must be sparse representations of the
+    /// same trie. Every node that is resolved in either of them is resolved in the result.
+    #[cfg(feature = "host")]
+    pub(crate) fn merge(&mut self, other: MerkleTrie) {
+        debug_assert_eq!(self.hash_slow(), other.hash_slow());
+        let root = std::mem::take(&mut self.root);
+        self.root = root.merge(other.root);
+    }
+
+    /// Returns a copy of the trie that only resolves the nodes on the paths to the given keys.
+    ///
+    /// All other subtrees are replaced by their digest, so the root hash stays the same.
+    #[cfg(feature = "host")]
+    pub(crate) fn prune<T: AsRef<[u8]>>(&self, keys: impl IntoIterator<Item = T>) -> MerkleTrie {
+        let keys: Vec<_> = keys.into_iter().map(Nibbles::unpack).collect();
+        let keys: Vec<_> = keys.iter().map(Nibbles::as_slice).collect();
+
+        let trie = MerkleTrie::new(self.root.prune(&keys));
+        if let Some(hash) = self.root_hash.get() {
+            let _ = trie.root_hash.set(*hash);
+        }
+        trie
+    }
+
+    /// Returns an iterator over all entries of the trie in ascending key order.
+    ///
+    /// Besides all resolved leaves, this also returns every unresolved subtree, i.e. every
+    /// [TrieEntry::Digest], as the content of those subtrees is unknown.
+    #[inline]
+    pub fn iter(&self) -> TrieIter<'_> {
+        TrieIter::new(&self.root, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Returns an iterator over all resolved leaves in ascending key order, yielding the full
+    /// nibble path and the value of each leaf.
+    ///
+    /// Unresolved subtrees are skipped silently, use [MerkleTrie::iter] to also return them.
+    #[inline]
+    pub fn leaves(&self) -> impl Iterator<Item = (Nibbles, &[u8])> {
+        self.iter().filter_map(|entry| match entry {
+            TrieEntry::Leaf(key, value) => Some((key, value)),
+            TrieEntry::Digest(..) => None,
+        })
+    }
+
+    /// Returns an iterator over all entries with a hashed key in the given range.
+    ///
+    /// The entries are returned in ascending key order. Unresolved subtrees that potentially
+    /// contain keys in the range are returned as [TrieEntry::Digest], as it can neither be
+    /// guaranteed that the range is complete nor that it is not.
+    #[inline]
+    pub fn range(&self, range: impl RangeBounds<B256>) -> TrieIter<'_> {
+        let start = range.start_bound().map(Nibbles::unpack);
+        let end = range.end_bound().map(Nibbles::unpack);
+        TrieIter::new(&self.root, start, end)
+    }
+}
+
+/// An entry of a [MerkleTrie] as returned by [MerkleTrie::iter] or [MerkleTrie::range].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrieEntry<'a> {
+    /// A resolved leaf with its full nibble path and its value.
+    Leaf(Nibbles, &'a [u8]),
+    /// An unresolved subtree with the nibble path to its root and its hash.
+    Digest(Nibbles, &'a B256),
+}
+
+/// An iterator over the entries of a [MerkleTrie].
+///
+/// This struct is created by the [MerkleTrie::iter] and [MerkleTrie::range] methods.
+#[derive(Debug, Clone)]
+pub struct TrieIter<'a> {
+    stack: Vec<(Nibbles, &'a Node)>,
+    start: Bound<Nibbles>,
+    end: Bound<Nibbles>,
+}
+
+impl<'a> TrieIter<'a> {
+    fn new(root: &'a Node, start: Bound<Nibbles>, end: Bound<Nibbles>) -> Self {
+        Self {
+            stack: vec![(Nibbles::default(), root)],
+            start,
+            end,
+        }
+    }
+
+    /// Returns whether the given key is within the range.
+    fn contains(&self, key: &[u8]) -> bool {
+        let after_start = match &self.start {
+            Bound::Included(start) => key >= start.as_slice(),
+            Bound::Excluded(start) => key > start.as_slice(),
+            Bound::Unbounded => true,
+        };
+        let before_end = match &self.end {
+            Bound::Included(end) => key <= end.as_slice(),
+            Bound::Excluded(end) => key < end.as_slice(),
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+
+    /// Returns whether any key with the given prefix can be within the range.
+    fn overlaps(&self, prefix: &[u8]) -> bool {
+        // the smallest key with that prefix is prefix||00..., the largest is prefix||ff...
+        let after_start = match &self.start {
+            Bound::Included(start) => prefix >= truncated(start, prefix.len()),
+            Bound::Excluded(start) => match prefix.cmp(truncated(start, prefix.len())) {
+                std::cmp::Ordering::Less => false,
+                std::cmp::Ordering::Equal => !start[prefix.len()..].iter().all(|n| *n == 0xf),
+                std::cmp::Ordering::Greater => true,
+            },
+            Bound::Unbounded => true,
+        };
+        let before_end = match &self.end {
+            Bound::Included(end) => prefix <= truncated(end, prefix.len()),
+            Bound::Excluded(end) => match prefix.cmp(truncated(end, prefix.len())) {
+                std::cmp::Ordering::Less => true,
+                std::cmp::Ordering::Equal => !end[prefix.len()..].iter().all(|n| *n == 0),
+                std::cmp::Ordering::Greater => false,
+            },
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+}
+
+impl<'a> Iterator for TrieIter<'a> {
+    type Item = TrieEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((prefix, node)) = self.stack.pop() {
+            match node {
+                Node::Null => {}
+                Node::Leaf(suffix, value) => {
+                    let key = prefix.join(suffix);
+                    if self.contains(&key) {
+                        return Some(TrieEntry::Leaf(key, value));
+                    }
+                }
+                Node::Extension(suffix, child) => {
+                    let prefix = prefix.join(suffix);
+                    if self.overlaps(&prefix) {
+                        self.stack.push((prefix, child));
+                    }
+                }
+                Node::Branch(children) => {
+                    // push in reverse order, so that the smallest child is processed first
+                    for (idx, child) in children.iter().enumerate().rev() {
+                        if let Some(child) = child.as_deref() {
+                            let mut prefix = prefix.clone();
+                            prefix.push_unchecked(idx as u8);
+                            if self.overlaps(&prefix) {
+                                self.stack.push((prefix, child));
+                            }
+                        }
+                    }
+                }
+                Node::Digest(digest) => {
+                    if self.overlaps(&prefix) {
+                        return Some(TrieEntry::Digest(prefix, digest));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Returns the first `len` nibbles of the key or the entire key if it is shorter.
+#[inline]
+fn truncated(key: &Nibbles, len: usize) -> &[u8] {
+    &key[..len.min(key.len())]
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
@@ -114,29 +416,207 @@ enum Node {
     #[default]
     Null,
     Leaf(Nibbles, Box<[u8]>),
-    Extension(Nibbles, Box<Node>),
-    Branch([Option<Box<Node>>; 16]),
+    Extension(Nibbles, Box<Child>),
+    Branch([Option<Box<Child>>; 16]),
     Digest(B256),
 }
 
+/// A child of a [Node] together with its memoized [NodeRef].
+///
+/// The reference of a child is needed whenever its parent is encoded, i.e. for every hash and
+/// proof, so it is only computed once per child. The memoized reference is not serialized.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+struct Child {
+    node: Node,
+    #[serde(skip)]
+    reference: OnceCell<NodeRef>,
+}
+
+impl PartialEq for Child {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl Eq for Child {}
+
+impl Deref for Child {
+    type Target = Node;
+
+    #[inline]
+    fn deref(&self) -> &Node {
+        &self.node
+    }
+}
+
+impl Child {
+    /// Returns the reference to the child as it is used inside of its parent node.
+    ///
+    /// The reference is only computed on the first call, any subsequent call returns the cached
+    /// value.
+    #[inline]
+    fn reference(&self) -> NodeRef {
+        *self.reference.get_or_init(|| self.node.reference())
+    }
+
+    /// Merges the child with the corresponding child of another sparse representation of the
+    /// same trie. As the reference does not change, it is kept if it was already computed.
+    #[cfg(feature = "host")]
+    fn merge(self, other: Child) -> Child {
+        let reference = match self.reference.get() {
+            Some(_) => self.reference,
+            None => other.reference,
+        };
+        Child {
+            node: self.node.merge(other.node),
+            reference,
+        }
+    }
+
+    /// Returns a pruned copy of the child, or replaces it by its digest if none of the keys are
+    /// in its subtree. Nodes shorter than 32 bytes are embedded in their parent and are always
+    /// kept.
+    #[cfg(feature = "host")]
+    fn prune(&self, keys: &[&[u8]]) -> Child {
+        let node = if !keys.is_empty() {
+            self.node.prune(keys)
+        } else {
+            match self.reference() {
+                NodeRef::Digest(digest) => Node::Digest(digest),
+                _ => self.node.clone(),
+            }
+        };
+        // pruning does not change the reference of the child
+        Child {
+            node,
+            reference: self.reference.clone(),
+        }
+    }
+}
+
 impl Node {
+    /// Wraps the node into a [Child] of another node.
+    #[inline]
+    fn boxed(self) -> Box<Child> {
+        Box::new(Child {
+            node: self,
+            reference: OnceCell::new(),
+        })
+    }
+
     /// Returns a reference to the value corresponding to the key.
     /// It panics when neither inclusion nor exclusion of the key can be shown in the sparse trie.
+    #[inline]
     fn get(&self, key_nibs: &[u8]) -> Option<&[u8]> {
+        self.try_get(key_nibs)
+            .expect("Attempted to access unresolved node")
+    }
+
+    /// Returns the value corresponding to the key, or `None` when an unresolved node is on the path.
+    fn try_get(&self, key_nibs: &[u8]) -> Option<Option<&[u8]>> {
         match self {
-            Node::Null => None,
-            Node::Leaf(prefix, value) if prefix == key_nibs => Some(value),
-            Node::Leaf(..) => None,
-            Node::Extension(prefix, child) => key_nibs
-                .strip_prefix(prefix.as_slice())
-                .and_then(|remaining| child.get(remaining)),
+            Node::Null => Some(None),
+            Node::Leaf(prefix, value) if prefix == key_nibs => Some(Some(value)),
+            Node::Leaf(..) => Some(None),
+            Node::Extension(prefix, child) => match key_nibs.strip_prefix(prefix.as_slice()) {
+                Some(remaining) => child.try_get(remaining),
+                None => Some(None),
+            },
             Node::Branch(children) => match key_nibs.split_first() {
-                Some((idx, remaining)) => children[*idx as usize]
-                    .as_deref()
-                    .and_then(|node| node.get(remaining)),
-                None => None, // branch nodes don't have values in our MPT version
+                Some((idx, remaining)) => match children[*idx as usize].as_deref() {
+                    Some(child) => child.try_get(remaining),
+                    None => Some(None),
+                },
+                None => Some(None),
+            },
+            Node::Digest(_) => None,
+        }
+    }
+
+    /// Appends the RLP encoding of all the hashed descendants on the path to the key.
+    /// It returns `false` when an unresolved node is on the path.
+    #[cfg(feature = "host")]
+    fn proof(&self, key_nibs: &[u8], out: &mut Vec<Vec<u8>>) -> bool {
+        let (child, remaining) = match self {
+            Node::Null | Node::Leaf(..) => return true,
+            Node::Extension(prefix, child) => match key_nibs.strip_prefix(prefix.as_slice()) {
+                Some(remaining) => (child.as_ref(), remaining),
+                None => return true,
             },
-            Node::Digest(_) => panic!("Attempted to access unresolved node"),
+            Node::Branch(children) => match key_nibs.split_first() {
+                Some((idx, remaining)) => match children[*idx as usize].as_deref() {
+                    Some(child) => (child, remaining),
+                    None => return true,
+                },
+                None => return true,
+            },
+            Node::Digest(_) => return false,
+        };
+        if let Node::Digest(_) = child.node {
+            return false;
+        }
+
+        // nodes shorter than 32 bytes are embedded in their parent
+        let rlp = child.rlp_encoded();
+        if rlp.len() >= 32 {
+            out.push(rlp);
+        }
+        child.proof(remaining, out)
+    }
+
+    /// Merges this node with the corresponding node of another sparse representation of the same
+    /// trie, resolving each node that is resolved in either of them.
+    #[cfg(feature = "host")]
+    fn merge(self, other: Node) -> Node {
+        match (self, other) {
+            (Node::Digest(_), node) | (node, Node::Digest(_)) => node,
+            (Node::Extension(prefix, child), Node::Extension(_, other)) => {
+                Node::Extension(prefix, Box::new(child.merge(*other)))
+            }
+            (Node::Branch(mut children), Node::Branch(others)) => {
+                for (child, other) in children.iter_mut().zip(others) {
+                    if let Some(other) = other {
+                        *child = Some(match child.take() {
+                            Some(child) => Box::new(child.merge(*other)),
+                            None => other,
+                        });
+                    }
+                }
+                Node::Branch(children)
+            }
+            // null nodes and leaves are identical in both tries
+            (node, _) => node,
+        }
+    }
+
+    /// Returns a copy of the node that only resolves the descendants on the paths to the keys.
+    #[cfg(feature = "host")]
+    fn prune(&self, keys: &[&[u8]]) -> Node {
+        match self {
+            Node::Null | Node::Leaf(..) | Node::Digest(_) => self.clone(),
+            Node::Extension(prefix, child) => {
+                let remaining: Vec<_> = keys
+                    .iter()
+                    .filter_map(|key| key.strip_prefix(prefix.as_slice()))
+                    .collect();
+                Node::Extension(prefix.clone(), Box::new(child.prune(&remaining)))
+            }
+            Node::Branch(children) => {
+                let mut pruned: [Option<Box<Child>>; 16] = Default::default();
+                for (idx, (pruned, child)) in pruned.iter_mut().zip(children).enumerate() {
+                    if let Some(child) = child.as_deref() {
+                        let remaining: Vec<_> = keys
+                            .iter()
+                            .filter_map(|key| key.split_first())
+                            .filter(|(nibble, _)| **nibble as usize == idx)
+                            .map(|(_, remaining)| remaining)
+                            .collect();
+                        *pruned = Some(Box::new(child.prune(&remaining)));
+                    }
+                }
+                Node::Branch(pruned)
+            }
         }
     }
 
@@ -151,58 +631,86 @@ impl Node {
                 1 + children
                     .iter()
                     .filter_map(Option::as_deref)
-                    .map(Node::size)
+                    .map(|child| child.size())
                     .sum::<usize>()
             }
         }
     }
 
-    /// Returns the RLP encoding of the node.
-    fn rlp_encoded(&self) -> Vec<u8> {
+    /// Returns the hash of the node, i.e. the keccak hash of its RLP encoding.
+    fn hash(&self) -> B256 {
+        match self {
+            Node::Null => EMPTY_ROOT_HASH,
+            Node::Digest(digest) => *digest,
+            node => {
+                let mut hasher = Keccak256::new();
+                node.encode_rlp(&mut hasher);
+                hasher.finalize()
+            }
+        }
+    }
+
+    /// Returns the reference to this node as it is used inside of its parent node.
+    fn reference(&self) -> NodeRef {
+        match self {
+            Node::Null => NodeRef::Empty,
+            Node::Digest(digest) => NodeRef::Digest(*digest),
+            node => {
+                let mut builder = NodeRefBuilder::default();
+                node.encode_rlp(&mut builder);
+                builder.finish()
+            }
+        }
+    }
+
+    /// Streams the RLP encoding of the node into the given sink.
+    ///
+    /// The references of all children are computed before anything is written. This way, the
+    /// RLP header can be written directly without encoding the payload into a temporary buffer.
+    fn encode_rlp(&self, out: &mut impl RlpSink) {
         match self {
-            Node::Null => vec![EMPTY_STRING_CODE],
+            Node::Null => out.put(&[EMPTY_STRING_CODE]),
             Node::Leaf(prefix, value) => {
                 let path = prefix.encode_path_leaf(true);
-                let mut out = encoded_header(true, path.length() + value.length());
-                path.encode(&mut out);
-                value.encode(&mut out);
-
-                out
+                put_header(out, true, path.as_slice().length() + value.length());
+                put_string(out, &path);
+                put_string(out, value);
             }
             Node::Extension(prefix, child) => {
                 let path = prefix.encode_path_leaf(false);
-                let node_ref = NodeRef::from_node(child);
-                let mut out = encoded_header(true, path.length() + node_ref.length());
-                path.encode(&mut out);
-                node_ref.encode(&mut out);
-
-                out
+                let child_ref = child.reference();
+                put_header(out, true, path.as_slice().length() + child_ref.length());
+                put_string(out, &path);
+                child_ref.encode(out);
             }
             Node::Branch(children) => {
                 let mut child_refs: [NodeRef; 16] = Default::default();
                 let mut payload_length = 1; // start with 1 for the EMPTY_STRING_CODE at the end
-
-                for (i, child) in children.iter().enumerate() {
-                    match child.as_deref() {
-                        Some(node) => {
-                            let node_ref = NodeRef::from_node(node);
-                            payload_length += node_ref.length();
-                            child_refs[i] = node_ref;
-                        }
-                        None => payload_length += 1,
+                for (child_ref, child) in child_refs.iter_mut().zip(children) {
+                    if let Some(node) = child.as_deref() {
+                        *child_ref = node.reference();
                     }
+                    payload_length += child_ref.length();
                 }
 
-                let mut out = encoded_header(true, payload_length);
-                child_refs.iter().for_each(|child| child.encode(&mut out));
+                put_header(out, true, payload_length);
+                child_refs
+                    .iter()
+                    .for_each(|child_ref| child_ref.encode(out));
                 // add an EMPTY_STRING_CODE for the missing value
-                out.push(EMPTY_STRING_CODE);
-
-                out
+                out.put(&[EMPTY_STRING_CODE]);
             }
-            Node::Digest(digest) => alloy_rlp::encode(digest),
+            Node::Digest(digest) => put_string(out, digest.as_slice()),
         }
     }
+
+    /// Returns the RLP encoding of the node.
+    #[cfg(any(test, feature = "host"))]
+    fn rlp_encoded(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_rlp(&mut out);
+        out
+    }
 }
 
 impl legacy_rlp::Decodable for Node {
@@ -217,19 +725,19 @@ impl legacy_rlp::Decodable for Node {
                     let val = rlp.val_at::<Vec<u8>>(1)?;
                     Ok(Node::Leaf(path, val.into_boxed_slice()))
                 } else {
-                    let node = Decodable::decode(&rlp.at(1)?)?;
+                    let node: Node = Decodable::decode(&rlp.at(1)?)?;
                     if node == Node::Null {
                         return Err(DecoderError::Custom("extension node with null child"));
                     }
-                    Ok(Node::Extension(path, Box::new(node)))
+                    Ok(Node::Extension(path, node.boxed()))
                 }
             }
             Prototype::List(17) => {
-                let mut children: [Option<Box<Node>>; 16] = Default::default();
+                let mut children: [Option<Box<Child>>; 16] = Default::default();
                 for (i, node_rlp) in rlp.iter().enumerate().take(16) {
                     match node_rlp.prototype()? {
                         Prototype::Null | Prototype::Data(0) => {}
-                        _ => children[i] = Some(Box::new(Decodable::decode(&node_rlp)?)),
+                        _ => children[i] = Some(Node::decode(&node_rlp)?.boxed()),
                     }
                 }
                 // verify that there is no 17th element with a value
@@ -250,69 +758,181 @@ impl legacy_rlp::Decodable for Node {
 }
 
 /// Represents the way in which a node is referenced from within another node.
-#[derive(Default)]
-enum NodeRef<'a> {
+#[derive(Debug, Clone, Copy, Default)]
+enum NodeRef {
     #[default]
     Empty,
-    Digest(&'a B256),
-    Node(Vec<u8>),
+    /// The hash of the RLP encoded node.
+    Digest(B256),
+    /// The RLP encoded node itself, if it is shorter than 32 bytes.
+    Inline([u8; 31], u8),
 }
 
-impl NodeRef<'_> {
+impl NodeRef {
+    /// Returns the length of the reference when RLP encoded.
     #[inline]
-    fn from_node(node: &Node) -> NodeRef<'_> {
-        match node {
-            Node::Null => NodeRef::Empty,
-            Node::Digest(digest) => NodeRef::Digest(digest),
-            node => NodeRef::Node(node.rlp_encoded()),
+    fn length(&self) -> usize {
+        match self {
+            NodeRef::Empty => 1,
+            // hash length + 1 byte for the RLP header
+            NodeRef::Digest(_) => 1 + B256::len_bytes(),
+            NodeRef::Inline(_, len) => *len as usize,
         }
     }
-}
 
-impl Encodable for NodeRef<'_> {
+    /// Writes the RLP encoded reference into the given sink.
     #[inline]
-    fn encode(&self, out: &mut dyn BufMut) {
+    fn encode(&self, out: &mut impl RlpSink) {
         match self {
-            NodeRef::Empty => out.put_u8(EMPTY_STRING_CODE),
-            NodeRef::Digest(digest) => digest.encode(out),
-            NodeRef::Node(rlp) => {
-                if rlp.len() >= B256::len_bytes() {
-                    keccak256(rlp).encode(out);
-                } else {
-                    out.put_slice(rlp);
-                }
-            }
+            NodeRef::Empty => out.put(&[EMPTY_STRING_CODE]),
+            NodeRef::Digest(digest) => put_string(out, digest.as_slice()),
+            NodeRef::Inline(rlp, len) => out.put(&rlp[..*len as usize]),
         }
     }
+}
+
+/// A destination for RLP encoded data.
+trait RlpSink {
+    fn put(&mut self, bytes: &[u8]);
+}
+
+impl RlpSink for Keccak256 {
     #[inline]
-    fn length(&self) -> usize {
-        // hash length + 1 byte for the RLP header
-        const DIGEST_LENGTH: usize = 1 + B256::len_bytes();
+    fn put(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+}
 
-        match self {
-            NodeRef::Empty => 1,
-            NodeRef::Digest(_) => DIGEST_LENGTH,
-            NodeRef::Node(rlp) => {
-                if rlp.len() >= B256::len_bytes() {
-                    DIGEST_LENGTH
-                } else {
-                    rlp.len()
-                }
-            }
+impl RlpSink for Vec<u8> {
+    #[inline]
+    fn put(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// A [RlpSink] computing the [NodeRef] of an encoded node.
+///
+/// Encodings shorter than 32 bytes are kept in a fixed-size buffer. As soon as the encoding gets
+/// longer, it is streamed into a keccak hasher instead.
+#[derive(Default)]
+struct NodeRefBuilder {
+    buf: [u8; 31],
+    len: usize,
+    hasher: Option<Keccak256>,
+}
+
+impl NodeRefBuilder {
+    #[inline]
+    fn finish(self) -> NodeRef {
+        match self.hasher {
+            Some(hasher) => NodeRef::Digest(hasher.finalize()),
+            None => NodeRef::Inline(self.buf, self.len as u8),
+        }
+    }
+}
+
+impl RlpSink for NodeRefBuilder {
+    #[inline]
+    fn put(&mut self, bytes: &[u8]) {
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(bytes);
+        } else if self.len + bytes.len() <= self.buf.len() {
+            self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+            self.len += bytes.len();
+        } else {
+            let mut hasher = Keccak256::new();
+            hasher.update(&self.buf[..self.len]);
+            hasher.update(bytes);
+            self.hasher = Some(hasher);
         }
     }
 }
 
+/// Writes an RLP header into the given sink.
 #[inline]
-fn encoded_header(list: bool, payload_length: usize) -> Vec<u8> {
+fn put_header(out: &mut impl RlpSink, list: bool, payload_length: usize) {
     debug_assert!(payload_length > 0);
     let header = Header {
         list,
         payload_length,
     };
-    let mut out = Vec::with_capacity(header.length() + payload_length);
-    header.encode(&mut out);
-    out
+    // an RLP header consists of at most 1 + 8 bytes
+    let mut buf = [0u8; 9];
+    let mut remaining = &mut buf[..];
+    header.encode(&mut remaining);
+    let len = 9 - remaining.len();
+    out.put(&buf[..len]);
+}
+
+/// Writes the given bytes as an RLP string into the given sink.
+#[inline]
+fn put_string(out: &mut impl RlpSink, bytes: &[u8]) {
+    if bytes.len() == 1 && bytes[0] < EMPTY_STRING_CODE {
+        out.put(bytes);
+    } else {
+        put_header(out, false, bytes.len());
+        out.put(bytes);
+    }
+}
+
+/// Builds the node for the sorted leaves, which share the first `depth` nibbles of their keys.
+#[cfg(feature = "host")]
+fn build_node(leaves: &[(Nibbles, Vec<u8>)], depth: usize) -> Node {
+    match leaves {
+        [] => Node::Null,
+        [(key, value)] => Node::Leaf(key.slice(depth..), value.as_slice().into()),
+        [(first, _), .., (last, _)] => {
+            // as the leaves are sorted, the first and the last key have the shortest common prefix
+            let common = first.slice(depth..).common_prefix_length(&last[depth..]);
+            let branch = build_branch(leaves, depth + common);
+            match common {
+                0 => branch,
+                _ => Node::Extension(first.slice(depth..depth + common), branch.boxed()),
+            }
+        }
+    }
+}
+
+/// Builds the branch node for the sorted leaves, which differ in the nibble at `depth`.
+#[cfg(feature = "host")]
+fn build_branch(leaves: &[(Nibbles, Vec<u8>)], depth: usize) -> Node {
+    let mut children: [Option<Box<Child>>; 16] = Default::default();
+    for group in leaves.chunk_by(|(a, _), (b, _)| a[depth] == b[depth]) {
+        let nibble = group[0].0[depth];
+        children[nibble as usize] = Some(build_node(group, depth + 1).boxed());
+    }
+
+    Node::Branch(children)
+}
+
+/// Decoded trie nodes indexed by their hash.
+///
+/// This allows to build several tries from the same collection of nodes, e.g. from an execution
+/// witness, while decoding every node only once.
+#[cfg(feature = "host")]
+#[derive(Debug, Default)]
+pub(crate) struct NodeSet(HashMap<B256, Node>);
+
+#[cfg(feature = "host")]
+impl NodeSet {
+    /// Decodes the given RLP-encoded nodes.
+    pub(crate) fn from_rlp_nodes<T: AsRef<[u8]>>(
+        nodes: impl IntoIterator<Item = T>,
+    ) -> Result<Self, ParseNodeError> {
+        let mut nodes_by_hash = HashMap::new();
+        for rlp in nodes {
+            let rlp = rlp.as_ref();
+            nodes_by_hash.insert(keccak256(rlp), legacy_rlp::decode(rlp)?);
+        }
+        Ok(Self(nodes_by_hash))
+    }
+
+    /// Returns the trie with the given root hash, resolving all the nodes contained in the set.
+    /// It returns `None` if the root node itself is not contained.
+    pub(crate) fn trie(&self, root: &B256) -> Option<MerkleTrie> {
+        let root = self.0.get(root)?.clone();
+        Some(MerkleTrie::new(resolve_trie(root, &self.0)))
+    }
 }
 
 /// Returns the decoded node and its RLP hash.
@@ -327,13 +947,13 @@ fn resolve_trie(root: Node, nodes_by_hash: &HashMap<B256, Node>) -> Node {
     match root {
         Node::Null | Node::Leaf(..) => root,
         Node::Extension(prefix, child) => {
-            Node::Extension(prefix, Box::new(resolve_trie(*child, nodes_by_hash)))
+            Node::Extension(prefix, resolve_trie(child.node, nodes_by_hash).boxed())
         }
         Node::Branch(mut children) => {
             // iterate over the children in place, resolving each child node recursively.
             for child in children.iter_mut() {
                 if let Some(node) = child.take() {
-                    *child = Some(Box::new(resolve_trie(*node, nodes_by_hash)));
+                    *child = Some(resolve_trie(node.node, nodes_by_hash).boxed());
                 }
             }
             Node::Branch(children)
@@ -361,7 +981,7 @@ mod tests {
     use super::*;
     use crate::StateAccount;
     use alloy_primitives::{address, uint, Bytes, U256};
-    use alloy_trie::HashBuilder;
+    use alloy_trie::{proof::ProofRetainer, HashBuilder};
     use serde_json::json;
     use std::collections::BTreeMap;
 
@@ -379,10 +999,10 @@ mod tests {
 
     #[test]
     pub fn mpt_null() {
-        let mpt = MerkleTrie(Node::Null);
+        let mpt = MerkleTrie::new(Node::Null);
         assert_eq!(
             mpt,
-            MerkleTrie::from_rlp_nodes(rlp_encoded(&mpt.0)).unwrap()
+            MerkleTrie::from_rlp_nodes(rlp_encoded(&mpt.root)).unwrap()
         );
 
         assert_eq!(mpt.hash_slow(), EMPTY_ROOT_HASH);
@@ -396,10 +1016,10 @@ mod tests {
 
     #[test]
     pub fn mpt_digest() {
-        let mpt = MerkleTrie(Node::Digest(B256::ZERO));
+        let mpt = MerkleTrie::new(Node::Digest(B256::ZERO));
         assert_eq!(
             mpt,
-            MerkleTrie::from_rlp_nodes(rlp_encoded(&mpt.0)).unwrap()
+            MerkleTrie::from_rlp_nodes(rlp_encoded(&mpt.root)).unwrap()
         );
 
         assert_eq!(mpt.hash_slow(), B256::ZERO);
@@ -408,10 +1028,10 @@ mod tests {
 
     #[test]
     pub fn mpt_leaf() {
-        let mpt = MerkleTrie(Node::Leaf(Nibbles::unpack(B256::ZERO), vec![0].into()));
+        let mpt = MerkleTrie::new(Node::Leaf(Nibbles::unpack(B256::ZERO), vec![0].into()));
         assert_eq!(
             mpt,
-            MerkleTrie::from_rlp_nodes(rlp_encoded(&mpt.0)).unwrap()
+            MerkleTrie::from_rlp_nodes(rlp_encoded(&mpt.root)).unwrap()
         );
 
         assert_eq!(
@@ -429,16 +1049,10 @@ mod tests {
 
     #[test]
     pub fn mpt_branch() {
-        let mut children: [Option<Box<Node>>; 16] = Default::default();
-        children[0] = Some(Box::new(Node::Leaf(
-            Nibbles::from_nibbles([0; 63]),
-            vec![0].into(),
-        )));
-        children[1] = Some(Box::new(Node::Leaf(
-            Nibbles::from_nibbles([1; 63]),
-            vec![1].into(),
-        )));
-        let mpt = MerkleTrie(Node::Branch(children));
+        let mut children: [Option<Box<Child>>; 16] = Default::default();
+        children[0] = Some(Node::Leaf(Nibbles::from_nibbles([0; 63]), vec![0].into()).boxed());
+        children[1] = Some(Node::Leaf(Nibbles::from_nibbles([1; 63]), vec![1].into()).boxed());
+        let mpt = MerkleTrie::new(Node::Branch(children));
         assert_eq!(
             mpt.hash_slow(),
             b256!("f09860d0bbaa3a755a53bbeb7b06824cdda5ac2ee5557d14aa49117a47bd0a3e")
@@ -454,19 +1068,13 @@ mod tests {
 
     #[test]
     pub fn mpt_extension() {
-        let mut children: [Option<Box<Node>>; 16] = Default::default();
-        children[0] = Some(Box::new(Node::Leaf(
-            Nibbles::from_nibbles([0; 62]),
-            vec![0].into(),
-        )));
-        children[1] = Some(Box::new(Node::Leaf(
-            Nibbles::from_nibbles([1; 62]),
-            vec![1].into(),
-        )));
+        let mut children: [Option<Box<Child>>; 16] = Default::default();
+        children[0] = Some(Node::Leaf(Nibbles::from_nibbles([0; 62]), vec![0].into()).boxed());
+        children[1] = Some(Node::Leaf(Nibbles::from_nibbles([1; 62]), vec![1].into()).boxed());
         let branch = Node::Branch(children);
-        let mpt = MerkleTrie(Node::Extension(
+        let mpt = MerkleTrie::new(Node::Extension(
             Nibbles::from_nibbles([0; 1]),
-            branch.into(),
+            branch.boxed(),
         ));
         assert_eq!(
             mpt.hash_slow(),
@@ -490,7 +1098,7 @@ mod tests {
     #[test]
     #[should_panic]
     pub fn get_digest() {
-        let mpt = MerkleTrie(Node::Digest(B256::ZERO));
+        let mpt = MerkleTrie::new(Node::Digest(B256::ZERO));
         mpt.get([]);
     }
 
@@ -524,6 +1132,280 @@ mod tests {
         assert_eq!(mpt.hash_slow(), root);
     }
 
+    #[test]
+    #[cfg(feature = "host")]
+    pub fn from_leaves() {
+        let leaves: BTreeMap<_, _> = (0u64..256)
+            .map(|i| {
+                (
+                    keccak256(B256::from(U256::from(i))),
+                    alloy_rlp::encode(i + 1),
+                )
+            })
+            .collect();
+
+        for n in [0, 1, 2, leaves.len()] {
+            let leaves: Vec<_> = leaves.iter().take(n).collect();
+            let mut hash_builder = HashBuilder::default();
+            for (key, value) in &leaves {
+                hash_builder.add_leaf(Nibbles::unpack(key), value);
+            }
+
+            let mpt = MerkleTrie::from_leaves(leaves.iter().map(|(k, v)| (**k, v.to_vec())));
+            assert_eq!(mpt.hash_slow(), hash_builder.root());
+            for (key, value) in &leaves {
+                assert_eq!(mpt.get(key), Some(value.as_slice()));
+            }
+        }
+    }
+
+    #[test]
+    pub fn hash_inline_nodes() {
+        // short keys and values result in nodes with an RLP encoding of less than 32 bytes
+        let leaves: BTreeMap<_, _> = (0u8..64)
+            .map(|i| (Nibbles::unpack([i]), alloy_rlp::encode(i)))
+            .collect();
+
+        let proof_keys = leaves.keys().cloned().collect();
+        let mut hash_builder = HashBuilder::default().with_proof_retainer(proof_keys);
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        let root = hash_builder.root();
+        let proofs = hash_builder.take_proofs();
+
+        let mpt = MerkleTrie::from_rlp_nodes(proofs.into_values()).unwrap();
+        assert_eq!(mpt.hash_slow(), root);
+        for (key, value) in &leaves {
+            assert_eq!(mpt.get(key.pack()), Some(value.as_slice()));
+        }
+    }
+
+    #[test]
+    pub fn hash_memoized() {
+        let mut children: [Option<Box<Child>>; 16] = Default::default();
+        children[0] = Some(Node::Leaf(Nibbles::from_nibbles([0; 63]), vec![0].into()).boxed());
+        children[1] = Some(Node::Leaf(Nibbles::from_nibbles([1; 63]), vec![1].into()).boxed());
+        let mpt = MerkleTrie::new(Node::Branch(children));
+        let uncached = mpt.clone();
+
+        let hash = mpt.hash_slow();
+        assert_eq!(mpt.root_hash.get(), Some(&hash));
+        assert_eq!(uncached.root_hash.get(), None);
+        // the references of all the children must be memoized as well
+        let Node::Branch(children) = &mpt.root else {
+            unreachable!()
+        };
+        assert!(children
+            .iter()
+            .flatten()
+            .all(|c| c.reference.get().is_some()));
+        // the cache must not have an effect on equality or the hash
+        assert_eq!(mpt, uncached);
+        assert_eq!(mpt.hash_slow(), uncached.hash_slow());
+        assert_eq!(
+            serde_json::to_string(&mpt).unwrap(),
+            serde_json::to_string(&uncached).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn iter_sparse_mpt() {
+        const NUM_LEAVES: usize = 256;
+
+        let leaves: BTreeMap<_, _> = (0..NUM_LEAVES)
+            .map(|i| {
+                let key = U256::from(i);
+                (
+                    Nibbles::unpack(keccak256(key.to_be_bytes::<32>())),
+                    alloy_rlp::encode(key),
+                )
+            })
+            .collect();
+
+        // generate proofs only for every second leaf
+        let proof_keys: Vec<_> = leaves.keys().step_by(2).cloned().collect();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(proof_keys.clone()));
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        hash_builder.root();
+        let proofs = hash_builder.take_proofs();
+        let mpt = MerkleTrie::from_rlp_nodes(proofs.into_values()).unwrap();
+
+        // all the proven leaves must be resolved and returned in order
+        let resolved: Vec<_> = mpt.leaves().map(|(key, _)| key).collect();
+        assert_eq!(resolved, proof_keys);
+        for (key, value) in mpt.leaves() {
+            assert_eq!(value, leaves[&key].as_slice());
+        }
+        // the remaining leaves are hidden behind digests
+        assert!(mpt
+            .iter()
+            .any(|entry| matches!(entry, TrieEntry::Digest(..))));
+
+        // a range between two proven keys must contain the unproven key in between as a digest
+        let start = B256::from_slice(&proof_keys[10].pack());
+        let end = B256::from_slice(&proof_keys[11].pack());
+        let entries: Vec<_> = mpt.range(start..end).collect();
+        assert!(matches!(&entries[0], TrieEntry::Leaf(key, _) if key == &proof_keys[10]));
+        assert!(entries[1..]
+            .iter()
+            .all(|entry| matches!(entry, TrieEntry::Digest(..))));
+        assert!(entries.len() > 1);
+
+        let entries: Vec<_> = mpt.range(start..=end).collect();
+        assert!(matches!(entries.last(), Some(TrieEntry::Leaf(key, _)) if key == &proof_keys[11]));
+    }
+
+    #[test]
+    pub fn range_full_mpt() {
+        const NUM_LEAVES: usize = 256;
+
+        let leaves: BTreeMap<_, _> = (0..NUM_LEAVES)
+            .map(|i| {
+                let key = U256::from(i);
+                (keccak256(key.to_be_bytes::<32>()), alloy_rlp::encode(key))
+            })
+            .collect();
+
+        // generate proofs for all the leaves
+        let proof_keys: Vec<_> = leaves.keys().map(Nibbles::unpack).collect();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(proof_keys));
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(Nibbles::unpack(key), value);
+        }
+        hash_builder.root();
+        let proofs = hash_builder.take_proofs();
+        let mpt = MerkleTrie::from_rlp_nodes(proofs.into_values()).unwrap();
+
+        assert_eq!(mpt.iter().count(), NUM_LEAVES);
+
+        let keys: Vec<_> = leaves.keys().cloned().collect();
+        let range = keys[42]..keys[100];
+        let expected: Vec<_> = leaves.range(range.clone()).collect();
+        let actual: Vec<_> = mpt
+            .range(range)
+            .map(|entry| match entry {
+                TrieEntry::Leaf(key, value) => (B256::from_slice(&key.pack()), value),
+                TrieEntry::Digest(..) => panic!("unexpected digest"),
+            })
+            .collect();
+        assert_eq!(actual.len(), expected.len());
+        for ((key, value), (expected_key, expected_value)) in actual.into_iter().zip(expected) {
+            assert_eq!(&key, expected_key);
+            assert_eq!(value, expected_value.as_slice());
+        }
+
+        // empty ranges
+        assert_eq!(mpt.range(keys[42]..keys[42]).count(), 0);
+        assert_eq!(mpt.range(B256::ZERO..keys[0]).count(), 0);
+        assert_eq!(
+            mpt.range((Bound::Excluded(keys[255]), Bound::Unbounded))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "host")]
+    pub fn prune_and_merge() {
+        let leaves: Vec<_> = (0..256u64)
+            .map(|i| {
+                let key = U256::from(i);
+                (keccak256(key.to_be_bytes::<32>()), alloy_rlp::encode(key))
+            })
+            .collect();
+        let mpt = MerkleTrie::from_leaves(leaves.clone());
+        let keys: Vec<_> = leaves.iter().map(|(key, _)| *key).collect();
+
+        // a pruned trie only resolves the given keys
+        let left = mpt.prune(&keys[..10]);
+        let right = mpt.prune(&keys[5..20]);
+        assert_eq!(left.hash_slow(), mpt.hash_slow());
+        assert_eq!(right.hash_slow(), mpt.hash_slow());
+        assert!(left.size() < mpt.size());
+        for (key, value) in &leaves[..10] {
+            assert_eq!(left.try_get(key), Some(Some(value.as_slice())));
+        }
+        assert_eq!(left.try_get(keys[20]), None);
+
+        // merging resolves the union of the keys
+        let mut merged = left.clone();
+        merged.merge(right);
+        assert_eq!(merged, mpt.prune(&keys[..20]));
+        assert_eq!(merged.hash_slow(), mpt.hash_slow());
+
+        // merging with the full trie resolves everything
+        merged.merge(mpt.clone());
+        assert_eq!(merged, mpt);
+        // pruning to no keys keeps only the root node
+        assert_eq!(mpt.prune::<B256>([]).size(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "host")]
+    pub fn proof() {
+        let leaves: Vec<_> = (0..256u64)
+            .map(|i| {
+                let key = U256::from(i);
+                (keccak256(key.to_be_bytes::<32>()), alloy_rlp::encode(key))
+            })
+            .collect();
+        let mpt = MerkleTrie::from_leaves(leaves.clone());
+
+        for (key, value) in &leaves {
+            // the proof resolves the path to the key and nothing else
+            let proof = mpt.proof(key).unwrap();
+            let proven = MerkleTrie::from_rlp_nodes(&proof).unwrap();
+            assert_eq!(proven.hash_slow(), mpt.hash_slow());
+            assert_eq!(proven.get(key), Some(value.as_slice()));
+            assert_eq!(proven.proof(key), Some(proof));
+        }
+
+        // exclusion proof of a non-existing key
+        let key = keccak256(U256::from(256).to_be_bytes::<32>());
+        let proof = mpt.proof(key).unwrap();
+        let proven = MerkleTrie::from_rlp_nodes(&proof).unwrap();
+        assert_eq!(proven.hash_slow(), mpt.hash_slow());
+        assert_eq!(proven.get(key), None);
+
+        // keys behind unresolved nodes cannot be proven
+        let pruned = mpt.prune([leaves[0].0]);
+        assert_eq!(pruned.proof(leaves[1].0), None);
+        assert_eq!(MerkleTrie::default().proof(key), Some(vec![]));
+    }
+
+    #[test]
+    #[cfg(feature = "host")]
+    pub fn try_get() {
+        let leaves: Vec<_> = (0..256u64)
+            .map(|i| {
+                let key = U256::from(i);
+                (keccak256(key.to_be_bytes::<32>()), alloy_rlp::encode(key))
+            })
+            .collect();
+        let mpt = MerkleTrie::from_leaves(leaves.clone());
+        let pruned = mpt.prune([leaves[0].0]);
+
+        let (key, value) = &leaves[0];
+        assert_eq!(pruned.try_get(key), Some(Some(value.as_slice())));
+        assert_eq!(pruned.get(key), Some(value.as_slice()));
+        // the path to other keys is not resolved
+        assert_eq!(pruned.try_get(leaves[1].0), None);
+        assert_eq!(mpt.try_get(leaves[1].0), Some(Some(leaves[1].1.as_slice())));
+        assert_eq!(mpt.try_get(B256::ZERO), Some(None));
+    }
+
+    #[test]
+    #[should_panic(expected = "unresolved node")]
+    pub fn get_unresolved() {
+        let mpt = MerkleTrie::new(Node::Digest(B256::ZERO));
+        mpt.get(B256::ZERO);
+    }
+
     #[test]
     pub fn parse_empty_proof() {
         let account_proof: Vec<Bytes> = Vec::new();
@@ -0,0 +1,451 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A flat encoding of a [MerkleTrie] that can be accessed without deserializing it.
+//!
+//! The encoding consists of the little-endian `u32` offset of the root node followed by all the
+//! nodes of the trie. Each node starts with a one byte tag followed by its data:
+//! - `Null`: no data
+//! - `Leaf`: number of nibbles as `u32`, one byte per nibble, length of the value as `u32`, value
+//! - `Extension`: number of nibbles as `u32`, one byte per nibble, offset of the child as `u32`
+//! - `Branch`: offsets of the 16 children as `u32`, where `0` denotes an empty child
+//! - `Digest`: the 32-byte hash
+//!
+//! Children are always encoded before their parents, so that every reference points to a lower
+//! offset. This guarantees that the encoding does not contain any cycles.
+
+use super::{
+    put_header, put_string, MerkleTrie, Node, NodeRef, NodeRefBuilder, ParseNodeError, RlpSink,
+    Trie, EMPTY_ROOT_HASH,
+};
+use alloy_primitives::{utils::Keccak256, B256};
+use alloy_rlp::{Encodable, EMPTY_STRING_CODE};
+use nybbles::Nibbles;
+
+const NULL: u8 = 0;
+const LEAF: u8 = 1;
+const EXTENSION: u8 = 2;
+const BRANCH: u8 = 3;
+const DIGEST: u8 = 4;
+
+/// Offset of the root pointer, it is never used by a node and thus denotes an empty child.
+const NO_CHILD: u32 = 0;
+
+impl MerkleTrie {
+    /// Returns the flat encoding of the trie, which can be accessed with [FlatMerkleTrie].
+    pub fn to_flat(&self) -> Vec<u8> {
+        // reserve space for the root offset
+        let mut out = vec![0; 4];
+        let root = write_node(&self.root, &mut out);
+        out[..4].copy_from_slice(&root.to_le_bytes());
+
+        out
+    }
+}
+
+/// Appends the node and all its children to `out` and returns the offset of the node.
+fn write_node(node: &Node, out: &mut Vec<u8>) -> u32 {
+    let offset = match node {
+        Node::Null => {
+            let offset = out.len();
+            out.push(NULL);
+            offset
+        }
+        Node::Leaf(path, value) => {
+            let offset = out.len();
+            out.push(LEAF);
+            write_slice(path, out);
+            write_slice(value, out);
+            offset
+        }
+        Node::Extension(path, child) => {
+            let child = write_node(child, out);
+            let offset = out.len();
+            out.push(EXTENSION);
+            write_slice(path, out);
+            out.extend_from_slice(&child.to_le_bytes());
+            offset
+        }
+        Node::Branch(children) => {
+            let mut child_offsets = [NO_CHILD; 16];
+            for (child_offset, child) in child_offsets.iter_mut().zip(children) {
+                if let Some(node) = child.as_deref() {
+                    *child_offset = write_node(node, out);
+                }
+            }
+            let offset = out.len();
+            out.push(BRANCH);
+            child_offsets
+                .iter()
+                .for_each(|child| out.extend_from_slice(&child.to_le_bytes()));
+            offset
+        }
+        Node::Digest(digest) => {
+            let offset = out.len();
+            out.push(DIGEST);
+            out.extend_from_slice(digest.as_slice());
+            offset
+        }
+    };
+
+    u32::try_from(offset).expect("trie too large")
+}
+
+/// Appends the length prefixed slice to `out`.
+fn write_slice(slice: &[u8], out: &mut Vec<u8>) {
+    let len = u32::try_from(slice.len()).expect("slice too large");
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(slice);
+}
+
+/// A sparse Merkle Patricia trie borrowed from its flat encoding.
+///
+/// The nodes are accessed directly in the underlying buffer without any allocations. It panics,
+/// when the accessed part of the encoding is invalid, e.g. when a path contains a byte that is not
+/// a valid nibble.
+#[derive(Debug, Clone, Copy)]
+pub struct FlatMerkleTrie<'a> {
+    data: &'a [u8],
+    root: u32,
+}
+
+/// A node of a [FlatMerkleTrie], where children are referenced by their offset.
+enum FlatNode<'a> {
+    Null,
+    Leaf(&'a [u8], &'a [u8]),
+    Extension(&'a [u8], u32),
+    Branch(&'a [u8]),
+    Digest(B256),
+}
+
+impl<'a> FlatMerkleTrie<'a> {
+    /// Creates a new trie from its flat encoding as returned by [MerkleTrie::to_flat].
+    ///
+    /// Only the root reference is validated, nodes are validated when they are accessed.
+    pub fn new(data: &'a [u8]) -> Result<Self, ParseNodeError> {
+        let root = data.get(..4).ok_or(ParseNodeError::Flat("missing root"))?;
+        let root = u32::from_le_bytes(root.try_into().unwrap());
+        if root == NO_CHILD || root as usize >= data.len() {
+            return Err(ParseNodeError::Flat("invalid root"));
+        }
+
+        Ok(Self { data, root })
+    }
+
+    /// Returns a reference to the byte value corresponding to the key.
+    ///
+    /// It panics when neither inclusion nor exclusion of the key can be guaranteed.
+    #[inline]
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<&'a [u8]> {
+        self.try_get(key)
+            .expect("Attempted to access unresolved node")
+    }
+
+    /// Returns a reference to the byte value corresponding to the key.
+    ///
+    /// Unlike [FlatMerkleTrie::get], it returns `None` instead of panicking when neither inclusion
+    /// nor exclusion of the key can be guaranteed.
+    pub(crate) fn try_get(&self, key: impl AsRef<[u8]>) -> Option<Option<&'a [u8]>> {
+        let key_nibs = Nibbles::unpack(key);
+        let mut key_nibs = key_nibs.as_slice();
+
+        let mut offset = self.root;
+        loop {
+            match self.node(offset) {
+                FlatNode::Null => return Some(None),
+                FlatNode::Leaf(path, value) => return Some((path == key_nibs).then_some(value)),
+                FlatNode::Extension(path, child) => {
+                    let Some(remaining) = key_nibs.strip_prefix(path) else {
+                        return Some(None);
+                    };
+                    key_nibs = remaining;
+                    offset = self.child(offset, child);
+                }
+                FlatNode::Branch(children) => {
+                    // branch nodes don't have values in our MPT version
+                    let Some((idx, remaining)) = key_nibs.split_first() else {
+                        return Some(None);
+                    };
+                    let child = branch_child(children, *idx as usize);
+                    if child == NO_CHILD {
+                        return Some(None);
+                    }
+                    key_nibs = remaining;
+                    offset = self.child(offset, child);
+                }
+                FlatNode::Digest(_) => return None,
+            }
+        }
+    }
+
+    /// Returns the hash of the trie's root node.
+    pub fn hash_slow(&self) -> B256 {
+        match self.node(self.root) {
+            FlatNode::Null => EMPTY_ROOT_HASH,
+            FlatNode::Digest(digest) => digest,
+            _ => {
+                let mut hasher = Keccak256::new();
+                self.encode_rlp(self.root, &mut hasher);
+                hasher.finalize()
+            }
+        }
+    }
+
+    /// Returns the reference to the node at the given offset as it is used inside of its parent.
+    fn reference(&self, offset: u32) -> NodeRef {
+        match self.node(offset) {
+            FlatNode::Null => NodeRef::Empty,
+            FlatNode::Digest(digest) => NodeRef::Digest(digest),
+            _ => {
+                let mut builder = NodeRefBuilder::default();
+                self.encode_rlp(offset, &mut builder);
+                builder.finish()
+            }
+        }
+    }
+
+    /// Streams the RLP encoding of the node at the given offset into the given sink.
+    fn encode_rlp(&self, offset: u32, out: &mut impl RlpSink) {
+        match self.node(offset) {
+            FlatNode::Null => out.put(&[EMPTY_STRING_CODE]),
+            FlatNode::Leaf(path, value) => {
+                let path = Nibbles::from_nibbles_unchecked(path).encode_path_leaf(true);
+                put_header(out, true, path.as_slice().length() + value.length());
+                put_string(out, &path);
+                put_string(out, value);
+            }
+            FlatNode::Extension(path, child) => {
+                let path = Nibbles::from_nibbles_unchecked(path).encode_path_leaf(false);
+                let child_ref = self.reference(self.child(offset, child));
+                put_header(out, true, path.as_slice().length() + child_ref.length());
+                put_string(out, &path);
+                child_ref.encode(out);
+            }
+            FlatNode::Branch(children) => {
+                let mut child_refs: [NodeRef; 16] = Default::default();
+                let mut payload_length = 1; // start with 1 for the EMPTY_STRING_CODE at the end
+                for (idx, child_ref) in child_refs.iter_mut().enumerate() {
+                    let child = branch_child(children, idx);
+                    if child != NO_CHILD {
+                        *child_ref = self.reference(self.child(offset, child));
+                    }
+                    payload_length += child_ref.length();
+                }
+
+                put_header(out, true, payload_length);
+                child_refs
+                    .iter()
+                    .for_each(|child_ref| child_ref.encode(out));
+                // add an EMPTY_STRING_CODE for the missing value
+                out.put(&[EMPTY_STRING_CODE]);
+            }
+            FlatNode::Digest(digest) => put_string(out, digest.as_slice()),
+        }
+    }
+
+    /// Validates the reference from the parent to the child and returns the child's offset.
+    #[inline]
+    fn child(&self, parent: u32, child: u32) -> u32 {
+        assert!(
+            child != NO_CHILD && child < parent,
+            "invalid flat trie: invalid child reference"
+        );
+        child
+    }
+
+    /// Decodes the node at the given offset.
+    fn node(&self, offset: u32) -> FlatNode<'a> {
+        let mut reader = Reader(&self.data[offset as usize..]);
+        match reader.u8() {
+            NULL => FlatNode::Null,
+            LEAF => FlatNode::Leaf(reader.path(), reader.slice()),
+            EXTENSION => FlatNode::Extension(reader.path(), reader.u32()),
+            BRANCH => FlatNode::Branch(reader.bytes(16 * 4)),
+            DIGEST => FlatNode::Digest(B256::from_slice(reader.bytes(32))),
+            _ => panic!("invalid flat trie: invalid node tag"),
+        }
+    }
+}
+
+impl Trie for FlatMerkleTrie<'_> {
+    #[inline]
+    fn get(&self, key: impl AsRef<[u8]>) -> Option<&[u8]> {
+        FlatMerkleTrie::get(self, key)
+    }
+
+    #[inline]
+    fn try_get(&self, key: impl AsRef<[u8]>) -> Option<Option<&[u8]>> {
+        FlatMerkleTrie::try_get(self, key)
+    }
+
+    #[inline]
+    fn hash_slow(&self) -> B256 {
+        FlatMerkleTrie::hash_slow(self)
+    }
+}
+
+/// Returns the offset of the child with the given index from the encoded children of a branch.
+#[inline]
+fn branch_child(children: &[u8], idx: usize) -> u32 {
+    u32::from_le_bytes(children[idx * 4..(idx + 1) * 4].try_into().unwrap())
+}
+
+/// A simple cursor over the flat encoding that panics when reading past the end.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        assert!(self.0.len() >= len, "invalid flat trie: unexpected end");
+        let (bytes, remaining) = self.0.split_at(len);
+        self.0 = remaining;
+        bytes
+    }
+
+    #[inline]
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    #[inline]
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.bytes(4).try_into().unwrap())
+    }
+
+    #[inline]
+    fn slice(&mut self) -> &'a [u8] {
+        let len = self.u32() as usize;
+        self.bytes(len)
+    }
+
+    /// Reads a path, which must consist of one byte per nibble.
+    ///
+    /// Bytes above `0x0f` would be packed into the same RLP encoding as a valid path, while never
+    /// matching any key. Rejecting them is essential to not prove the exclusion of a present key.
+    #[inline]
+    fn path(&mut self) -> &'a [u8] {
+        let path = self.slice();
+        assert!(
+            path.iter().all(|&nib| nib <= 0x0f),
+            "invalid flat trie: invalid nibble"
+        );
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::{keccak256, U256};
+    use alloy_trie::{proof::ProofRetainer, HashBuilder};
+
+    /// Returns a sparse trie containing proofs for every second of the given number of leaves.
+    fn sparse_mpt(num_leaves: usize) -> MerkleTrie {
+        let leaves: Vec<_> = (0..num_leaves)
+            .map(|i| {
+                let key = U256::from(i);
+                (
+                    Nibbles::unpack(keccak256(key.to_be_bytes::<32>())),
+                    alloy_rlp::encode(key),
+                )
+            })
+            .collect::<std::collections::BTreeMap<_, _>>()
+            .into_iter()
+            .collect();
+
+        let proof_keys = leaves.iter().step_by(2).map(|(k, _)| k.clone()).collect();
+        let mut hash_builder =
+            HashBuilder::default().with_proof_retainer(ProofRetainer::new(proof_keys));
+        for (key, value) in &leaves {
+            hash_builder.add_leaf(key.clone(), value);
+        }
+        hash_builder.root();
+
+        MerkleTrie::from_rlp_nodes(hash_builder.take_proofs().into_values()).unwrap()
+    }
+
+    #[test]
+    fn flat_null() {
+        let mpt = MerkleTrie::default();
+        let flat = mpt.to_flat();
+        let flat_mpt = FlatMerkleTrie::new(&flat).unwrap();
+
+        assert_eq!(flat_mpt.hash_slow(), EMPTY_ROOT_HASH);
+        assert_eq!(flat_mpt.get([]), None);
+        assert_eq!(flat_mpt.get([1, 2, 3]), None);
+    }
+
+    #[test]
+    fn flat_sparse_mpt() {
+        let mpt = sparse_mpt(1024);
+        let flat = mpt.to_flat();
+        let flat_mpt = FlatMerkleTrie::new(&flat).unwrap();
+
+        assert_eq!(flat_mpt.hash_slow(), mpt.hash_slow());
+        for (key, value) in mpt.leaves() {
+            assert_eq!(flat_mpt.get(key.pack()), Some(value));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "unresolved node")]
+    fn flat_get_digest() {
+        let mpt = MerkleTrie::new(Node::Digest(B256::ZERO));
+        let flat = mpt.to_flat();
+        FlatMerkleTrie::new(&flat).unwrap().get([]);
+    }
+
+    #[test]
+    fn flat_try_get_digest() {
+        let mpt = MerkleTrie::new(Node::Digest(B256::ZERO));
+        let flat = mpt.to_flat();
+        assert_eq!(FlatMerkleTrie::new(&flat).unwrap().try_get([]), None);
+    }
+
+    #[test]
+    fn flat_invalid_root() {
+        FlatMerkleTrie::new(&[]).unwrap_err();
+        FlatMerkleTrie::new(&[0, 0, 0, 0, NULL]).unwrap_err();
+        FlatMerkleTrie::new(&[5, 0, 0, 0, NULL]).unwrap_err();
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid nibble")]
+    fn flat_invalid_nibble() {
+        let key = [0x12];
+        let mpt = MerkleTrie::new(Node::Leaf(Nibbles::unpack(key), vec![0x2a].into()));
+        let mut flat = mpt.to_flat();
+        // both paths are packed into the same encoding and thus result in the same root
+        let tampered = [0x00, 0x12];
+        assert_eq!(
+            Nibbles::from_nibbles_unchecked(tampered).encode_path_leaf(true),
+            Nibbles::unpack(key).encode_path_leaf(true)
+        );
+        // root offset, leaf tag and path length precede the path
+        assert_eq!(flat[9..11], [0x01, 0x02]);
+        flat[9..11].copy_from_slice(&tampered);
+
+        let flat_mpt = FlatMerkleTrie::new(&flat).unwrap();
+        flat_mpt.get(key);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid child reference")]
+    fn flat_cyclic_reference() {
+        // an extension node referencing itself
+        let mut flat = vec![4, 0, 0, 0, EXTENSION, 1, 0, 0, 0, 0];
+        flat.extend_from_slice(&4u32.to_le_bytes());
+        FlatMerkleTrie::new(&flat).unwrap().hash_slow();
+    }
+}
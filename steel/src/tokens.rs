@@ -0,0 +1,195 @@
+// Copyright 2024 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bindings and helpers for reading the state of standard token contracts.
+//!
+//! The bindings are generated with [steel_bindings] and can thus be used for the preflight on the
+//! host and for the calls in the guest alike, e.g.
+//! `tokens::IERC20::steel(address, &env).balanceOf(account).call()`. As some tokens, e.g. MKR,
+//! return their name and symbol as `bytes32` instead of `string`, use [name] and [symbol] to
+//! read them from arbitrary tokens.
+//!
+//! [steel_bindings]: crate::steel_bindings
+
+use crate::{steel_bindings, CallBuilder, CallEnv};
+use alloy_primitives::Address;
+use alloy_sol_types::{sol_data, SolCall, SolType};
+
+steel_bindings! {
+    #![sol(all_derives)]
+    /// The read methods of the ERC-20 token standard, including its metadata.
+    pub interface IERC20 {
+        function totalSupply() external view returns (uint256);
+        function balanceOf(address account) external view returns (uint256);
+        function allowance(address owner, address spender) external view returns (uint256);
+        function name() external view returns (string);
+        function symbol() external view returns (string);
+        function decimals() external view returns (uint8);
+    }
+}
+
+steel_bindings! {
+    #![sol(all_derives)]
+    /// The read methods of the ERC-721 non-fungible token standard, including its metadata and
+    /// enumeration extensions.
+    pub interface IERC721 {
+        function supportsInterface(bytes4 interfaceId) external view returns (bool);
+        function balanceOf(address owner) external view returns (uint256);
+        function ownerOf(uint256 tokenId) external view returns (address);
+        function getApproved(uint256 tokenId) external view returns (address);
+        function isApprovedForAll(address owner, address operator) external view returns (bool);
+        function name() external view returns (string);
+        function symbol() external view returns (string);
+        function tokenURI(uint256 tokenId) external view returns (string);
+        function totalSupply() external view returns (uint256);
+        function tokenByIndex(uint256 index) external view returns (uint256);
+        function tokenOfOwnerByIndex(address owner, uint256 index) external view returns (uint256);
+    }
+}
+
+steel_bindings! {
+    #![sol(all_derives)]
+    /// The read methods of the ERC-1155 multi token standard, including its metadata URI.
+    pub interface IERC1155 {
+        function supportsInterface(bytes4 interfaceId) external view returns (bool);
+        function balanceOf(address account, uint256 id) external view returns (uint256);
+        function balanceOfBatch(address[] calldata accounts, uint256[] calldata ids)
+            external view returns (uint256[] memory);
+        function isApprovedForAll(address account, address operator) external view returns (bool);
+        function uri(uint256 id) external view returns (string);
+    }
+}
+
+steel_bindings! {
+    #![sol(all_derives)]
+    /// The read methods of the ERC-4626 tokenized vault standard, including those of its
+    /// ERC-20 shares.
+    pub interface IERC4626 {
+        function totalSupply() external view returns (uint256);
+        function balanceOf(address account) external view returns (uint256);
+        function allowance(address owner, address spender) external view returns (uint256);
+        function name() external view returns (string);
+        function symbol() external view returns (string);
+        function decimals() external view returns (uint8);
+        function asset() external view returns (address);
+        function totalAssets() external view returns (uint256);
+        function convertToShares(uint256 assets) external view returns (uint256);
+        function convertToAssets(uint256 shares) external view returns (uint256);
+        function maxDeposit(address receiver) external view returns (uint256);
+        function maxMint(address receiver) external view returns (uint256);
+        function maxWithdraw(address owner) external view returns (uint256);
+        function maxRedeem(address owner) external view returns (uint256);
+        function previewDeposit(uint256 assets) external view returns (uint256);
+        function previewMint(uint256 shares) external view returns (uint256);
+        function previewWithdraw(uint256 assets) external view returns (uint256);
+        function previewRedeem(uint256 shares) external view returns (uint256);
+    }
+}
+
+/// Returns a builder calling `name()` on the token at the given address.
+///
+/// The name is decoded from a `string` or, for non-standard tokens, from a `bytes32`.
+pub fn name<E: CallEnv>(
+    address: Address,
+    env: E,
+) -> CallBuilder<StringOrBytes32<IERC20::nameCall>, E> {
+    env.call_builder(address, &StringOrBytes32(IERC20::nameCall {}))
+}
+
+/// Returns a builder calling `symbol()` on the token at the given address.
+///
+/// The symbol is decoded from a `string` or, for non-standard tokens, from a `bytes32`.
+pub fn symbol<E: CallEnv>(
+    address: Address,
+    env: E,
+) -> CallBuilder<StringOrBytes32<IERC20::symbolCall>, E> {
+    env.call_builder(address, &StringOrBytes32(IERC20::symbolCall {}))
+}
+
+/// A call returning a string, which also accepts a right-padded `bytes32` as return value.
+///
+/// It encodes exactly like the wrapped call, but its [SolCall::Return] is the [String] itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StringOrBytes32<C>(pub C);
+
+impl<C: SolCall> SolCall for StringOrBytes32<C> {
+    type Parameters<'a> = C::Parameters<'a>;
+    type Token<'a> = C::Token<'a>;
+    type Return = String;
+    type ReturnTuple<'a> = (sol_data::String,);
+    type ReturnToken<'a> = <(sol_data::String,) as SolType>::Token<'a>;
+
+    const SIGNATURE: &'static str = C::SIGNATURE;
+    const SELECTOR: [u8; 4] = C::SELECTOR;
+
+    fn new(tuple: <Self::Parameters<'_> as SolType>::RustType) -> Self {
+        Self(C::new(tuple))
+    }
+
+    fn tokenize(&self) -> Self::Token<'_> {
+        self.0.tokenize()
+    }
+
+    fn abi_decode_returns(data: &[u8], validate: bool) -> alloy_sol_types::Result<String> {
+        if let Ok((string,)) = <(sol_data::String,)>::abi_decode_sequence(data, validate) {
+            return Ok(string);
+        }
+        // a bytes32 is exactly one word, padded with zeros on the right
+        if data.len() != 32 {
+            return Err(alloy_sol_types::Error::type_check_fail(
+                data,
+                "string or bytes32",
+            ));
+        }
+        let len = data.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        String::from_utf8(data[..len].to_vec())
+            .map_err(|_| alloy_sol_types::Error::type_check_fail(data, "bytes32 string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::B256;
+
+    #[test]
+    fn string_or_bytes32() {
+        type Call = StringOrBytes32<IERC20::nameCall>;
+        assert_eq!(Call::SELECTOR, IERC20::nameCall::SELECTOR);
+        assert_eq!(
+            StringOrBytes32(IERC20::nameCall {}).abi_encode(),
+            IERC20::nameCall {}.abi_encode()
+        );
+
+        let string = IERC20::nameCall::abi_encode_returns(&("Tether USD".to_string(),));
+        assert_eq!(
+            Call::abi_decode_returns(&string, true).unwrap(),
+            "Tether USD"
+        );
+
+        let mut bytes32 = B256::ZERO;
+        bytes32[..5].copy_from_slice(b"Maker");
+        assert_eq!(
+            Call::abi_decode_returns(bytes32.as_slice(), true).unwrap(),
+            "Maker"
+        );
+        assert_eq!(
+            Call::abi_decode_returns(B256::ZERO.as_slice(), true).unwrap(),
+            ""
+        );
+
+        assert!(Call::abi_decode_returns(&[0xff; 32], true).is_err());
+        assert!(Call::abi_decode_returns(&[0; 31], true).is_err());
+    }
+}
@@ -14,36 +14,42 @@
 
 #![cfg(feature = "host")]
 
-use alloy_primitives::{address, b256, uint, Address, U256};
+use alloy_primitives::{
+    address, b256, hex, keccak256, uint, Address, Bytes, StorageKey, StorageValue, B256, U256,
+};
 use alloy_sol_types::{sol, SolCall};
+use revm::primitives::SpecId;
 use risc0_steel::{
-    config::{ChainSpec, ETH_MAINNET_CHAIN_SPEC, ETH_SEPOLIA_CHAIN_SPEC},
-    ethereum::EthEvmEnv,
-    host, CallBuilder, Contract,
+    config::{
+        ChainSpec, EIP1559_CONSTANTS_DEFAULT, ETH_MAINNET_CHAIN_SPEC, ETH_SEPOLIA_CHAIN_SPEC,
+    },
+    ethereum::{EthBlockHeader, EthEvmEnv, EthEvmInput, EthFlatEvmInput},
+    host::{
+        self,
+        provider::{
+            AsyncProvider, EIP1186Proof, ExecutionWitness, GenesisAccount, MemoryProvider,
+            PrestateAccount, PrestateTrace, Provider, TraceCall, TraceProvider,
+        },
+        testing::{self, CallOverrides, TestProvider},
+        AccessListItem, MissingState, PreflightSnapshot,
+    },
+    steel_bindings, tokens, Contract, EnvelopeError, FlatDecodeError, VersionedEvmInput,
+};
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Debug,
+    rc::Rc,
+    sync::Mutex,
+    time::Duration,
 };
-use std::fmt::Debug;
 use test_log::test;
 
-macro_rules! provider {
-    () => {
-        test_provider!()
-        // use the following to fill the cache file
-        // test_provider!("<RPC-URL>")
-    };
-}
-
 const RPC_CACHE_FILE: &str = "testdata/rpc_cache.json";
 
-// Create a file provider or a cached Ethers provider when an URL is specified.
-macro_rules! test_provider {
-    () => {
-        host::provider::EthFileProvider::from_file(&RPC_CACHE_FILE.into()).unwrap()
-    };
-    ($url:tt) => {{
-        let client = host::EthersClient::new_client($url, 3, 500).unwrap();
-        let provider = host::provider::EthersProvider::new(client);
-        host::provider::CachedProvider::new(RPC_CACHE_FILE.into(), provider).unwrap()
-    }};
+/// Returns the provider replaying the cache file, or recording it when `RPC_URL` is set.
+fn test_provider() -> TestProvider {
+    TestProvider::new(RPC_CACHE_FILE).unwrap()
 }
 
 const ERC20_TEST_CONTRACT: Address = address!("dAC17F958D2ee523a2206206994597C13D831ec7"); // USDT
@@ -64,7 +70,7 @@ fn erc20_balance_of() {
     let result = eth_call(
         call,
         ERC20_TEST_CONTRACT,
-        BuilderOverrides::default(),
+        CallOverrides::default(),
         ERC20_TEST_BLOCK,
         &ETH_MAINNET_CHAIN_SPEC,
     );
@@ -80,7 +86,7 @@ fn erc20_multi_balance_of() {
         account: address!("5a52E96BAcdaBb82fd05763E25335261B270Efcb"),
     };
 
-    let mut env = EthEvmEnv::from_provider(provider!(), ERC20_TEST_BLOCK)
+    let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
         .unwrap()
         .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
     let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
@@ -97,6 +103,1116 @@ fn erc20_multi_balance_of() {
     assert_eq!(result2._0, uint!(0x38d7ea4c68000_U256));
 }
 
+steel_bindings! {
+    interface IERC20Bindings {
+        event Transfer(address indexed from, address indexed to, uint256 value);
+        function balanceOf(address account) external view returns (uint);
+    }
+}
+
+#[test]
+fn erc20_bindings() {
+    let account1 = address!("F977814e90dA44bFA03b6295A0616a897441aceC");
+    let account2 = address!("5a52E96BAcdaBb82fd05763E25335261B270Efcb");
+
+    let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
+        .unwrap()
+        .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let preflight = IERC20Bindings::steel(ERC20_TEST_CONTRACT, &mut env)
+        .balanceOf(account1)
+        .call()
+        .unwrap();
+    IERC20Bindings::steel(ERC20_TEST_CONTRACT, &mut env)
+        .balanceOf(account2)
+        .call()
+        .unwrap();
+    let input = env.into_input().unwrap();
+
+    // the bindings are used the same way in the guest
+    let env = input.into_env().with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let contract = IERC20Bindings::steel(ERC20_TEST_CONTRACT, &env);
+    let result1 = contract.balanceOf(account1).call();
+    let result2 = contract.balanceOf(account2).call();
+    assert_eq!(result1._0, preflight._0);
+    assert_eq!(result1._0, uint!(3000000000000000_U256));
+    assert_eq!(result2._0, uint!(0x38d7ea4c68000_U256));
+
+    // the generated types are available as well
+    let call = IERC20Bindings::balanceOfCall { account: account1 };
+    assert_eq!(
+        call.abi_encode(),
+        IERC20::balanceOfCall { account: account1 }.abi_encode()
+    );
+}
+
+steel_bindings! {
+    interface ILarge {
+        event Updated(address indexed sender, uint256 value);
+        struct Pair { uint256 first; uint256 second; }
+        function value0() external view returns (uint256);
+        function value1() external view returns (uint256);
+        function value2() external view returns (uint256);
+        function value3() external view returns (uint256);
+        function value4() external view returns (uint256);
+        function value5() external view returns (uint256);
+        function value6() external view returns (uint256);
+        function value7() external view returns (uint256);
+        function value8() external view returns (uint256);
+        function value9() external view returns (uint256);
+        function value10() external view returns (uint256);
+        function value11() external view returns (uint256);
+        function value12() external view returns (uint256);
+        function value13() external view returns (uint256);
+        function value14() external view returns (uint256);
+        function value15() external view returns (uint256);
+        function value16() external view returns (uint256);
+        function value17() external view returns (uint256);
+        function value18() external view returns (uint256);
+        function value19() external view returns (uint256);
+        function value20() external view returns (uint256);
+        function value21() external view returns (uint256);
+        function value22() external view returns (uint256);
+        function value23() external view returns (uint256);
+        function value24() external view returns (uint256);
+        function value25() external view returns (uint256);
+        function value26() external view returns (uint256);
+        function value27() external view returns (uint256);
+        function value28() external view returns (uint256);
+        function value29() external view returns (uint256);
+        function value30() external view returns (uint256);
+        function value31() external view returns (uint256);
+        function value32() external view returns (uint256);
+        function value33() external view returns (uint256);
+        function value34() external view returns (uint256);
+        function value35() external view returns (uint256);
+        function value36() external view returns (uint256);
+        function value37() external view returns (uint256);
+        function value38() external view returns (uint256);
+        function value39() external view returns (uint256);
+        function valueOf(address account, uint256[] calldata ids) external view returns (uint256);
+        function pairOf(Pair memory pair) external view returns (uint256);
+    }
+}
+
+#[test]
+fn large_bindings() {
+    let (address, provider) = storage_contract_provider(B256::with_last_byte(42));
+
+    let mut env = EthEvmEnv::from_provider(provider, 100).unwrap();
+    let pair = ILarge::Pair {
+        first: U256::from(1),
+        second: U256::from(2),
+    };
+    let first = ILarge::steel(address, &mut env).value0().call().unwrap();
+    let last = ILarge::steel(address, &mut env).value39().call().unwrap();
+    let value = ILarge::steel(address, &mut env)
+        .valueOf(Address::ZERO, vec![U256::from(1)])
+        .call()
+        .unwrap();
+    let pair = ILarge::steel(address, &mut env)
+        .pairOf(pair)
+        .call()
+        .unwrap();
+    for result in [first._0, last._0, value._0, pair._0] {
+        assert_eq!(result, uint!(42_U256));
+    }
+
+    let input = env.into_input().unwrap();
+    let env = input.into_env();
+    assert_eq!(
+        ILarge::steel(address, &env).value39().call()._0,
+        uint!(42_U256)
+    );
+}
+
+#[test]
+fn erc20_deterministic_input() {
+    let call1 = IERC20::balanceOfCall {
+        account: address!("F977814e90dA44bFA03b6295A0616a897441aceC"),
+    };
+    let call2 = IERC20::balanceOfCall {
+        account: address!("5a52E96BAcdaBb82fd05763E25335261B270Efcb"),
+    };
+
+    let preflight = |calls: [&IERC20::balanceOfCall; 2]| {
+        let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
+            .unwrap()
+            .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+        let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+        for call in calls {
+            contract.call_builder(call).call().unwrap();
+        }
+        env.into_input().unwrap()
+    };
+
+    // the same calls in a different order must result in the identical input
+    let input1 = preflight([&call1, &call2]);
+    let input2 = preflight([&call2, &call1]);
+    assert_eq!(
+        serde_json::to_string(&input1).unwrap(),
+        serde_json::to_string(&input2).unwrap()
+    );
+    assert_eq!(input1.digest(), input2.digest());
+
+    // the digest must not depend on the order of the collections
+    let digest = input1.digest();
+    let mut input = input1;
+    input.storage_tries.reverse();
+    input.contracts.reverse();
+    assert_eq!(input.digest(), digest);
+}
+
+#[test]
+fn shared_storage_root_input() {
+    // two contracts with identical storage, each returning a different slot
+    let storage: BTreeMap<_, _> = [
+        (B256::ZERO, B256::with_last_byte(42)),
+        (B256::with_last_byte(1), B256::with_last_byte(43)),
+    ]
+    .into();
+    let slot0 = address!("1111111111111111111111111111111111111111");
+    let slot1 = address!("2222222222222222222222222222222222222222");
+    let provider = MemoryProvider::new()
+        .with_account(
+            slot0,
+            GenesisAccount {
+                code: hex!("60005460005260206000f3").into(),
+                storage: storage.clone(),
+                ..Default::default()
+            },
+        )
+        .with_account(
+            slot1,
+            GenesisAccount {
+                code: hex!("60015460005260206000f3").into(),
+                storage,
+                ..Default::default()
+            },
+        )
+        .with_block_number(100);
+
+    let mut env = EthEvmEnv::from_provider(provider, 100).unwrap();
+    Contract::preflight(slot0, &mut env)
+        .call_builder(&IStorage::valueCall {})
+        .call()
+        .unwrap();
+    Contract::preflight(slot1, &mut env)
+        .call_builder(&IStorage::valueCall {})
+        .call()
+        .unwrap();
+    let input = env.into_input().unwrap();
+    assert_eq!(input.storage_tries.len(), 1);
+
+    // the single storage trie must contain the slots accessed by both contracts
+    let env = input.into_env();
+    let result = Contract::new(slot0, &env)
+        .call_builder(&IStorage::valueCall {})
+        .call();
+    assert_eq!(result._0, uint!(42_U256));
+    let result = Contract::new(slot1, &env)
+        .call_builder(&IStorage::valueCall {})
+        .call();
+    assert_eq!(result._0, uint!(43_U256));
+}
+
+#[test]
+fn erc20_versioned_input() {
+    let call = IERC20::balanceOfCall {
+        account: address!("F977814e90dA44bFA03b6295A0616a897441aceC"),
+    };
+
+    let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
+        .unwrap()
+        .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+    contract.call_builder(&call).call().unwrap();
+    let input = env.into_versioned_input().unwrap();
+
+    // the envelope must survive a serialization roundtrip
+    let serialized = serde_json::to_string(&input).unwrap();
+    let input: VersionedEvmInput<EthBlockHeader> = serde_json::from_str(&serialized).unwrap();
+    assert_eq!(input.chain_id(), ETH_MAINNET_CHAIN_SPEC.chain_id());
+    assert!(input
+        .check_chain_id(ETH_SEPOLIA_CHAIN_SPEC.chain_id())
+        .is_err());
+
+    let env = input.into_env(&ETH_MAINNET_CHAIN_SPEC);
+    let contract = Contract::new(ERC20_TEST_CONTRACT, &env);
+    let result = contract.call_builder(&call).call();
+    assert_eq!(result._0, uint!(3000000000000000_U256));
+}
+
+#[test]
+fn erc20_unversioned_input() {
+    let call = IERC20::balanceOfCall {
+        account: address!("F977814e90dA44bFA03b6295A0616a897441aceC"),
+    };
+
+    let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
+        .unwrap()
+        .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+    contract.call_builder(&call).call().unwrap();
+    let input = env.into_input().unwrap();
+
+    // unversioned inputs are read with the format of the first version
+    let serialized = serde_json::to_string(&input).unwrap();
+    let read = |chain_id| {
+        let mut deserializer = serde_json::Deserializer::from_str(&serialized);
+        VersionedEvmInput::<EthBlockHeader>::deserialize_payload(1, chain_id, &mut deserializer)
+            .unwrap()
+    };
+
+    let result = read(ETH_SEPOLIA_CHAIN_SPEC.chain_id()).try_into_env(&ETH_MAINNET_CHAIN_SPEC);
+    assert!(matches!(result, Err(EnvelopeError::ChainIdMismatch { .. })));
+
+    let env = read(ETH_MAINNET_CHAIN_SPEC.chain_id())
+        .try_into_env(&ETH_MAINNET_CHAIN_SPEC)
+        .unwrap();
+    let contract = Contract::new(ERC20_TEST_CONTRACT, &env);
+    let result = contract.call_builder(&call).call();
+    assert_eq!(result._0, uint!(3000000000000000_U256));
+}
+
+#[test]
+fn erc20_flat_input() {
+    let call = IERC20::balanceOfCall {
+        account: address!("F977814e90dA44bFA03b6295A0616a897441aceC"),
+    };
+
+    let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
+        .unwrap()
+        .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+    contract.call_builder(&call).call().unwrap();
+    let flat = env.into_input().unwrap().to_flat();
+
+    let env = EthFlatEvmInput::decode(&flat)
+        .unwrap()
+        .into_env()
+        .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let contract = Contract::new(ERC20_TEST_CONTRACT, &env);
+    let result = contract.call_builder(&call).call();
+    assert_eq!(result._0, uint!(3000000000000000_U256));
+
+    // trailing bytes inside of the header slice must be rejected
+    let header_len = u32::from_le_bytes(flat[..4].try_into().unwrap());
+    let mut tampered = (header_len + 1).to_le_bytes().to_vec();
+    tampered.extend_from_slice(&flat[4..4 + header_len as usize]);
+    tampered.push(0);
+    tampered.extend_from_slice(&flat[4 + header_len as usize..]);
+    assert!(matches!(
+        EthFlatEvmInput::decode(&tampered),
+        Err(FlatDecodeError::TrailingData)
+    ));
+}
+
+#[test]
+fn erc20_async_preflight() {
+    let call1 = IERC20::balanceOfCall {
+        account: address!("F977814e90dA44bFA03b6295A0616a897441aceC"),
+    };
+    let call2 = IERC20::balanceOfCall {
+        account: address!("5a52E96BAcdaBb82fd05763E25335261B270Efcb"),
+    };
+
+    // a single-threaded runtime must be sufficient for async preflights
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let async_input = runtime.block_on(async {
+        let provider = AsyncTestProvider(Mutex::new(test_provider()));
+        let mut env = EthEvmEnv::from_provider_async(provider, ERC20_TEST_BLOCK)
+            .await
+            .unwrap()
+            .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+        let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+        contract.call_builder(&call1).call().await.unwrap();
+        contract.call_builder(&call2).call().await.unwrap();
+        env.into_input().await.unwrap()
+    });
+
+    // the async preflight must result in the same input as the blocking one
+    let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
+        .unwrap()
+        .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+    contract.call_builder(&call1).call().unwrap();
+    contract.call_builder(&call2).call().unwrap();
+    let input = env.into_input().unwrap();
+    assert_eq!(async_input.digest(), input.digest());
+}
+
+#[test]
+fn erc20_async_cancelled_call() {
+    let call1 = IERC20::balanceOfCall {
+        account: address!("F977814e90dA44bFA03b6295A0616a897441aceC"),
+    };
+    let call2 = IERC20::balanceOfCall {
+        account: address!("5a52E96BAcdaBb82fd05763E25335261B270Efcb"),
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let async_input = runtime.block_on(async {
+        let provider = AsyncTestProvider(Mutex::new(test_provider()));
+        let mut env = EthEvmEnv::from_provider_async(provider, ERC20_TEST_BLOCK)
+            .await
+            .unwrap()
+            .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+        let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+        // dropping an unfinished call must not lose the database
+        let call = contract.call_builder(&call1).call();
+        let _ = tokio::time::timeout(Duration::ZERO, call).await;
+        // enabling the verification must wait for the dropped call
+        let mut env = env.with_verification().await;
+        let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+        contract.call_builder(&call2).call().await.unwrap();
+        env.into_input().await.unwrap()
+    });
+
+    // the state accessed by the dropped call is still part of the input
+    let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
+        .unwrap()
+        .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+    contract.call_builder(&call1).call().unwrap();
+    contract.call_builder(&call2).call().unwrap();
+    let input = env.into_input().unwrap();
+    assert_eq!(async_input.digest(), input.digest());
+}
+
+#[test]
+fn erc20_cached_queries() {
+    let call = IERC20::balanceOfCall {
+        account: address!("F977814e90dA44bFA03b6295A0616a897441aceC"),
+    };
+
+    let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
+        .unwrap()
+        .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+    contract.call_builder(&call).call().unwrap();
+    let stats = env.db().stats();
+    assert!(stats.rpc_calls > 0);
+
+    // repeating the call must be answered completely from the cache
+    let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+    contract.call_builder(&call).call().unwrap();
+    let repeated = env.db().stats();
+    assert_eq!(repeated.rpc_calls, stats.rpc_calls);
+    assert!(repeated.cache_hits > stats.cache_hits);
+
+    // only accounts with accessed storage must be proven again
+    let missing = env.db().missing_proofs();
+    assert_eq!(missing.len(), 1);
+    assert_eq!(missing[0].0, ERC20_TEST_CONTRACT);
+}
+
+#[test]
+fn trace_empty_prestate_account() {
+    let (address, provider) = storage_contract_provider(B256::with_last_byte(42));
+
+    // the tracer lists touched but non-existing accounts with empty values
+    let missing = address!("2222222222222222222222222222222222222222");
+    let prestate = PrestateTrace::from([
+        (
+            address,
+            PrestateAccount {
+                code: STORAGE_CODE.into(),
+                ..Default::default()
+            },
+        ),
+        (missing, PrestateAccount::default()),
+    ]);
+    let proof_requests = Rc::new(Cell::new(0));
+    let provider = TraceTestProvider {
+        inner: provider,
+        prestate,
+        witness: None,
+        proof_requests: proof_requests.clone(),
+    };
+
+    let mut db = host::db::TraceDb::new(provider, 100);
+    db.trace(&TraceCall::default()).unwrap();
+    assert!(revm::Database::basic(&mut db, address).unwrap().is_some());
+    assert_eq!(proof_requests.get(), 0);
+
+    // like in the guest, the empty account must not exist
+    assert_eq!(revm::Database::basic(&mut db, missing).unwrap(), None);
+    assert_eq!(proof_requests.get(), 1);
+}
+
+#[test]
+fn erc20_trace_preflight() {
+    let call = IERC20::balanceOfCall {
+        account: address!("F977814e90dA44bFA03b6295A0616a897441aceC"),
+    };
+
+    // preflight with the default strategy to learn the accessed state
+    let mut env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK)
+        .unwrap()
+        .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+    let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+    let expected_result = contract.call_builder(&call).call().unwrap();
+    let accounts = env.db().accounts().clone();
+    let expected_input = env.into_input().unwrap();
+
+    // create the corresponding prestate trace and a witness containing all the proofs
+    let contracts: HashMap<_, _> = expected_input
+        .contracts
+        .iter()
+        .map(|code| (keccak256(code), code.clone()))
+        .collect();
+    let provider = test_provider();
+    let mut prestate = PrestateTrace::new();
+    let mut witness = ExecutionWitness::default();
+    for (address, keys) in accounts {
+        let keys: Vec<StorageKey> = keys.into_iter().map(StorageKey::from).collect();
+        let proof = provider
+            .get_proof(address, keys.clone(), ERC20_TEST_BLOCK)
+            .unwrap();
+        witness.state.extend(proof.account_proof);
+        for storage_proof in proof.storage_proof {
+            witness.state.extend(storage_proof.proof);
+        }
+
+        let storage = keys
+            .into_iter()
+            .map(|key| {
+                let value = provider.get_storage_at(address, key, ERC20_TEST_BLOCK);
+                (key, value.unwrap().into())
+            })
+            .collect();
+        let account = PrestateAccount {
+            balance: proof.balance,
+            nonce: proof.nonce,
+            code: contracts.get(&proof.code_hash).cloned().unwrap_or_default(),
+            storage,
+        };
+        prestate.insert(address, account);
+    }
+
+    for (witness, expected_proofs) in [(None, prestate.len()), (Some(witness), 0)] {
+        let proof_requests = Rc::new(Cell::new(0));
+        let provider = TraceTestProvider {
+            inner: test_provider(),
+            prestate: prestate.clone(),
+            witness,
+            proof_requests: proof_requests.clone(),
+        };
+        let mut env = EthEvmEnv::from_trace_provider(provider, ERC20_TEST_BLOCK)
+            .unwrap()
+            .with_chain_spec(&ETH_MAINNET_CHAIN_SPEC);
+        let mut contract = Contract::preflight(ERC20_TEST_CONTRACT, &mut env);
+        let result = contract.call_builder(&call).call().unwrap();
+        assert_eq!(result._0, expected_result._0);
+        assert_eq!(proof_requests.get(), 0);
+
+        // the traced preflight must result in the same input as the default one
+        let input = env.into_input().unwrap();
+        assert_eq!(input.digest(), expected_input.digest());
+        assert_eq!(proof_requests.get(), expected_proofs);
+    }
+}
+
+#[test]
+fn check_commitment() {
+    let env = EthEvmEnv::from_provider(test_provider(), ERC20_TEST_BLOCK).unwrap();
+    let commitment = env.block_commitment();
+
+    // the commitment is valid in the 256 blocks following the committed block
+    let status = host::check_commitment(&test_provider(), &commitment, ERC20_TEST_BLOCK).unwrap();
+    assert!(status.canonical);
+    assert_eq!(status.remaining_blocks, host::BLOCKHASH_WINDOW);
+    assert!(status.is_valid());
+
+    let head = ERC20_TEST_BLOCK + host::BLOCKHASH_WINDOW - 1;
+    let status = host::check_commitment(&test_provider(), &commitment, head).unwrap();
+    assert_eq!(status.remaining_blocks, 1);
+    assert!(status.is_valid());
+
+    let head = ERC20_TEST_BLOCK + host::BLOCKHASH_WINDOW;
+    let status = host::check_commitment(&test_provider(), &commitment, head).unwrap();
+    assert_eq!(status.remaining_blocks, 0);
+    assert!(!status.is_valid());
+
+    // a commitment to a different block hash is not canonical
+    let mut reorged = commitment.clone();
+    reorged.blockHash = B256::ZERO;
+    let status = host::check_commitment(&test_provider(), &reorged, ERC20_TEST_BLOCK).unwrap();
+    assert!(!status.canonical);
+    assert!(!status.is_valid());
+
+    // commitments to future blocks are rejected
+    host::check_commitment(&test_provider(), &commitment, ERC20_TEST_BLOCK - 1).unwrap_err();
+}
+
+sol! {
+    interface IStorage {
+        function value() external view returns (uint256);
+    }
+}
+
+/// Runtime code returning the value of the storage slot 0 for any call.
+const STORAGE_CODE: [u8; 11] = hex!("60005460005260206000f3");
+
+/// Returns a contract running [STORAGE_CODE] with the given value in its storage slot 0.
+fn storage_contract(value: B256) -> GenesisAccount {
+    GenesisAccount {
+        code: STORAGE_CODE.into(),
+        storage: [(B256::ZERO, value)].into(),
+        ..Default::default()
+    }
+}
+
+/// Returns a [MemoryProvider] for block 100 containing only a [storage_contract] with the given
+/// value.
+fn storage_contract_provider(value: B256) -> (Address, MemoryProvider) {
+    let address = address!("1111111111111111111111111111111111111111");
+    let provider = MemoryProvider::new()
+        .with_account(address, storage_contract(value))
+        .with_block_number(100);
+    (address, provider)
+}
+
+#[test]
+fn memory_provider() {
+    let (address, provider) = storage_contract_provider(B256::with_last_byte(42));
+
+    // preflight the call on the host
+    let mut env = EthEvmEnv::from_provider(provider, 100).unwrap();
+    let mut contract = Contract::preflight(address, &mut env);
+    let result = contract
+        .call_builder(&IStorage::valueCall {})
+        .call()
+        .unwrap();
+    assert_eq!(result._0, uint!(42_U256));
+
+    // the proofs computed by the provider must be accepted in the guest
+    let input = env.into_input().unwrap();
+    let env = input.into_env();
+    let contract = Contract::new(address, &env);
+    let result = contract.call_builder(&IStorage::valueCall {}).call();
+    assert_eq!(result._0, uint!(42_U256));
+}
+
+#[test]
+fn tokens_bytes32_symbol() {
+    let mut symbol = B256::ZERO;
+    symbol[..3].copy_from_slice(b"MKR");
+    let (address, provider) = storage_contract_provider(symbol);
+
+    let mut env = EthEvmEnv::from_provider(provider, 100).unwrap();
+    // a bytes32 cannot be decoded as the string of the standard
+    tokens::IERC20::steel(address, &mut env)
+        .symbol()
+        .call()
+        .unwrap_err();
+    assert_eq!(tokens::symbol(address, &mut env).call().unwrap(), "MKR");
+
+    let input = env.into_input().unwrap();
+    let env = input.into_env();
+    assert_eq!(tokens::symbol(address, &env).call(), "MKR");
+}
+
+/// Returns a contract returning the given data for any call.
+fn returning_contract(data: &[u8]) -> GenesisAccount {
+    let len = u16::try_from(data.len()).unwrap().to_be_bytes();
+    // CODECOPY the data appended to the 14 bytes of code into memory and RETURN it
+    let mut code = hex!("610000600e6000396100006000f3").to_vec();
+    code[1..3].copy_from_slice(&len);
+    code[9..11].copy_from_slice(&len);
+    code.extend_from_slice(data);
+    GenesisAccount {
+        code: code.into(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn tokens_erc721_token_uri() {
+    let uri = "ipfs://token/7".to_string();
+    let address = address!("1111111111111111111111111111111111111111");
+    let returns = tokens::IERC721::tokenURICall::abi_encode_returns(&(uri.clone(),));
+    let provider = MemoryProvider::new()
+        .with_account(address, returning_contract(&returns))
+        .with_block_number(100);
+
+    let mut env = EthEvmEnv::from_provider(provider, 100).unwrap();
+    let result = tokens::IERC721::steel(address, &mut env)
+        .tokenURI(U256::from(7))
+        .call()
+        .unwrap();
+    assert_eq!(result._0, uri);
+
+    let input = env.into_input().unwrap();
+    let env = input.into_env();
+    let result = tokens::IERC721::steel(address, &env)
+        .tokenURI(U256::from(7))
+        .call();
+    assert_eq!(result._0, uri);
+}
+
+#[test]
+fn tokens_erc1155() {
+    let balances = vec![uint!(1_U256), uint!(2_U256)];
+    let uri = "https://token/{id}.json".to_string();
+    let batch = address!("1111111111111111111111111111111111111111");
+    let metadata = address!("2222222222222222222222222222222222222222");
+    let provider = MemoryProvider::new()
+        .with_account(
+            batch,
+            returning_contract(&tokens::IERC1155::balanceOfBatchCall::abi_encode_returns(
+                &(balances.clone(),),
+            )),
+        )
+        .with_account(
+            metadata,
+            returning_contract(&tokens::IERC1155::uriCall::abi_encode_returns(&(
+                uri.clone(),
+            ))),
+        )
+        .with_block_number(100);
+    let accounts = vec![Address::ZERO, Address::ZERO];
+    let ids = vec![uint!(1_U256), uint!(2_U256)];
+
+    let mut env = EthEvmEnv::from_provider(provider, 100).unwrap();
+    let result = tokens::IERC1155::steel(batch, &mut env)
+        .balanceOfBatch(accounts.clone(), ids.clone())
+        .call()
+        .unwrap();
+    assert_eq!(result._0, balances);
+    let result = tokens::IERC1155::steel(metadata, &mut env)
+        .uri(U256::from(1))
+        .call()
+        .unwrap();
+    assert_eq!(result._0, uri);
+
+    let input = env.into_input().unwrap();
+    let env = input.into_env();
+    let result = tokens::IERC1155::steel(batch, &env)
+        .balanceOfBatch(accounts, ids)
+        .call();
+    assert_eq!(result._0, balances);
+    let result = tokens::IERC1155::steel(metadata, &env)
+        .uri(U256::from(1))
+        .call();
+    assert_eq!(result._0, uri);
+}
+
+#[test]
+fn tokens_erc4626_convert_to_assets() {
+    // runtime code returning twice its first argument, i.e. a vault with two assets per share
+    let code = hex!("60043560020260005260206000f3");
+    let address = address!("1111111111111111111111111111111111111111");
+    let provider = MemoryProvider::new()
+        .with_account(
+            address,
+            GenesisAccount {
+                code: code.into(),
+                ..Default::default()
+            },
+        )
+        .with_block_number(100);
+
+    let mut env = EthEvmEnv::from_provider(provider, 100).unwrap();
+    for shares in [0, 21, 500] {
+        let result = tokens::IERC4626::steel(address, &mut env)
+            .convertToAssets(U256::from(shares))
+            .call()
+            .unwrap();
+        assert_eq!(result._0, U256::from(2 * shares));
+    }
+
+    let input = env.into_input().unwrap();
+    let env = input.into_env();
+    let result = tokens::IERC4626::steel(address, &env)
+        .convertToAssets(U256::from(21))
+        .call();
+    assert_eq!(result._0, uint!(42_U256));
+}
+
+#[test]
+fn input_stats() {
+    let (address, provider) = storage_contract_provider(B256::with_last_byte(42));
+
+    let mut env = EthEvmEnv::from_provider(provider, 100).unwrap();
+    Contract::preflight(address, &mut env)
+        .call_builder(&IStorage::valueCall {})
+        .call()
+        .unwrap();
+    let (input, stats) = env.into_input_with_stats().unwrap();
+
+    assert_eq!(stats.storage_slots.get(&address), Some(&1));
+    assert_eq!(stats.total_storage_slots(), 1);
+    assert_eq!(stats.state_trie.nodes, input.state_trie.size());
+    assert_eq!(stats.storage_tries.len(), input.storage_tries.len());
+    assert_eq!(
+        stats.contracts.get(&keccak256(STORAGE_CODE)),
+        Some(&STORAGE_CODE.len())
+    );
+    assert_eq!(stats.ancestor_depth, 0);
+    assert_eq!(
+        stats.total_bytes,
+        bincode::serialized_size(&input).unwrap() as usize
+    );
+    assert!(
+        stats.total_bytes
+            > stats.state_trie.bytes + stats.storage_tries_bytes() + stats.contracts_bytes()
+    );
+}
+
+#[test]
+fn replay() {
+    let (address, provider) = storage_contract_provider(B256::with_last_byte(42));
+
+    let mut env = EthEvmEnv::from_provider(provider, 100).unwrap();
+    Contract::preflight(address, &mut env)
+        .call_builder(&IStorage::valueCall {})
+        .call()
+        .unwrap();
+    let calls = env.preflight_calls().to_vec();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].signature(), IStorage::valueCall::SIGNATURE);
+    let mut input = env.into_input().unwrap();
+
+    // the complete input reproduces the preflight
+    let report = input.replay(&calls).unwrap();
+    assert!(report.is_ok(), "{report:?}");
+
+    // without the storage trie, the accessed slot is missing
+    input.storage_tries.clear();
+    let report = input.replay(&calls).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.mismatches().count(), 1);
+    assert_eq!(
+        report.missing.into_iter().collect::<Vec<_>>(),
+        vec![MissingState::Storage(address, U256::ZERO)]
+    );
+
+    // without the bytecode, the call already fails before accessing the storage
+    input.contracts.clear();
+    let report = input.replay(&calls).unwrap();
+    assert_eq!(
+        report.missing.into_iter().collect::<Vec<_>>(),
+        vec![MissingState::Code(keccak256(STORAGE_CODE))]
+    );
+}
+
+#[test]
+fn merge_and_prune() {
+    let addresses = [
+        address!("1111111111111111111111111111111111111111"),
+        address!("2222222222222222222222222222222222222222"),
+    ];
+    let mut provider = MemoryProvider::new().with_block_number(100);
+    for (i, address) in addresses.into_iter().enumerate() {
+        let account = storage_contract(B256::with_last_byte(i as u8 + 1));
+        provider = provider.with_account(address, account);
+    }
+
+    // preflight the calls to each contract in a separate session
+    let preflight = |addresses: &[Address]| {
+        let mut env = EthEvmEnv::from_provider(&provider, 100).unwrap();
+        for address in addresses {
+            Contract::preflight(*address, &mut env)
+                .call_builder(&IStorage::valueCall {})
+                .call()
+                .unwrap();
+        }
+        let calls = env.preflight_calls().to_vec();
+        (env.into_input().unwrap(), calls)
+    };
+    let (first, first_calls) = preflight(&addresses[..1]);
+    let (second, second_calls) = preflight(&addresses[1..]);
+    let (combined, _) = preflight(&addresses);
+    let serialize = |input: &EthEvmInput| bincode::serialize(input).unwrap();
+
+    // the merged input must be identical to the input of a combined preflight
+    let merged = EthEvmInput::merge(first.prune(&first_calls).unwrap(), second).unwrap();
+    assert_eq!(serialize(&merged), serialize(&combined));
+    let calls: Vec<_> = first_calls.iter().chain(&second_calls).collect();
+    assert!(merged.replay(calls).unwrap().is_ok());
+
+    // pruning the merged input to the calls of one session must reproduce its input
+    assert_eq!(
+        serialize(&merged.prune(&first_calls).unwrap()),
+        serialize(&first)
+    );
+    let pruned = merged.prune(&second_calls).unwrap();
+    assert!(!pruned.replay(&first_calls).unwrap().missing.is_empty());
+    assert!(pruned.prune(&first_calls).is_err());
+}
+
+#[test]
+fn access_list() {
+    let address = address!("1111111111111111111111111111111111111111");
+    let unused = address!("2222222222222222222222222222222222222222");
+    // a second slot, which is never accessed by the call
+    let mut account = storage_contract(B256::with_last_byte(42));
+    account
+        .storage
+        .insert(B256::with_last_byte(1), B256::with_last_byte(1));
+    let provider = MemoryProvider::new()
+        .with_account(address, account)
+        .with_block_number(100);
+
+    let mut env = EthEvmEnv::from_provider(&provider, 100).unwrap();
+    Contract::preflight(address, &mut env)
+        .call_builder(&IStorage::valueCall {})
+        .call()
+        .unwrap();
+    let mut access_list = env.db().access_list();
+    let input = env.into_input().unwrap();
+
+    // the contract with its accessed storage slot is part of the sorted list
+    let item = access_list.0.iter().find(|item| item.address == address);
+    assert_eq!(item.unwrap().storage_keys, vec![B256::ZERO]);
+    assert!(access_list
+        .0
+        .windows(2)
+        .all(|w| w[0].address < w[1].address));
+
+    // seeding with the list, the call only needs to request the code
+    access_list.0.push(AccessListItem {
+        address: unused,
+        storage_keys: vec![B256::ZERO],
+    });
+    access_list.0.push(AccessListItem {
+        address,
+        storage_keys: vec![B256::with_last_byte(1)],
+    });
+    let mut env = EthEvmEnv::from_provider(&provider, 100)
+        .unwrap()
+        .with_verification()
+        .with_access_list(&access_list)
+        .unwrap();
+    // all the listed proofs are requested at once, one per account
+    let accounts: HashSet<_> = access_list.0.iter().map(|item| item.address).collect();
+    let stats = env.db().stats();
+    assert_eq!(stats.rpc_calls, accounts.len() as u64);
+    assert_eq!(stats.batches, 1);
+    let rpc_calls = stats.rpc_calls;
+    Contract::preflight(address, &mut env)
+        .call_builder(&IStorage::valueCall {})
+        .call()
+        .unwrap();
+    assert_eq!(env.db().stats().rpc_calls, rpc_calls + 1);
+
+    // state that is listed but not accessed must not become part of the input
+    let seeded = env.into_input().unwrap();
+    assert_eq!(
+        bincode::serialize(&seeded).unwrap(),
+        bincode::serialize(&input).unwrap()
+    );
+}
+
+#[test]
+fn preflight_snapshot() {
+    let addresses = [
+        address!("1111111111111111111111111111111111111111"),
+        address!("2222222222222222222222222222222222222222"),
+    ];
+    let mut provider = MemoryProvider::new().with_block_number(100);
+    for (i, address) in addresses.into_iter().enumerate() {
+        let account = storage_contract(B256::with_last_byte(i as u8 + 1));
+        provider = provider.with_account(address, account);
+    }
+    type Env<'a> = EthEvmEnv<host::db::ProofDb<&'a MemoryProvider>>;
+    let call = |env: &mut Env, address: Address| {
+        Contract::preflight(address, env)
+            .call_builder(&IStorage::valueCall {})
+            .call()
+            .unwrap();
+    };
+    let serialize = |input: &EthEvmInput| bincode::serialize(input).unwrap();
+
+    let mut env = EthEvmEnv::from_provider(&provider, 100).unwrap();
+    call(&mut env, addresses[0]);
+    let calls = env.preflight_calls().to_vec();
+    let snapshot = serde_json::to_string(&env.snapshot()).unwrap();
+    call(&mut env, addresses[1]);
+    let combined = env.into_input().unwrap();
+
+    // resuming the session and adding the second call results in the same input
+    let snapshot: PreflightSnapshot<EthBlockHeader> = serde_json::from_str(&snapshot).unwrap();
+    assert_eq!(snapshot.accounts[&addresses[0]].len(), 1);
+    assert_eq!(snapshot.contracts[&addresses[0]], Bytes::from(STORAGE_CODE));
+    let mut env = EthEvmEnv::from_snapshot(&provider, snapshot.clone()).unwrap();
+    // all the accounts are restored with a single batch
+    let stats = env.db().stats();
+    assert_eq!(stats.rpc_calls, snapshot.accounts.len() as u64);
+    assert_eq!(stats.batches, 1);
+    assert_eq!(stats.cache_hits, 0);
+    call(&mut env, addresses[1]);
+    assert_eq!(serialize(&env.into_input().unwrap()), serialize(&combined));
+
+    // a snapshot of a block that is not canonical is rejected
+    let mut reorged = snapshot.clone();
+    reorged.header.extra_data = Bytes::from_static(b"reorged");
+    assert!(EthEvmEnv::from_snapshot(&provider, reorged).is_err());
+
+    // re-targeting the session creates an input for the other block without executing the call
+    let env = EthEvmEnv::from_snapshot_at(&provider, &snapshot, 99).unwrap();
+    assert!(env.preflight_calls().is_empty());
+    let input = env.into_input().unwrap();
+    assert_eq!(input.header.number, 99);
+    assert!(input.replay(&calls).unwrap().is_ok());
+}
+
+#[test]
+fn preflight_parallel() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<host::provider::CachedProvider<host::provider::AlloyProvider>>();
+    assert_send_sync::<host::db::ProofDb<host::provider::EthFileProvider>>();
+    assert_send_sync::<risc0_steel::StateDb>();
+
+    let (address, provider) = storage_contract_provider(B256::with_last_byte(42));
+
+    let preflight = |mut env: EthEvmEnv<host::db::ProofDb<&MemoryProvider>>| {
+        Contract::preflight(address, &mut env)
+            .call_builder(&IStorage::valueCall {})
+            .call()?;
+        env.into_input()
+    };
+    let blocks = 90..=100;
+    let inputs = host::preflight_parallel(&provider, blocks.clone(), preflight).unwrap();
+
+    // the results must match sequential preflights in the order of the blocks
+    assert_eq!(inputs.len(), blocks.clone().count());
+    for (input, block) in inputs.into_iter().zip(blocks) {
+        let expected = preflight(EthEvmEnv::from_provider(&provider, block).unwrap()).unwrap();
+        let env = input.into_env();
+        assert_eq!(env.header().number, block);
+        assert_eq!(
+            env.block_commitment(),
+            expected.into_env().block_commitment()
+        );
+    }
+}
+
+/// A provider returning invalid responses for testing the verification.
+struct TamperingProvider {
+    inner: MemoryProvider,
+    tamper: Tamper,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Tamper {
+    None,
+    Balance,
+    StorageValue,
+    Code,
+    Header(u64),
+}
+
+impl Provider for TamperingProvider {
+    type Error = <MemoryProvider as Provider>::Error;
+    type Header = EthBlockHeader;
+
+    fn get_block_header(&self, block: u64) -> Result<Option<Self::Header>, Self::Error> {
+        let mut header = self.inner.get_block_header(block)?;
+        if self.tamper == Tamper::Header(block) {
+            header.as_mut().unwrap().extra_data = Bytes::from_static(b"tampered");
+        }
+        Ok(header)
+    }
+    fn get_transaction_count(&self, address: Address, block: u64) -> Result<u64, Self::Error> {
+        self.inner.get_transaction_count(address, block)
+    }
+    fn get_balance(&self, address: Address, block: u64) -> Result<U256, Self::Error> {
+        self.inner.get_balance(address, block)
+    }
+    fn get_code(&self, address: Address, block: u64) -> Result<Bytes, Self::Error> {
+        let code = self.inner.get_code(address, block)?;
+        Ok(match self.tamper {
+            Tamper::Code => Bytes::from_static(&hex!("60016000f3")),
+            _ => code,
+        })
+    }
+    fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: u64,
+    ) -> Result<StorageValue, Self::Error> {
+        self.inner.get_storage_at(address, key, block)
+    }
+    fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: u64,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        let mut proof = self.inner.get_proof(address, storage_keys, block)?;
+        match self.tamper {
+            Tamper::Balance => proof.balance += U256::from(1),
+            Tamper::StorageValue => proof
+                .storage_proof
+                .iter_mut()
+                .for_each(|p| p.value += U256::from(1)),
+            _ => {}
+        }
+        Ok(proof)
+    }
+}
+
+#[test]
+fn verification() {
+    // runtime code returning the hash of the block 10 blocks back for any call
+    let blockhash_code = hex!("600a43034060005260206000f3");
+    let (storage_address, inner) = storage_contract_provider(B256::with_last_byte(42));
+    let blockhash_address = address!("2222222222222222222222222222222222222222");
+    let inner = inner.with_account(
+        blockhash_address,
+        GenesisAccount {
+            code: blockhash_code.into(),
+            ..Default::default()
+        },
+    );
+
+    // BLOCKHASH only queries the database before Prague
+    let chain_spec = ChainSpec::new_single(1, SpecId::CANCUN, EIP1559_CONSTANTS_DEFAULT);
+    let preflight = |tamper| -> anyhow::Result<()> {
+        let provider = TamperingProvider {
+            inner: inner.clone(),
+            tamper,
+        };
+        let mut env = EthEvmEnv::from_provider(provider, 100)?
+            .with_chain_spec(&chain_spec)
+            .with_verification();
+        let call = IStorage::valueCall {};
+        let value = Contract::preflight(storage_address, &mut env)
+            .call_builder(&call)
+            .call()?
+            ._0;
+        assert_eq!(value, uint!(42_U256));
+        // the verified storage proof is reused for the input
+        assert!(env.db().missing_proofs().is_empty());
+        Contract::preflight(blockhash_address, &mut env)
+            .call_builder(&call)
+            .call()?;
+        env.into_input()?;
+        Ok(())
+    };
+
+    preflight(Tamper::None).unwrap();
+    let cases = [
+        (Tamper::Balance, "InvalidAccountProof"),
+        (Tamper::StorageValue, "InvalidStorageProof"),
+        (Tamper::Code, "InvalidCode"),
+        (Tamper::Header(95), "InvalidHeader(95)"),
+    ];
+    for (tamper, expected) in cases {
+        let err = preflight(tamper).unwrap_err();
+        assert!(err.to_string().contains(expected), "{err:#}");
+    }
+}
+
 #[test]
 fn uniswap_exact_output_single() {
     // mimic tx 0x241c81c3aa4c68cd07ae03a756050fc47fd91918a710250453d34c6db9d11997
@@ -137,7 +1253,7 @@ fn uniswap_exact_output_single() {
     let result = eth_call(
         call,
         contract,
-        BuilderOverrides {
+        CallOverrides {
             from: Some(caller),
             ..Default::default()
         },
@@ -197,7 +1313,7 @@ fn precompile() {
     let result = eth_call(
         ViewCallTest::testPrecompileCall {},
         VIEW_CALL_TEST_CONTRACT,
-        BuilderOverrides::default(),
+        CallOverrides::default(),
         VIEW_CALL_TEST_BLOCK,
         &ETH_SEPOLIA_CHAIN_SPEC,
     );
@@ -212,7 +1328,7 @@ fn nonexistent_account() {
     let result = eth_call(
         ViewCallTest::testNonexistentAccountCall {},
         VIEW_CALL_TEST_CONTRACT,
-        BuilderOverrides::default(),
+        CallOverrides::default(),
         VIEW_CALL_TEST_BLOCK,
         &ETH_SEPOLIA_CHAIN_SPEC,
     );
@@ -224,7 +1340,7 @@ fn eoa_account() {
     let result = eth_call(
         ViewCallTest::testEoaAccountCall {},
         VIEW_CALL_TEST_CONTRACT,
-        BuilderOverrides::default(),
+        CallOverrides::default(),
         VIEW_CALL_TEST_BLOCK,
         &ETH_SEPOLIA_CHAIN_SPEC,
     );
@@ -236,7 +1352,7 @@ fn blockhash() {
     let result = eth_call(
         ViewCallTest::testBlockhashCall {},
         VIEW_CALL_TEST_CONTRACT,
-        BuilderOverrides::default(),
+        CallOverrides::default(),
         VIEW_CALL_TEST_BLOCK,
         &ETH_SEPOLIA_CHAIN_SPEC,
     );
@@ -251,7 +1367,7 @@ fn chainid() {
     let result = eth_call(
         ViewCallTest::testChainidCall {},
         VIEW_CALL_TEST_CONTRACT,
-        BuilderOverrides::default(),
+        CallOverrides::default(),
         VIEW_CALL_TEST_BLOCK,
         &ETH_SEPOLIA_CHAIN_SPEC,
     );
@@ -264,7 +1380,7 @@ fn gasprice() {
     let result = eth_call(
         ViewCallTest::testGaspriceCall {},
         VIEW_CALL_TEST_CONTRACT,
-        BuilderOverrides {
+        CallOverrides {
             gas_price: Some(gas_price),
             ..Default::default()
         },
@@ -279,7 +1395,7 @@ fn multi_contract_calls() {
     let result = eth_call(
         ViewCallTest::testMuliContractCallsCall {},
         VIEW_CALL_TEST_CONTRACT,
-        BuilderOverrides::default(),
+        CallOverrides::default(),
         VIEW_CALL_TEST_BLOCK,
         &ETH_SEPOLIA_CHAIN_SPEC,
     );
@@ -288,7 +1404,7 @@ fn multi_contract_calls() {
 
 #[test]
 fn call_eoa() {
-    let mut env = EthEvmEnv::from_provider(provider!(), VIEW_CALL_TEST_BLOCK)
+    let mut env = EthEvmEnv::from_provider(test_provider(), VIEW_CALL_TEST_BLOCK)
         .unwrap()
         .with_chain_spec(&ETH_SEPOLIA_CHAIN_SPEC);
     let mut contract = Contract::preflight(Address::ZERO, &mut env);
@@ -298,29 +1414,106 @@ fn call_eoa() {
         .expect_err("calling an EOA should fail");
 }
 
-/// Simple struct to operate over different [CallBuilder] types.
-#[derive(Debug, Default)]
-struct BuilderOverrides {
-    gas_price: Option<U256>,
-    from: Option<Address>,
+/// An [AsyncProvider] wrapping the blocking test provider.
+struct AsyncTestProvider<P>(Mutex<P>);
+
+impl<P> AsyncProvider for AsyncTestProvider<P>
+where
+    P: Provider + Send,
+    P::Header: Send,
+{
+    type Error = P::Error;
+    type Header = P::Header;
+
+    async fn get_block_header(&self, block: u64) -> Result<Option<Self::Header>, Self::Error> {
+        self.0.lock().unwrap().get_block_header(block)
+    }
+    async fn get_transaction_count(&self, address: Address, block: u64) -> Result<u64, P::Error> {
+        self.0.lock().unwrap().get_transaction_count(address, block)
+    }
+    async fn get_balance(&self, address: Address, block: u64) -> Result<U256, Self::Error> {
+        self.0.lock().unwrap().get_balance(address, block)
+    }
+    async fn get_code(&self, address: Address, block: u64) -> Result<Bytes, Self::Error> {
+        self.0.lock().unwrap().get_code(address, block)
+    }
+    async fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: u64,
+    ) -> Result<StorageValue, Self::Error> {
+        self.0.lock().unwrap().get_storage_at(address, key, block)
+    }
+    async fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: u64,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        self.0
+            .lock()
+            .unwrap()
+            .get_proof(address, storage_keys, block)
+    }
 }
 
-impl BuilderOverrides {
-    fn override_builder<E, C>(&self, mut builder: CallBuilder<E, C>) -> CallBuilder<E, C> {
-        if let Some(gas_price) = self.gas_price {
-            builder = builder.gas_price(gas_price);
-        }
-        if let Some(from) = self.from {
-            builder = builder.from(from);
-        }
-        builder
+/// A [TraceProvider] returning a fixed prestate and witness and counting the proof requests.
+struct TraceTestProvider<P> {
+    inner: P,
+    prestate: PrestateTrace,
+    witness: Option<ExecutionWitness>,
+    proof_requests: Rc<Cell<usize>>,
+}
+
+impl<P: Provider> Provider for TraceTestProvider<P> {
+    type Error = P::Error;
+    type Header = P::Header;
+
+    fn get_block_header(&self, block: u64) -> Result<Option<Self::Header>, Self::Error> {
+        self.inner.get_block_header(block)
+    }
+    fn get_transaction_count(&self, address: Address, block: u64) -> Result<u64, Self::Error> {
+        self.inner.get_transaction_count(address, block)
+    }
+    fn get_balance(&self, address: Address, block: u64) -> Result<U256, Self::Error> {
+        self.inner.get_balance(address, block)
+    }
+    fn get_code(&self, address: Address, block: u64) -> Result<Bytes, Self::Error> {
+        self.inner.get_code(address, block)
+    }
+    fn get_storage_at(
+        &self,
+        address: Address,
+        key: StorageKey,
+        block: u64,
+    ) -> Result<StorageValue, Self::Error> {
+        self.inner.get_storage_at(address, key, block)
+    }
+    fn get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<StorageKey>,
+        block: u64,
+    ) -> Result<EIP1186Proof, Self::Error> {
+        self.proof_requests.set(self.proof_requests.get() + 1);
+        self.inner.get_proof(address, storage_keys, block)
+    }
+}
+
+impl<P: Provider> TraceProvider for TraceTestProvider<P> {
+    fn trace_prestate(&self, _: &TraceCall, _: u64) -> Result<PrestateTrace, Self::Error> {
+        Ok(self.prestate.clone())
+    }
+    fn get_execution_witness(&self, _: u64) -> Result<Option<ExecutionWitness>, Self::Error> {
+        Ok(self.witness.clone())
     }
 }
 
 fn eth_call<C>(
     call: C,
     address: Address,
-    call_overrides: BuilderOverrides,
+    call_overrides: CallOverrides,
     block: u64,
     chain_spec: &ChainSpec,
 ) -> C::Return
@@ -328,27 +1521,12 @@ where
     C: SolCall,
     <C as SolCall>::Return: PartialEq + Debug,
 {
-    let mut env = EthEvmEnv::from_provider(provider!(), block)
-        .unwrap()
-        .with_chain_spec(chain_spec);
-
-    let mut preflight = Contract::preflight(address, &mut env);
-    let preflight_result = call_overrides
-        .override_builder(preflight.call_builder(&call))
-        .call()
-        .unwrap();
-
-    let input = env.into_input().unwrap();
-
-    let env = input.into_env().with_chain_spec(&ETH_SEPOLIA_CHAIN_SPEC);
-    let contract = Contract::new(address, &env);
-    let result = call_overrides
-        .override_builder(contract.call_builder(&call))
-        .call();
-    assert_eq!(
-        result, preflight_result,
-        "mismatch in preflight and execution"
-    );
-
-    result
+    testing::assert_eth_call(
+        test_provider(),
+        block,
+        chain_spec,
+        address,
+        &call,
+        &call_overrides,
+    )
 }